@@ -0,0 +1,11 @@
+// the version of sv-parser resolved in Cargo.lock, shown in the provenance header
+// of the output (--meta)
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    let lock = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let version = lock.split("[[package]]")
+        .find(|x| x.lines().any(|x| x == "name = \"sv-parser\""))
+        .and_then(|x| x.lines().find_map(|x| x.strip_prefix("version = \"")))
+        .map_or("unknown", |x| x.trim_end_matches('"'));
+    println!("cargo:rustc-env=SV_PARSER_VERSION={}", version);
+}
@@ -0,0 +1,23 @@
+// Port widths of various packed ranges for checking svinst_port
+
+module ranges #(
+    parameter W = 8,
+    localparam N = W * 2
+) (
+    input  logic       [7:0] DESC,      // descending
+    input  logic       [0:7] ASC,       // ascending
+    input  logic       [7:4] OFFSET,    // descending with a non-zero lsb
+    input  logic      [4:11] ASC_OFFSET,// ascending with a non-zero msb
+    output logic     [W-1:0] PARAM,     // parameter
+    output logic     [N-1:W] PARAM_OFFSET,
+    output logic [$clog2(N)-1:0] CLOG2,
+    output logic [W > 4 ? W : 4:1] COND,
+    output logic             SCALAR);
+endmodule
+
+module ranges_nonansi (DESC, ASC, OFFSET, SCALAR);
+    input  [15:0] DESC;
+    input  [0:15] ASC;
+    output [23:8] OFFSET;
+    output        SCALAR;
+endmodule
@@ -0,0 +1,25 @@
+// Port widths of typedef'd and packed struct types for checking svinst_port
+
+package types_pkg;
+    typedef logic [7:0] byte_t;
+    typedef struct packed {
+        logic [3:0] op;
+        byte_t      data, mask;
+        logic       valid;
+    } req_t;
+    typedef enum logic [1:0] { IDLE, BUSY } state_t;
+endpackage
+
+module types
+    import types_pkg::*;
+#(
+    parameter N = 4
+) (
+    input  types_pkg::req_t REQ,        // struct in a package
+    input  byte_t [N-1:0]   BYTES,      // typedef with packed dimensions
+    input  state_t          STATE,      // enum
+    output struct packed {
+        logic       a;
+        logic [2:0] b;
+    }                       ANON);      // anonymous struct
+endmodule
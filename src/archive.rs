@@ -0,0 +1,242 @@
+// Archives of source files (.tar, .tar.gz, .tgz, and .zip), as IP is often
+// delivered: the sources (.v and .sv members) and the members they include are
+// extracted into a temporary directory, where the sources are analyzed with the
+// include files resolved within the archive, and the members are reported as
+// "<archive>/<member>".
+
+use std::collections::HashSet;
+use std::fs::{create_dir_all, read, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use flate2::read::GzDecoder;
+use tempfile::TempDir;
+use crate::flatten;
+
+// the temporary directory is the archive root, which is searched for include files
+// as well as the directories of the sources; the member is shown by label while the
+// archive is alive
+pub struct Archive {
+    pub dir: TempDir
+}
+
+impl Drop for Archive {
+    fn drop(&mut self) {
+        labels().lock().unwrap_or_else(|x| x.into_inner()).retain(|x| x.0 != self.dir.path());
+    }
+}
+
+// the temporary directories of the archives and the archives, to show the members
+fn labels() -> &'static Mutex<Vec<(PathBuf, PathBuf)>> {
+    static LABELS: OnceLock<Mutex<Vec<(PathBuf, PathBuf)>>> = OnceLock::new();
+    LABELS.get_or_init(|| Mutex::new(vec![]))
+}
+
+pub fn is_archive(
+    path: &Path
+) -> bool {
+    let name = path.file_name().and_then(|x| x.to_str()).unwrap_or_default().to_ascii_lowercase();
+    [".tar", ".tar.gz", ".tgz", ".zip"].iter().any(|x| name.ends_with(x))
+}
+
+fn is_source(
+    path: &Path
+) -> bool {
+    matches!(path.extension().and_then(|x| x.to_str()), Some("v" | "sv"))
+}
+
+// path of a member relative to the archive root without "." and "..", or None if it
+// is outside the root
+fn normalize(
+    path: &Path
+) -> Option<PathBuf> {
+    let mut ret = PathBuf::new();
+    for x in path.components() {
+        match x {
+            Component::Normal(x) => ret.push(x),
+            Component::CurDir => (),
+            Component::ParentDir => {
+                if !ret.pop() {
+                    return None;
+                }
+            }
+            _ => return None
+        }
+    }
+    Some(ret)
+}
+
+// the files in an archive, or those of them in `wanted` extracted into a directory;
+// the members outside the archive root (e.g. "../x") are skipped
+fn visit_members(
+    path: &Path,
+    dir: &Path,
+    wanted: &HashSet<PathBuf>
+) -> io::Result<Vec<PathBuf>> {
+    let mut ret = vec![];
+    let name = path.to_string_lossy().to_ascii_lowercase();
+    let reader = BufReader::new(File::open(path)?);
+    if name.ends_with(".zip") {
+        let mut archive = zip::ZipArchive::new(reader).map_err(io::Error::other)?;
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).map_err(io::Error::other)?;
+            let Some(member) = file.enclosed_name().filter(|_| file.is_file()).and_then(|x| normalize(&x)) else { continue; };
+            if wanted.contains(&member) {
+                let target = dir.join(&member);
+                if let Some(x) = target.parent() {
+                    create_dir_all(x)?;
+                }
+                io::copy(&mut file, &mut File::create(target)?)?;
+            }
+            ret.push(member);
+        }
+        return Ok(ret);
+    }
+    let reader: Box<dyn Read> = if name.ends_with(".tar") { Box::new(reader) } else { Box::new(GzDecoder::new(reader)) };
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(member) = normalize(&entry.path()?) else { continue; };
+        if wanted.contains(&member) {
+            entry.unpack_in(dir)?;
+        }
+        ret.push(member);
+    }
+    Ok(ret)
+}
+
+// extract the sources of an archive and the members included by them, directly or
+// via other members; an include file is looked for relative to the archive root
+// and to the directory of the including member
+pub fn extract(
+    path: &Path
+) -> io::Result<Archive> {
+    let dir = tempfile::tempdir()?;
+    let members: HashSet<PathBuf> = visit_members(path, dir.path(), &HashSet::new())?.into_iter().collect();
+    let mut wanted: HashSet<PathBuf> = members.iter().filter(|x| is_source(x)).cloned().collect();
+    let mut extracted: HashSet<PathBuf> = HashSet::new();
+    while !wanted.is_empty() {
+        visit_members(path, dir.path(), &wanted)?;
+        let mut next = HashSet::new();
+        for member in &wanted {
+            let Ok(text) = read(dir.path().join(member)) else { continue; };
+            for (_, _, name) in flatten::find_includes(&String::from_utf8_lossy(&text)) {
+                let base = member.parent().unwrap_or(Path::new(""));
+                next.extend([Path::new(&name).to_path_buf(), base.join(&name)].iter()
+                    .filter_map(|x| normalize(x))
+                    .filter(|x| members.contains(x) && !extracted.contains(x) && !wanted.contains(x)));
+            }
+        }
+        extracted.extend(wanted);
+        wanted = next;
+    }
+    labels().lock().unwrap_or_else(|x| x.into_inner()).push((dir.path().to_path_buf(), path.to_path_buf()));
+    Ok(Archive { dir })
+}
+
+// path of a member as shown in the output, or None for a file outside the archives
+pub fn label(
+    path: &Path
+) -> Option<PathBuf> {
+    let labels = labels().lock().unwrap_or_else(|x| x.into_inner());
+    labels.iter().find_map(|(dir, archive)| path.strip_prefix(dir).ok().map(|x| archive.join(x)))
+}
+
+// extracted file of a member shown in the output, or the path itself otherwise
+pub fn resolve(
+    path: &Path
+) -> PathBuf {
+    let labels = labels().lock().unwrap_or_else(|x| x.into_inner());
+    labels.iter()
+        .find_map(|(dir, archive)| path.strip_prefix(archive).ok().filter(|x| !x.as_os_str().is_empty()).map(|x| dir.join(x)))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+// archive and path in it of a member shown in the output, or None for a file outside
+// the archives
+pub fn member(
+    path: &Path
+) -> Option<(PathBuf, PathBuf)> {
+    let labels = labels().lock().unwrap_or_else(|x| x.into_inner());
+    labels.iter()
+        .find_map(|(_, archive)| path.strip_prefix(archive).ok().filter(|x| !x.as_os_str().is_empty()).map(|x| (archive.clone(), x.to_path_buf())))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read_to_string;
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use super::*;
+
+    const MEMBERS: &[(&str, &str)] = &[
+        ("rtl/top.sv", "`include \"defs.svh\"\n`include \"local.svh\"\nmodule top; endmodule\n"),
+        ("defs.svh", "`include \"nested.svh\"\n"),
+        ("nested.svh", "typedef int n_t;\n"),
+        ("rtl/local.svh", "typedef int l_t;\n"),
+        ("unused.svh", "typedef int u_t;\n"),
+        ("doc/readme.txt", "not a source\n")
+    ];
+
+    fn write_tgz(
+        path: &Path
+    ) {
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(path).unwrap(), flate2::Compression::default()));
+        for (name, text) in MEMBERS {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(text.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, text.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn write_zip(
+        path: &Path
+    ) {
+        let mut writer = zip::ZipWriter::new(File::create(path).unwrap());
+        for (name, text) in MEMBERS {
+            writer.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(text.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn members_are_labelled_and_resolved() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, write) in [("ip.tgz", write_tgz as fn(&Path)), ("ip.zip", write_zip)] {
+            let path = dir.path().join(name);
+            write(&path);
+            assert!(is_archive(&path));
+            let archive = extract(&path).unwrap();
+            let root = archive.dir.path().to_path_buf();
+            // the sources and the members they include, relative to the root or the including member
+            for member in ["rtl/top.sv", "defs.svh", "nested.svh", "rtl/local.svh"] {
+                assert_eq!(read_to_string(root.join(member)).unwrap(), MEMBERS.iter().find(|x| x.0 == member).unwrap().1);
+                assert_eq!(label(&root.join(member)), Some(path.join(member)));
+                assert_eq!(super::member(&path.join(member)), Some((path.clone(), PathBuf::from(member))));
+                assert_eq!(resolve(&path.join(member)), root.join(member));
+            }
+            assert!(!root.join("unused.svh").exists());
+            assert!(!root.join("doc/readme.txt").exists());
+            assert_eq!(label(&dir.path().join("other.sv")), None);
+            assert_eq!(resolve(&path), path);
+            drop(archive);
+            assert_eq!(label(&root.join("rtl/top.sv")), None);
+            assert_eq!(resolve(&path.join("rtl/top.sv")), path.join("rtl/top.sv"));
+            assert_eq!(member(&path.join("rtl/top.sv")), None);
+        }
+    }
+
+    #[test]
+    fn members_outside_root_are_skipped() {
+        assert_eq!(normalize(Path::new("./a/../b/c.sv")), Some(PathBuf::from("b/c.sv")));
+        assert_eq!(normalize(Path::new("a/../../c.sv")), None);
+        assert_eq!(normalize(Path::new("/abs/c.sv")), None);
+    }
+}
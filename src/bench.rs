@@ -0,0 +1,134 @@
+// Benchmark of the analysis: each input file is analyzed repeatedly as a separate
+// compilation unit, and the time is broken down into the phases of preprocessing
+// (including reading the file), parsing, extracting the definitions, and emitting
+// the output, which is captured and discarded.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use sv_parser::{parse_sv_pp, preprocess};
+use tempfile::NamedTempFile;
+use tracing::error;
+use crate::logging::{self, LogOpt};
+use crate::report::print_defs;
+use crate::{analyze_defs, read_defines, read_sanitized, Interner, ModuleDef, Opt, SourceMap, Symbol, UsedBy, CAPTURE};
+
+#[derive(StructOpt)]
+#[structopt(name = "bench", about = "Measure the throughput of the analysis of the files")]
+pub struct BenchOpt {
+    /// A list of source files
+    #[structopt(required = true, parse(from_os_str))]
+    pub files: Vec<PathBuf>,
+
+    /// Define
+    #[structopt(short = "d", long = "define", multiple = true, number_of_values = 1)]
+    pub defines: Vec<String>,
+
+    /// Include path
+    #[structopt(short = "i", long = "include", multiple = true, number_of_values = 1)]
+    pub includes: Vec<PathBuf>,
+
+    /// Ignore any include
+    #[structopt(long = "ignore-include")]
+    pub ignore_include: bool,
+
+    /// Number of times the files are analyzed
+    #[structopt(short = "n", long = "iterations", default_value = "3")]
+    pub iterations: usize,
+
+    #[structopt(flatten)]
+    pub log: LogOpt
+}
+
+const PHASES: [&str; 4] = ["preprocess", "parse", "extract", "emit"];
+
+// time of each phase of a file, or None if it fails to be read or parsed
+fn bench_file(
+    path: &Path,
+    opt: &BenchOpt,
+    emit_opt: &Opt
+) -> Option<[Duration; 4]> {
+    let start = Instant::now();
+    let text = read_sanitized(path)?;
+    let mut tmpfile = NamedTempFile::new().ok()?;
+    tmpfile.write_all(text.as_bytes()).ok()?;
+    let mut sources = SourceMap::new(tmpfile.path());
+    sources.add(path, 0, text);
+    let defines = read_defines(&opt.defines);
+    let (pp_text, new_defines) = preprocess(tmpfile.path(), &defines, &opt.includes, false, opt.ignore_include).ok()?;
+    let preprocessed = Instant::now();
+
+    let (syntax_tree, _) = parse_sv_pp(pp_text, new_defines, false).ok()?;
+    let parsed = Instant::now();
+
+    let (defs, _) = analyze_defs(&syntax_tree, &mut sources, &HashMap::new(), &mut HashMap::new(), &Interner::new());
+    let extracted = Instant::now();
+
+    let mut used_by: HashMap<Symbol, Vec<UsedBy>> = HashMap::new();
+    for parent in &defs {
+        for inst in &parent.insts {
+            used_by.entry(inst.mod_symbol).or_default().push(UsedBy { parent, inst });
+        }
+    }
+    CAPTURE.with(|x| *x.borrow_mut() = Some(String::new()));
+    print_defs(&defs.iter().collect::<Vec<&ModuleDef>>(), &used_by, &[], &HashSet::new(), emit_opt);
+    CAPTURE.with(|x| x.borrow_mut().take());
+    let emitted = Instant::now();
+
+    Some([preprocessed - start, parsed - preprocessed, extracted - parsed, emitted - extracted])
+}
+
+pub fn run(
+    opt: &BenchOpt
+) -> i32 {
+    logging::init(&opt.log);
+    let mut exit_code = 0;
+    // the output is emitted with the default options
+    let emit_opt = Opt::from_iter(["svinst_port"]);
+
+    // the files and the bytes analyzed in each iteration
+    let iterations = opt.iterations.max(1);
+    let mut phases = [Duration::ZERO; 4];
+    let mut files = 0;
+    let mut bytes = 0;
+    for i in 0..iterations {
+        for path in &opt.files {
+            match bench_file(path, opt, &emit_opt) {
+                Some(times) => {
+                    for (phase, time) in phases.iter_mut().zip(times) {
+                        *phase += time;
+                    }
+                    if i == 0 {
+                        files += 1;
+                        bytes += path.metadata().map(|x| x.len()).unwrap_or_default();
+                    }
+                }
+                // the failures are reported only once
+                None if i == 0 => {
+                    error!("failed to read or parse: {:?}", path);
+                    exit_code = 1;
+                }
+                None => ()
+            }
+        }
+    }
+
+    let total: Duration = phases.iter().sum();
+    let secs = total.as_secs_f64().max(f64::MIN_POSITIVE);
+    println!("bench:");
+    println!("  iterations: {}", iterations);
+    println!("  files: {}", files);
+    println!("  bytes: {}", bytes);
+    println!("  total_ms: {:.3}", secs * 1000.0);
+    println!("  mb_per_s: {:.3}", (bytes * iterations as u64) as f64 / 1e6 / secs);
+    println!("  files_per_s: {:.3}", (files * iterations) as f64 / secs);
+    println!("  phases:");
+    for (name, time) in PHASES.iter().zip(phases) {
+        println!("    - phase: {}", name);
+        println!("      ms: {:.3}", time.as_secs_f64() * 1000.0);
+        println!("      share: {:.3}", time.as_secs_f64() / secs);
+    }
+    exit_code
+}
@@ -0,0 +1,151 @@
+// Heuristic detection of bus interfaces: the ports of a module are grouped by the
+// common prefix of their names, and a group which has the signals of a protocol is
+// reported as a bus of it. The built-in patterns can be extended by a file.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use serde::Deserialize;
+use crate::{escape_str, ModuleDef};
+
+// signals of a protocol without the prefix; a bus has all the required signals,
+// and is the master if the master signal is an output
+#[derive(Deserialize)]
+pub struct BusPattern {
+    pub protocol: String,
+    pub signals: Vec<String>,
+    #[serde(default)]
+    pub required: Vec<String>,
+    #[serde(default)]
+    pub master_signal: Option<String>
+}
+
+// built-in patterns as (protocol, signals, required signals, master signal); the
+// more specific ones come first, since a port belongs to only one bus
+const BUILTIN_PATTERNS: &[(&str, &[&str], &[&str], &str)] = &[
+    ("AXI4", &[
+        "awid", "awaddr", "awlen", "awsize", "awburst", "awlock", "awcache", "awprot", "awqos",
+        "awregion", "awuser", "awvalid", "awready", "wdata", "wstrb", "wlast", "wuser", "wvalid",
+        "wready", "bid", "bresp", "buser", "bvalid", "bready", "arid", "araddr", "arlen", "arsize",
+        "arburst", "arlock", "arcache", "arprot", "arqos", "arregion", "aruser", "arvalid",
+        "arready", "rid", "rdata", "rresp", "rlast", "ruser", "rvalid", "rready",
+    ], &["awaddr", "awlen", "awvalid", "awready", "wvalid", "wready", "bvalid", "bready", "araddr", "arlen", "arvalid", "arready", "rvalid", "rready"], "awvalid"),
+    ("AXI4-Lite", &[
+        "awaddr", "awprot", "awvalid", "awready", "wdata", "wstrb", "wvalid", "wready", "bresp",
+        "bvalid", "bready", "araddr", "arprot", "arvalid", "arready", "rdata", "rresp", "rvalid",
+        "rready",
+    ], &["awaddr", "awvalid", "awready", "wdata", "wvalid", "wready", "bvalid", "bready", "araddr", "arvalid", "arready", "rdata", "rvalid", "rready"], "awvalid"),
+    ("AXI4-Stream", &[
+        "tvalid", "tready", "tdata", "tstrb", "tkeep", "tlast", "tid", "tdest", "tuser",
+    ], &["tvalid", "tdata"], "tvalid"),
+    ("APB", &[
+        "paddr", "psel", "penable", "pwrite", "pwdata", "prdata", "pready", "pslverr", "pprot", "pstrb",
+    ], &["paddr", "psel", "penable", "pwrite"], "psel"),
+    ("Avalon-MM", &[
+        "address", "read", "write", "readdata", "writedata", "waitrequest", "readdatavalid",
+        "byteenable", "burstcount", "response", "writeresponsevalid", "lock", "debugaccess",
+    ], &["address", "waitrequest"], "address"),
+];
+
+pub fn builtin_patterns() -> Vec<BusPattern> {
+    BUILTIN_PATTERNS.iter()
+        .map(|(protocol, signals, required, master_signal)| BusPattern {
+            protocol: String::from(*protocol),
+            signals: signals.iter().map(|x| String::from(*x)).collect(),
+            required: required.iter().map(|x| String::from(*x)).collect(),
+            master_signal: Some(String::from(*master_signal))
+        })
+        .collect()
+}
+
+// patterns in a YAML (or JSON) file, which is a list of the patterns; all the
+// signals are required if the required ones are not given
+pub fn read_patterns(
+    path: &Path
+) -> Result<Vec<BusPattern>, Box<dyn std::error::Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut patterns: Vec<BusPattern> = serde_yaml::from_reader(reader)?;
+    for x in &mut patterns {
+        if x.required.is_empty() {
+            x.required = x.signals.clone();
+        }
+        x.signals.iter_mut().chain(&mut x.required).chain(&mut x.master_signal).for_each(|x| *x = x.to_ascii_lowercase());
+    }
+    Ok(patterns)
+}
+
+pub struct Bus<'a> {
+    pub protocol: &'a str,
+    pub prefix: String,
+    pub mode: Option<&'static str>,
+    // indices of the ports
+    pub ports: Vec<usize>
+}
+
+// buses of a module; the prefix of a port is the part before a signal of the
+// protocol, which is empty or ends with an underscore (e.g. "s_axi_" of "s_axi_awvalid")
+pub fn find_buses<'a>(
+    def: &ModuleDef,
+    patterns: &'a [BusPattern]
+) -> Vec<Bus<'a>> {
+    let names: Vec<String> = def.ports.iter().map(|x| x.name.to_ascii_lowercase()).collect();
+    let mut used = vec![false; names.len()];
+    let mut ret = vec![];
+    for pattern in patterns {
+        // (prefix, signal, port index) of the ports not in any bus yet
+        let mut candidates: Vec<(&str, &str, usize)> = vec![];
+        for (i, name) in names.iter().enumerate().filter(|(i, _)| !used[*i]) {
+            // the longest signal matches, e.g. "readdata" rather than "data"
+            let signal = pattern.signals.iter()
+                .filter(|x| name.strip_suffix(x.as_str()).is_some_and(|x| x.is_empty() || x.ends_with('_')))
+                .max_by_key(|x| x.len());
+            if let Some(signal) = signal {
+                candidates.push((&name[..name.len() - signal.len()], signal, i));
+            }
+        }
+        let mut prefixes: Vec<&str> = candidates.iter().map(|x| x.0).collect();
+        prefixes.sort();
+        prefixes.dedup();
+        for prefix in prefixes {
+            let members: Vec<&(&str, &str, usize)> = candidates.iter().filter(|x| x.0 == prefix).collect();
+            if !pattern.required.iter().all(|x| members.iter().any(|y| y.1 == x)) {
+                continue;
+            }
+            let mode = pattern.master_signal.as_ref()
+                .and_then(|x| members.iter().find(|y| y.1 == x))
+                .map(|x| if def.ports[x.2].dir == "input" { "slave" } else { "master" });
+            for x in &members {
+                used[x.2] = true;
+            }
+            ret.push(Bus {
+                protocol: &pattern.protocol,
+                prefix: String::from(&def.ports[members[0].2].name[..prefix.len()]),
+                mode,
+                ports: members.iter().map(|x| x.2).collect()
+            });
+        }
+    }
+    ret
+}
+
+pub fn print_buses(
+    def: &ModuleDef,
+    patterns: &[BusPattern]
+) {
+    let buses = find_buses(def, patterns);
+    if buses.is_empty() {
+        return;
+    }
+    outln!("        buses:");
+    for bus in &buses {
+        outln!("          - protocol: {}", escape_str(bus.protocol));
+        outln!("            prefix: {}", escape_str(&bus.prefix));
+        if let Some(mode) = bus.mode {
+            outln!("            mode: {}", escape_str(mode));
+        }
+        outln!("            ports:");
+        for &i in &bus.ports {
+            outln!("              - {}", escape_str(&def.ports[i].name));
+        }
+    }
+}
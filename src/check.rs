@@ -0,0 +1,83 @@
+// Checks of the definitions warned about: the module names against the file names,
+// the port connections of the instantiations, and the ports against the limits.
+
+use std::collections::HashMap;
+use tracing::warn;
+use crate::{FileResult, Opt, Symbol, UsedBy};
+
+// the checks desired
+pub fn check_defs(
+    results: &[FileResult],
+    used_by: &HashMap<Symbol, Vec<UsedBy>>,
+    opt: &Opt
+) {
+    if let Some(policy) = &opt.check_file_names {
+        check_file_names(results, policy);
+    }
+    if opt.check_conn_styles {
+        check_conn_styles(used_by);
+    }
+    if opt.max_port_width.is_some() || opt.max_port_count.is_some() {
+        check_port_limits(results, opt.max_port_width, opt.max_port_count);
+    }
+}
+
+// warn about the files defining several modules ("multiple") or not the module named
+// after the file ("mismatch"), or both
+fn check_file_names(
+    results: &[FileResult],
+    policy: &str
+) {
+    for result in results {
+        let stem = result.path.file_stem().and_then(|x| x.to_str()).unwrap_or("");
+        if policy != "mismatch" && result.defs.len() > 1 {
+            warn!("{:?} defines {} modules", result.path, result.defs.len());
+        }
+        if policy != "multiple" && !result.defs.iter().any(|x| *x.name == *stem) {
+            warn!("{:?} does not define module {}", result.path, stem);
+        }
+    }
+}
+
+// warn about the modules whose instantiations mix the named and the ordered port
+// connections, or have different numbers of connections (except with ".*")
+fn check_conn_styles(
+    used_by: &HashMap<Symbol, Vec<UsedBy>>
+) {
+    let mut uses: Vec<&Vec<UsedBy>> = used_by.values().collect();
+    uses.sort_by_key(|x| &x[0].inst.mod_name);
+    for insts in uses {
+        let location = |x: &UsedBy| format!("{} at {:?}:{}", x.inst.inst_name, x.inst.file, x.inst.line);
+        let name = &insts[0].inst.mod_name;
+        let named = insts.iter().find(|x| x.inst.conn_style == "named" || x.inst.conn_style == "wildcard");
+        let ordered = insts.iter().find(|x| x.inst.conn_style == "ordered");
+        if let (Some(named), Some(ordered)) = (named, ordered) {
+            warn!("{} is connected by name ({}) and by position ({})", name, location(named), location(ordered));
+        }
+        let mut counted = insts.iter().filter(|x| x.inst.conn_style == "named" || x.inst.conn_style == "ordered");
+        if let Some(first) = counted.next() {
+            if let Some(other) = counted.find(|x| x.inst.conn_count != first.inst.conn_count) {
+                warn!("{} has {} connections ({}) and {} connections ({})",
+                    name, first.inst.conn_count, location(first), other.inst.conn_count, location(other));
+            }
+        }
+    }
+}
+
+// warn about the ports wider than the limit and the modules with more ports than
+// the limit, which are often better bundled into interfaces or structs
+fn check_port_limits(
+    results: &[FileResult],
+    max_width: Option<i32>,
+    max_count: Option<usize>
+) {
+    for def in results.iter().flat_map(|x| &x.defs) {
+        if let Some(max) = max_count.filter(|&x| def.ports.len() > x) {
+            warn!("{:?}:{}: {} has {} ports (more than {})", def.file, def.line, def.name, def.ports.len(), max);
+        }
+        let Some(max) = max_width else { continue; };
+        for port in def.ports.iter().filter(|x| x.width > max) {
+            warn!("{:?}:{}: port {} of {} is {} bits wide (more than {})", def.file, port.line, port.name, def.name, port.width, max);
+        }
+    }
+}
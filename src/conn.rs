@@ -0,0 +1,534 @@
+// Net connectivity within a module: the drivers and the loads of each net, found
+// in continuous assignments, procedural blocks, and port connections of instances.
+// Only the parser finds the connections; the scanner leaves them empty.
+
+use std::collections::HashMap;
+use sv_parser::{NodeEvent, RefNode, SyntaxTree};
+use sv_parser_syntaxtree::{ListOfPortConnections, NamedPortConnection};
+use crate::{escape_str, get_expr_str, get_identifier, hier, pinout, special, FileResult, ModuleDef, Name, SourceMap, Symbol};
+
+// a reference to a net; `driver` is None for a port connection whose direction is
+// not resolved (yet), and a wildcard connection ".*" has an empty net name
+pub struct Connection {
+    pub net: String,
+    pub kind: String,
+    pub driver: Option<bool>,
+    pub inst_name: Option<String>,
+    pub port_name: Option<String>,
+    pub port_index: Option<usize>,
+    pub line: usize,
+    // clock of the procedural block sensitive to edges (e.g. "@(posedge clk)")
+    pub clock: Option<String>
+}
+
+// port connection of an instance to a constant such as "1'b0" or "'1", which has no
+// identifier; the value is the text of join_tokens, and `driver` is resolved as of
+// Connection
+pub struct ConstPin {
+    pub inst_name: String,
+    pub port_name: Option<String>,
+    pub port_index: Option<usize>,
+    pub value: String,
+    pub line: usize,
+    pub driver: Option<bool>
+}
+
+// expression connected to a port of an instance, as the text of join_tokens, and its
+// width inferred from the declarations of the parent (None if unknown); the port is
+// resolved as of Connection
+pub struct PinExpr {
+    pub inst_name: String,
+    pub port_name: Option<String>,
+    pub port_index: Option<usize>,
+    pub expr: String,
+    pub width: Option<i32>,
+    pub line: usize
+}
+
+// identifiers in a node as (name, line, is_assigned); an identifier is assigned if
+// it is in the left-hand side of an assignment, but not in its index
+fn identifiers(
+    syntax_tree: &SyntaxTree,
+    node: RefNode,
+    sources: &mut SourceMap
+) -> Vec<(String, usize, bool)> {
+    let mut ret = vec![];
+    let mut lvalue = 0;
+    let mut select = 0;
+    for x in node.into_iter().event() {
+        match x {
+            NodeEvent::Enter(RefNode::NetLvalue(_)) | NodeEvent::Enter(RefNode::VariableLvalue(_)) => lvalue += 1,
+            NodeEvent::Leave(RefNode::NetLvalue(_)) | NodeEvent::Leave(RefNode::VariableLvalue(_)) => lvalue -= 1,
+            NodeEvent::Enter(RefNode::Select(_)) | NodeEvent::Enter(RefNode::ConstantSelect(_)) => select += 1,
+            NodeEvent::Leave(RefNode::Select(_)) | NodeEvent::Leave(RefNode::ConstantSelect(_)) => select -= 1,
+            NodeEvent::Enter(x @ RefNode::SimpleIdentifier(_)) | NodeEvent::Enter(x @ RefNode::EscapedIdentifier(_)) => {
+                let Some(id) = get_identifier(x) else { continue; };
+                let Some(name) = syntax_tree.get_str(&id) else { continue; };
+                let Some((_, line)) = sources.resolve(syntax_tree, &id) else { continue; };
+                ret.push((String::from(name), line, lvalue > 0 && select == 0));
+            }
+            _ => ()
+        }
+    }
+    ret
+}
+
+// clock of a block: the signal of the edge events whose name is of a clock, or the
+// first one if none is (e.g. "clk" of "@(posedge clk or negedge rst_n)")
+fn block_clock(
+    syntax_tree: &SyntaxTree,
+    node: RefNode
+) -> Option<String> {
+    let mut signals = vec![];
+    for x in node {
+        let RefNode::EventExpressionExpression(x) = x else { continue; };
+        if x.nodes.0.is_none() {
+            continue;
+        }
+        let id = RefNode::from(&x.nodes.1).into_iter().find_map(|x| match x {
+            RefNode::SimpleIdentifier(_) | RefNode::EscapedIdentifier(_) => get_identifier(x),
+            _ => None
+        });
+        if let Some(name) = id.and_then(|x| syntax_tree.get_str(&x)) {
+            signals.push(name);
+        }
+    }
+    let clock = signals.iter().find(|x| pinout::port_role(x) == "clock").or(signals.first());
+    clock.map(|x| String::from(*x))
+}
+
+// continuous assignment or procedural block
+pub fn process_block(
+    syntax_tree: &SyntaxTree,
+    node: RefNode,
+    kind: &str,
+    sources: &mut SourceMap,
+    def: &mut ModuleDef
+) {
+    let clock = block_clock(syntax_tree, node.clone());
+    for (net, line, driver) in identifiers(syntax_tree, node, sources) {
+        def.conns.push(Connection {
+            net,
+            kind: String::from(kind),
+            driver: Some(driver),
+            inst_name: None,
+            port_name: None,
+            port_index: None,
+            line,
+            clock: clock.clone()
+        });
+    }
+}
+
+// declared net or variable, with the assignment in the declaration of a net
+pub fn process_net_decl(
+    syntax_tree: &SyntaxTree,
+    id: RefNode,
+    assignment: Option<RefNode>,
+    sources: &mut SourceMap,
+    def: &mut ModuleDef
+) {
+    let Some(id) = get_identifier(id) else { return; };
+    let Some(name) = syntax_tree.get_str(&id) else { return; };
+    if !def.nets.iter().any(|x| x == name) {
+        def.nets.push(String::from(name));
+    }
+    let Some(assignment) = assignment else { return; };
+    let Some((_, line)) = sources.resolve(syntax_tree, &id) else { return; };
+    def.conns.push(Connection {
+        net: String::from(name),
+        kind: String::from("assign"),
+        driver: Some(true),
+        inst_name: None,
+        port_name: None,
+        port_index: None,
+        line,
+        clock: None
+    });
+    process_block(syntax_tree, assignment, "assign", sources, def);
+}
+
+// port connections of an instance
+pub fn process_pins(
+    syntax_tree: &SyntaxTree,
+    node: RefNode,
+    sources: &mut SourceMap,
+    def: &mut ModuleDef
+) {
+    for x in node {
+        let RefNode::HierarchicalInstance(x) = x else { continue; };
+        let Some(id) = get_identifier(RefNode::from(&x.nodes.0.nodes.0)) else { continue; };
+        let Some(inst_name) = syntax_tree.get_str(&id) else { continue; };
+        let Some((_, inst_line)) = sources.resolve(syntax_tree, &id) else { continue; };
+        let pin = |net: String, port_name: Option<String>, port_index: Option<usize>, line: usize| Connection {
+            net,
+            kind: String::from("pin"),
+            driver: None,
+            inst_name: Some(String::from(inst_name)),
+            port_name,
+            port_index,
+            line,
+            clock: None
+        };
+        // a connection without any identifier is a constant
+        let mut const_pin = |expr: RefNode, port_name: Option<String>, port_index: Option<usize>, line: usize| {
+            let value = get_expr_str(syntax_tree, expr);
+            def.const_pins.push(ConstPin {
+                inst_name: String::from(inst_name),
+                port_name,
+                port_index,
+                value,
+                line,
+                driver: None
+            });
+        };
+        let mut exprs = vec![];
+        let mut pin_expr = |expr: String, port_name: Option<String>, port_index: Option<usize>, line: usize| {
+            exprs.push(PinExpr { inst_name: String::from(inst_name), port_name, port_index, expr, width: None, line });
+        };
+        let mut pins = vec![];
+        match &x.nodes.1.nodes.1 {
+            Some(ListOfPortConnections::Ordered(x)) => {
+                for (i, x) in x.nodes.0.contents().into_iter().enumerate() {
+                    if let Some(expr) = &x.nodes.1 {
+                        pin_expr(get_expr_str(syntax_tree, RefNode::from(expr)), None, Some(i), inst_line);
+                    }
+                    let ids = identifiers(syntax_tree, RefNode::from(x), sources);
+                    if let (true, Some(expr)) = (ids.is_empty(), &x.nodes.1) {
+                        const_pin(RefNode::from(expr), None, Some(i), inst_line);
+                    }
+                    for (net, line, _) in ids {
+                        pins.push(pin(net, None, Some(i), line));
+                    }
+                }
+            }
+            Some(ListOfPortConnections::Named(x)) => {
+                for x in x.nodes.0.contents() {
+                    match x {
+                        NamedPortConnection::Identifier(x) => {
+                            let Some(id) = get_identifier(RefNode::from(&x.nodes.2)) else { continue; };
+                            let Some(port_name) = syntax_tree.get_str(&id) else { continue; };
+                            let Some((_, line)) = sources.resolve(syntax_tree, &id) else { continue; };
+                            match &x.nodes.3 {
+                                // an implicit connection ".a" to the net of the same name
+                                None => {
+                                    pin_expr(String::from(port_name), Some(String::from(port_name)), None, line);
+                                    pins.push(pin(String::from(port_name), Some(String::from(port_name)), None, line));
+                                }
+                                Some(paren) => {
+                                    let Some(expr) = &paren.nodes.1 else { continue; };
+                                    pin_expr(get_expr_str(syntax_tree, RefNode::from(expr)), Some(String::from(port_name)), None, line);
+                                    let ids = identifiers(syntax_tree, RefNode::from(expr), sources);
+                                    if ids.is_empty() {
+                                        const_pin(RefNode::from(expr), Some(String::from(port_name)), None, line);
+                                    }
+                                    for (net, line, _) in ids {
+                                        pins.push(pin(net, Some(String::from(port_name)), None, line));
+                                    }
+                                }
+                            }
+                        }
+                        NamedPortConnection::Asterisk(_) => pins.push(pin(String::new(), None, None, inst_line))
+                    }
+                }
+            }
+            None => ()
+        }
+        def.conns.append(&mut pins);
+        def.pin_exprs.append(&mut exprs);
+    }
+}
+
+// clocks of the blocks where a net is sampled or driven, in the order of the
+// appearance; the clock itself is not in its own domain
+pub fn net_clocks<'a>(
+    def: &'a ModuleDef,
+    net: &str
+) -> Vec<&'a str> {
+    let mut ret = vec![];
+    for clock in def.conns.iter().filter(|x| x.net == net).filter_map(|x| x.clock.as_deref()) {
+        if clock != net && !ret.contains(&clock) {
+            ret.push(clock);
+        }
+    }
+    ret
+}
+
+// YAML value of the clocks of a net: null, the name of the clock, or a list of the
+// names if the net crosses the clock domains
+pub fn clock_value(
+    def: &ModuleDef,
+    net: &str
+) -> String {
+    match net_clocks(def, net).as_slice() {
+        [] => String::from("null"),
+        [clock] => escape_str(clock),
+        clocks => format!("[{}]", clocks.iter().map(|x| escape_str(x)).collect::<Vec<_>>().join(", "))
+    }
+}
+
+// resolve the port names and the directions of the port connections with the
+// definitions of the instantiated modules; a wildcard connection is expanded to
+// the ports whose names are the nets of the parent
+pub fn resolve_pins(
+    results: &mut [FileResult]
+) {
+    let ports: HashMap<Name, Vec<(String, bool)>> = results.iter()
+        .flat_map(|x| &x.defs)
+        .map(|x| (x.name.clone(), x.ports.iter().map(|x| (String::from(&*x.name), x.dir == "output")).collect()))
+        .collect();
+    for def in results.iter_mut().flat_map(|x| &mut x.defs) {
+        let mut conns = vec![];
+        for mut conn in std::mem::take(&mut def.conns) {
+            let child = conn.inst_name.as_ref()
+                .and_then(|x| def.insts.iter().find(|y| *y.inst_name == **x))
+                .and_then(|x| ports.get(&*x.mod_name));
+            let Some(child) = child else {
+                conns.push(conn);
+                continue;
+            };
+            if conn.net.is_empty() {
+                for (name, is_output) in child {
+                    if def.ports.iter().any(|x| *x.name == **name) || def.nets.contains(name) {
+                        conns.push(Connection {
+                            net: name.clone(),
+                            kind: conn.kind.clone(),
+                            driver: Some(*is_output),
+                            inst_name: conn.inst_name.clone(),
+                            port_name: Some(name.clone()),
+                            port_index: None,
+                            line: conn.line,
+                            clock: None
+                        });
+                    }
+                }
+                continue;
+            }
+            if let Some(i) = conn.port_index {
+                conn.port_name = child.get(i).map(|x| x.0.clone());
+            }
+            conn.driver = child.iter().find(|x| Some(&x.0) == conn.port_name.as_ref()).map(|x| x.1);
+            conns.push(conn);
+        }
+        def.conns = conns;
+        for pin in &mut def.const_pins {
+            let child = def.insts.iter().find(|x| *x.inst_name == pin.inst_name).and_then(|x| ports.get(&*x.mod_name));
+            let Some(child) = child else { continue; };
+            if let Some(i) = pin.port_index {
+                pin.port_name = child.get(i).map(|x| x.0.clone());
+            }
+            pin.driver = child.iter().find(|x| Some(&x.0) == pin.port_name.as_ref()).map(|x| x.1);
+        }
+    }
+}
+
+// a net with its references
+pub struct Net<'a> {
+    pub name: &'a str,
+    pub drivers: Vec<&'a Connection>,
+    pub loads: Vec<&'a Connection>,
+    pub unknown: Vec<&'a Connection>
+}
+
+// nets of a module in the order of the ports, the declarations, and the implicit
+// nets of the port connections and the continuous assignments; the ports
+// themselves are not counted as references
+pub fn nets(
+    def: &ModuleDef
+) -> Vec<Net<'_>> {
+    let mut names: Vec<&str> = def.ports.iter().map(|x| &*x.name).collect();
+    let implicit = def.conns.iter().filter(|x| x.kind == "pin" || (x.kind == "assign" && x.driver == Some(true)));
+    for name in def.nets.iter().chain(implicit.map(|x| &x.net)) {
+        if !name.is_empty() && !names.contains(&name.as_str()) && !def.params.iter().any(|x| &x.name == name) {
+            names.push(name);
+        }
+    }
+    let mut nets: Vec<Net> = names.iter().map(|&name| Net { name, drivers: vec![], loads: vec![], unknown: vec![] }).collect();
+    let index: HashMap<&str, usize> = names.iter().enumerate().map(|(i, &x)| (x, i)).collect();
+    for conn in &def.conns {
+        let Some(&i) = index.get(conn.net.as_str()) else { continue; };
+        let list = match conn.driver {
+            Some(true) => &mut nets[i].drivers,
+            Some(false) => &mut nets[i].loads,
+            None => &mut nets[i].unknown
+        };
+        // a net referred to more than once in a line is listed once
+        let same = |x: &&Connection| x.kind == conn.kind && x.line == conn.line && x.inst_name == conn.inst_name && x.port_name == conn.port_name;
+        if !list.iter().any(same) {
+            list.push(conn);
+        }
+    }
+    nets
+}
+
+fn print_conns(
+    key: &str,
+    conns: &[&Connection]
+) {
+    if conns.is_empty() {
+        outln!("            {}: []", key);
+        return;
+    }
+    outln!("            {}:", key);
+    for x in conns {
+        outln!("              - kind: {}", escape_str(&x.kind));
+        if let Some(inst_name) = &x.inst_name {
+            outln!("                inst_name: {}", escape_str(inst_name));
+        }
+        if let Some(port_name) = &x.port_name {
+            outln!("                port_name: {}", escape_str(port_name));
+        } else if let Some(i) = x.port_index {
+            outln!("                port_index: {}", i);
+        }
+        outln!("                line: {}", x.line);
+    }
+}
+
+pub fn print_nets(
+    def: &ModuleDef
+) {
+    let nets = nets(def);
+    if nets.is_empty() {
+        outln!("        nets: []");
+        return;
+    }
+    outln!("        nets:");
+    for net in &nets {
+        outln!("          - net_name: {}", escape_str(net.name));
+        // the ports of the module drive or load the nets from the outside
+        if let Some(port) = def.ports.iter().find(|x| *x.name == *net.name) {
+            outln!("            port_dir: {}", escape_str(&port.dir));
+        }
+        print_conns("drivers", &net.drivers);
+        print_conns("loads", &net.loads);
+        if !net.unknown.is_empty() {
+            print_conns("unknown", &net.unknown);
+        }
+    }
+}
+
+// fan-out of each net, and the nets which are never driven or never read; an input
+// port is driven and an output port is read from the outside, and a connection of
+// an unknown direction is regarded as both
+pub fn print_net_report(
+    results: &[FileResult]
+) {
+    for def in results.iter().flat_map(|x| &x.defs) {
+        let nets = nets(def);
+        outln!("  - mod_name: {}", escape_str(&def.name));
+        outln!("    file_name: {}", escape_str(def.file.to_str().unwrap()));
+        if nets.is_empty() {
+            outln!("    fanout: []");
+        } else {
+            outln!("    fanout:");
+        }
+        for net in &nets {
+            outln!("      - net_name: {}", escape_str(net.name));
+            outln!("        count: {}", net.loads.len());
+        }
+        let dir = |net: &Net| def.ports.iter().find(|x| *x.name == *net.name).map(|x| x.dir.as_str());
+        let undriven: Vec<&Net> = nets.iter()
+            .filter(|x| x.drivers.is_empty() && x.unknown.is_empty() && dir(x) != Some("input"))
+            .collect();
+        let unread: Vec<&Net> = nets.iter()
+            .filter(|x| x.loads.is_empty() && x.unknown.is_empty() && dir(x) != Some("output"))
+            .collect();
+        for (key, list) in [("undriven", undriven), ("unread", unread)] {
+            if list.is_empty() {
+                outln!("    {}: []", key);
+                continue;
+            }
+            outln!("    {}:", key);
+            for net in list {
+                outln!("      - {}", escape_str(net.name));
+            }
+        }
+    }
+}
+
+// input ports of the instances tied to constants, limited to the port names
+// matching any of the patterns if given; a port whose direction is not resolved is
+// also listed
+pub fn print_const_pins(
+    results: &[FileResult],
+    patterns: &[String]
+) {
+    let pins: Vec<(&ModuleDef, &ConstPin)> = results.iter()
+        .flat_map(|x| &x.defs)
+        .flat_map(|x| x.const_pins.iter().map(move |pin| (x, pin)))
+        .filter(|x| x.1.driver != Some(true))
+        .filter(|x| patterns.is_empty() || x.1.port_name.as_ref().is_some_and(|name| {
+            patterns.iter().any(|pattern| special::glob_match(pattern.as_bytes(), name.as_bytes()))
+        }))
+        .collect();
+    if pins.is_empty() {
+        outln!("const_pins: []");
+        return;
+    }
+    outln!("const_pins:");
+    for (parent, pin) in pins {
+        let inst = parent.insts.iter().find(|x| *x.inst_name == pin.inst_name);
+        outln!("  - parent_name: {}", escape_str(&parent.name));
+        outln!("    inst_name: {}", escape_str(&pin.inst_name));
+        if let Some(inst) = inst {
+            outln!("    mod_name: {}", escape_str(&inst.mod_name));
+        }
+        if let Some(port_name) = &pin.port_name {
+            outln!("    port_name: {}", escape_str(port_name));
+        } else if let Some(i) = pin.port_index {
+            outln!("    port_index: {}", i);
+        }
+        outln!("    value: {}", escape_str(&pin.value));
+        outln!("    file_name: {}", escape_str(parent.file.to_str().unwrap()));
+        outln!("    line: {}", pin.line);
+    }
+}
+
+// expressions connected to the ports of the instances with their inferred widths,
+// and the widths of the ports with the parameter values of the instances (null if
+// the module or the port is not found)
+pub fn print_pin_widths(
+    results: &[FileResult],
+    overrides: &HashMap<String, String>
+) {
+    let mut defs: HashMap<Symbol, &ModuleDef> = HashMap::new();
+    for def in results.iter().flat_map(|x| &x.defs) {
+        defs.entry(def.symbol).or_insert(def);
+    }
+    let pins: Vec<(&ModuleDef, &PinExpr)> = results.iter()
+        .flat_map(|x| &x.defs)
+        .flat_map(|x| x.pin_exprs.iter().map(move |pin| (x, pin)))
+        .collect();
+    if pins.is_empty() {
+        outln!("pin_widths: []");
+        return;
+    }
+    outln!("pin_widths:");
+    for (parent, pin) in pins {
+        let inst = parent.insts.iter().find(|x| *x.inst_name == pin.inst_name);
+        let child = inst.and_then(|x| defs.get(&x.mod_symbol));
+        let port = child.and_then(|x| match (&pin.port_name, pin.port_index) {
+            (Some(name), _) => x.ports.iter().find(|y| *y.name == *name),
+            (None, Some(i)) => x.ports.get(i),
+            _ => None
+        });
+        let port_width = inst.zip(child).zip(port).map(|((inst, child), port)| {
+            hier::port_width(port, &hier::effective_params(child, Some(inst), &parent.params, overrides, &[]))
+        });
+        outln!("  - parent_name: {}", escape_str(&parent.name));
+        outln!("    inst_name: {}", escape_str(&pin.inst_name));
+        if let Some(inst) = inst {
+            outln!("    mod_name: {}", escape_str(&inst.mod_name));
+        }
+        if let Some(port_name) = pin.port_name.as_deref().or(port.map(|x| &*x.name)) {
+            outln!("    port_name: {}", escape_str(port_name));
+        } else if let Some(i) = pin.port_index {
+            outln!("    port_index: {}", i);
+        }
+        outln!("    expr: {}", escape_str(&pin.expr));
+        outln!("    expr_width: {}", pin.width.map_or_else(|| String::from("null"), |x| x.to_string()));
+        outln!("    port_width: {}", port_width.map_or_else(|| String::from("null"), |x| x.to_string()));
+        outln!("    file_name: {}", escape_str(parent.file.to_str().unwrap()));
+        outln!("    line: {}", pin.line);
+    }
+}
@@ -0,0 +1,135 @@
+// Effects of the `unconnected_drive and `resetall directives. An `unconnected_drive
+// pulls the unconnected input ports of the modules after it to 0 or 1 until
+// `nounconnected_drive or `resetall, across the files compiled after it, and a
+// `resetall restores all the directives to the defaults. They are found in the
+// active code of the files themselves, not in the include files.
+
+use std::path::{Path, PathBuf};
+use crate::{escape_str, FileResult};
+
+// range where an `unconnected_drive is active, ended by the directive at the
+// location, or lasting to the end of the input (or of the file with --separate)
+pub struct DriveRange {
+    pub drive: String,
+    pub file: PathBuf,
+    pub line: usize,
+    pub end: Option<(String, PathBuf, usize)>
+}
+
+pub struct DriveDirectives {
+    pub ranges: Vec<DriveRange>,
+    pub resets: Vec<(PathBuf, usize)>,
+    // whether the files are separate (--separate), where a range ends with the file
+    separate: bool,
+    // whether the last range is still active
+    open: bool
+}
+
+impl DriveDirectives {
+    pub fn new(separate: bool) -> Self {
+        DriveDirectives { ranges: vec![], resets: vec![], separate, open: false }
+    }
+
+    // track the directives of a file, given in the order of the lines
+    pub fn add(&mut self, path: &Path, directives: &[(String, String, usize)]) {
+        for (name, arg, line) in directives {
+            match name.as_str() {
+                "unconnected_drive" => {
+                    self.close(name, path, *line);
+                    let drive = arg.split_whitespace().next().unwrap_or_default();
+                    self.ranges.push(DriveRange { drive: String::from(drive), file: path.to_path_buf(), line: *line, end: None });
+                    self.open = true;
+                }
+                "nounconnected_drive" => self.close(name, path, *line),
+                "resetall" => {
+                    self.close(name, path, *line);
+                    self.resets.push((path.to_path_buf(), *line));
+                }
+                _ => ()
+            }
+        }
+    }
+
+    // end the file, which ends the active range with --separate
+    pub fn end_file(&mut self) {
+        if self.separate {
+            self.open = false;
+        }
+    }
+
+    fn close(&mut self, name: &str, path: &Path, line: usize) {
+        if !self.open {
+            return;
+        }
+        if let Some(x) = self.ranges.last_mut() {
+            x.end = Some((String::from(name), path.to_path_buf(), line));
+        }
+        self.open = false;
+    }
+}
+
+// whether a location is at or after another in the order of the files
+fn is_after(
+    order: &[&Path],
+    x: (&Path, usize),
+    y: (&Path, usize)
+) -> bool {
+    let index = |path: &Path| order.iter().position(|&z| z == path);
+    (index(x.0), x.1) >= (index(y.0), y.1)
+}
+
+pub fn print_drive_directives(
+    directives: &DriveDirectives,
+    results: &[FileResult]
+) {
+    let order: Vec<&Path> = results.iter().map(|x| x.path.as_path()).collect();
+    if directives.ranges.is_empty() {
+        outln!("  unconnected_drive: []");
+    } else {
+        outln!("  unconnected_drive:");
+    }
+    for range in &directives.ranges {
+        outln!("    - drive: {}", escape_str(&range.drive));
+        outln!("      file_name: {}", escape_str(range.file.to_str().unwrap()));
+        outln!("      line: {}", range.line);
+        match &range.end {
+            Some((name, file, line)) => {
+                outln!("      ended_by: {}", escape_str(name));
+                outln!("      end_file: {}", escape_str(file.to_str().unwrap()));
+                outln!("      end_line: {}", line);
+            }
+            None => {
+                outln!("      ended_by: null");
+                outln!("      end_file: null");
+                outln!("      end_line: null");
+            }
+        }
+        // the modules whose declarations begin in the range
+        let modules: Vec<&str> = results.iter()
+            .flat_map(|x| &x.defs)
+            .filter(|x| is_after(&order, (&x.file, x.line), (&range.file, range.line)))
+            .filter(|x| match &range.end {
+                Some((_, file, line)) => !is_after(&order, (&x.file, x.line), (file, *line)),
+                None => !directives.separate || range.file == *x.file
+            })
+            .map(|x| &*x.name)
+            .collect();
+        if modules.is_empty() {
+            outln!("      modules: []");
+        } else {
+            outln!("      modules:");
+        }
+        for name in modules {
+            outln!("        - {}", escape_str(name));
+        }
+    }
+    if directives.resets.is_empty() {
+        outln!("  resetall: []");
+    } else {
+        outln!("  resetall:");
+    }
+    for (file, line) in &directives.resets {
+        outln!("    - file_name: {}", escape_str(file.to_str().unwrap()));
+        outln!("      line: {}", line);
+    }
+}
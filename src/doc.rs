@@ -0,0 +1,54 @@
+// block of comments immediately preceding a declaration; a comment after a token
+// on the same line or a blank line ends the block
+pub struct DocComment {
+    lines: Vec<String>,
+    newlines: usize,
+    after_token: bool
+}
+
+impl Default for DocComment {
+    fn default() -> Self {
+        DocComment { lines: vec![], newlines: 1, after_token: false }
+    }
+}
+
+impl DocComment {
+    pub fn whitespace(&mut self, text: &str) {
+        self.newlines += text.matches('\n').count();
+        if self.newlines >= 2 {
+            self.lines.clear();
+        }
+    }
+
+    pub fn comment(&mut self, text: &str) {
+        // a trailing comment describes the preceding token
+        if self.after_token && self.newlines == 0 {
+            self.lines.clear();
+        } else {
+            for line in text.lines() {
+                let line = line.trim();
+                let line = line.strip_suffix("*/").unwrap_or(line);
+                let line = line.trim_start_matches(['/', '*', '!']).trim();
+                if !line.is_empty() {
+                    self.lines.push(String::from(line));
+                }
+            }
+        }
+        self.newlines = usize::from(text.ends_with('\n'));
+        self.after_token = false;
+    }
+
+    // end the block at a token and return its text, which describes the token
+    pub fn token(&mut self) -> Option<String> {
+        let ret = self.text();
+        self.lines.clear();
+        self.newlines = 0;
+        self.after_token = true;
+        ret
+    }
+
+    // text of the current block, if any
+    pub fn text(&self) -> Option<String> {
+        if self.lines.is_empty() { None } else { Some(self.lines.join("\n")) }
+    }
+}
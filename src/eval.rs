@@ -0,0 +1,725 @@
+// Evaluator of the constant expressions in packed ranges and parameter values,
+// and of the packed widths of data types.
+// Only integer arithmetic and a few system functions are supported; an expression
+// which contains anything else (e.g. an unknown identifier or a function call)
+// cannot be evaluated.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use crate::ParamDef;
+
+// maximum depth of parameters and types which refer to other ones
+const MAX_PARAM_DEPTH: usize = 32;
+
+// maximum number of iterations of a generate loop to be counted
+const MAX_LOOP_COUNT: i64 = 1 << 16;
+
+// names visible to an expression: parameters of the module, and type definitions
+// and package parameters, the latter mapping a name (optionally with its package)
+// to the type text or the value
+#[derive(Clone, Copy)]
+pub struct Scope<'a> {
+    pub params: &'a [ParamDef],
+    pub typedefs: &'a HashMap<String, String>
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    // a sized number with its size; the value is None if it has x or z digits
+    Sized(i64, Option<i64>),
+    Ident(String),
+    Op(&'static str)
+}
+
+// longer operators come first so that they are matched greedily
+const OPERATORS: &[&str] = &[
+    "<<<", ">>>", "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "::", "+:", "-:",
+    "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "~", "!", "(", ")",
+    "[", "]", "{", "}", "?", ":", ",", ";", "=",
+];
+
+// widths of the built-in integer types
+const INTEGER_TYPES: &[(&str, i64)] = &[
+    ("bit", 1), ("logic", 1), ("reg", 1), ("byte", 8), ("shortint", 16),
+    ("int", 32), ("integer", 32), ("longint", 64), ("time", 64),
+];
+
+// digits of a based number such as 'hff or 'b1010; the value is None if the digits
+// have x, z, or ?
+fn read_based(
+    text: &[u8],
+    pos: &mut usize
+) -> Option<Option<i64>> {
+    *pos += 1;
+    if text.get(*pos).is_some_and(|x| x.eq_ignore_ascii_case(&b's')) {
+        *pos += 1;
+    }
+    let radix = match text.get(*pos)?.to_ascii_lowercase() {
+        b'b' => 2,
+        b'o' => 8,
+        b'd' => 10,
+        b'h' => 16,
+        _ => return None
+    };
+    *pos += 1;
+    while text.get(*pos).is_some_and(|x| x.is_ascii_whitespace()) {
+        *pos += 1;
+    }
+    let begin = *pos;
+    while text.get(*pos).is_some_and(|x| x.is_ascii_alphanumeric() || *x == b'_' || *x == b'?') {
+        *pos += 1;
+    }
+    let digits: String = text[begin..*pos].iter().filter(|&&x| x != b'_').map(|&x| x as char).collect();
+    // x, z, and ? digits fail here
+    Some(i64::from_str_radix(&digits, radix).ok())
+}
+
+fn tokenize(
+    text: &str
+) -> Option<Vec<Token>> {
+    let text = text.as_bytes();
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while pos < text.len() {
+        let c = text[pos];
+        if c.is_ascii_whitespace() {
+            pos += 1;
+        } else if c == b'\'' {
+            let value = read_based(text, &mut pos)?;
+            // the size of a sized number is kept for the width of an expression
+            match tokens.last() {
+                Some(&Token::Number(size)) => {
+                    tokens.pop();
+                    tokens.push(Token::Sized(size, value));
+                }
+                _ => tokens.push(Token::Number(value?))
+            }
+        } else if c.is_ascii_digit() {
+            let begin = pos;
+            while pos < text.len() && (text[pos].is_ascii_digit() || text[pos] == b'_') {
+                pos += 1;
+            }
+            let digits: String = text[begin..pos].iter().filter(|&&x| x != b'_').map(|&x| x as char).collect();
+            tokens.push(Token::Number(digits.parse().ok()?));
+        } else if c.is_ascii_alphabetic() || c == b'_' || c == b'$' {
+            let begin = pos;
+            while pos < text.len() && (text[pos].is_ascii_alphanumeric() || text[pos] == b'_' || text[pos] == b'$') {
+                pos += 1;
+            }
+            tokens.push(Token::Ident(String::from_utf8_lossy(&text[begin..pos]).into_owned()));
+        } else {
+            let op = OPERATORS.iter().find(|x| text[pos..].starts_with(x.as_bytes()))?;
+            pos += op.len();
+            tokens.push(Token::Op(op));
+        }
+    }
+    Some(tokens)
+}
+
+// binary operators from the lowest precedence
+const BINARY_OPERATORS: &[&[&str]] = &[
+    &["||"], &["&&"], &["|"], &["^"], &["&"], &["==", "!="],
+    &["<", "<=", ">", ">="], &["<<", ">>", "<<<", ">>>"], &["+", "-"], &["*", "/", "%"], &["**"],
+];
+
+struct Evaluator<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    scope: Scope<'a>,
+    depth: usize
+}
+
+impl Evaluator<'_> {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(x)) => Some(x),
+            _ => None
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Option<()> {
+        if self.peek_op() != Some(op) {
+            return None;
+        }
+        self.pos += 1;
+        Some(())
+    }
+
+    // conditional expression, which has the lowest precedence
+    fn expression(&mut self) -> Option<i64> {
+        let cond = self.binary(0)?;
+        if self.peek_op() != Some("?") {
+            return Some(cond);
+        }
+        self.pos += 1;
+        let lhs = self.expression()?;
+        self.expect(":")?;
+        let rhs = self.expression()?;
+        Some(if cond != 0 { lhs } else { rhs })
+    }
+
+    fn binary(&mut self, level: usize) -> Option<i64> {
+        if level == BINARY_OPERATORS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(op) = self.peek_op().filter(|x| BINARY_OPERATORS[level].contains(x)) {
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = match op {
+                "||" => ((lhs != 0) || (rhs != 0)) as i64,
+                "&&" => ((lhs != 0) && (rhs != 0)) as i64,
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "==" => (lhs == rhs) as i64,
+                "!=" => (lhs != rhs) as i64,
+                "<" => (lhs < rhs) as i64,
+                "<=" => (lhs <= rhs) as i64,
+                ">" => (lhs > rhs) as i64,
+                ">=" => (lhs >= rhs) as i64,
+                "<<" | "<<<" => lhs.checked_shl(u32::try_from(rhs).ok()?)?,
+                ">>" | ">>>" => lhs.checked_shr(u32::try_from(rhs).ok()?)?,
+                "+" => lhs.checked_add(rhs)?,
+                "-" => lhs.checked_sub(rhs)?,
+                "*" => lhs.checked_mul(rhs)?,
+                "/" => lhs.checked_div(rhs)?,
+                "%" => lhs.checked_rem(rhs)?,
+                "**" => lhs.checked_pow(u32::try_from(rhs).ok()?)?,
+                _ => return None
+            };
+        }
+        Some(lhs)
+    }
+
+    fn unary(&mut self) -> Option<i64> {
+        match self.tokens.get(self.pos)? {
+            Token::Op("-") => {
+                self.pos += 1;
+                self.unary()?.checked_neg()
+            }
+            Token::Op("+") => {
+                self.pos += 1;
+                self.unary()
+            }
+            Token::Op("~") => {
+                self.pos += 1;
+                Some(!self.unary()?)
+            }
+            Token::Op("!") => {
+                self.pos += 1;
+                Some((self.unary()? == 0) as i64)
+            }
+            _ => self.primary()
+        }
+    }
+
+    fn peek_ident(&self) -> Option<&str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Ident(x)) => Some(x),
+            _ => None
+        }
+    }
+
+    // skip the next identifier if it is one of `names`
+    fn skip_ident(&mut self, names: &[&str]) -> bool {
+        let found = self.peek_ident().is_some_and(|x| names.contains(&x));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    // packed width of a data type, e.g. logic [7:0] or a packed struct
+    fn type_width(&mut self) -> Option<i64> {
+        let name = String::from(self.peek_ident()?);
+        self.pos += 1;
+        let mut width = if let Some((_, x)) = INTEGER_TYPES.iter().find(|(x, _)| *x == name) {
+            *x
+        } else if name == "struct" || name == "union" {
+            if !self.skip_ident(&["packed"]) {
+                return None;
+            }
+            self.skip_ident(&["signed", "unsigned"]);
+            self.expect("{")?;
+            let mut total: i64 = 0;
+            while self.peek_op() != Some("}") {
+                self.skip_ident(&["rand", "randc"]);
+                let member = self.type_width()?;
+                // members declared together
+                loop {
+                    self.peek_ident()?;
+                    self.pos += 1;
+                    total = match name.as_str() {
+                        "struct" => total.checked_add(member)?,
+                        _ => total.max(member)
+                    };
+                    if self.peek_op() != Some(",") {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+                self.expect(";")?;
+            }
+            self.pos += 1;
+            total
+        } else if name == "enum" {
+            let base = if self.peek_op() == Some("{") { 32 } else { self.type_width()? };
+            self.expect("{")?;
+            let mut depth = 1;
+            while depth > 0 {
+                match self.tokens.get(self.pos)? {
+                    Token::Op("{") => depth += 1,
+                    Token::Op("}") => depth -= 1,
+                    _ => ()
+                }
+                self.pos += 1;
+            }
+            base
+        } else {
+            // a user-defined type, which may be prefixed with its package
+            let mut name = name;
+            if self.peek_op() == Some("::") {
+                self.pos += 1;
+                name = format!("{}::{}", name, self.peek_ident()?);
+                self.pos += 1;
+            }
+            let text = self.scope.typedefs.get(&name)?;
+            if self.depth >= MAX_PARAM_DEPTH {
+                return None;
+            }
+            eval_depth(text, self.scope, self.depth + 1, Evaluator::type_width)?
+        };
+        self.skip_ident(&["signed", "unsigned"]);
+        while self.peek_op() == Some("[") {
+            self.pos += 1;
+            let msb = self.expression()?;
+            self.expect(":")?;
+            let lsb = self.expression()?;
+            self.expect("]")?;
+            width = width.checked_mul(msb.checked_sub(lsb)?.checked_abs()? + 1)?;
+        }
+        Some(width)
+    }
+
+    fn primary(&mut self) -> Option<i64> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        match token {
+            Token::Number(x) => Some(*x),
+            Token::Sized(_, x) => *x,
+            Token::Ident(name) if name.starts_with('$') => {
+                let name = name.clone();
+                self.expect("(")?;
+                let value = match name.as_str() {
+                    "$clog2" => clog2(self.expression()?),
+                    "$signed" | "$unsigned" => self.expression()?,
+                    "$bits" => self.type_width()?,
+                    _ => return None
+                };
+                self.expect(")")?;
+                Some(value)
+            }
+            Token::Ident(name) => {
+                // a parameter of the module takes precedence over one of a package
+                let mut name = name.clone();
+                if self.peek_op() == Some("::") {
+                    self.pos += 1;
+                    name = format!("{}::{}", name, self.peek_ident()?);
+                    self.pos += 1;
+                }
+                let value = match self.scope.params.iter().find(|x| x.name == name) {
+                    Some(param) => param.value.as_deref()?,
+                    None => self.scope.typedefs.get(&name)?
+                };
+                if self.depth >= MAX_PARAM_DEPTH {
+                    return None;
+                }
+                eval_depth(value, self.scope, self.depth + 1, Evaluator::expression)
+            }
+            Token::Op("(") => {
+                let value = self.expression()?;
+                self.expect(")")?;
+                Some(value)
+            }
+            _ => None
+        }
+    }
+
+    // width of an expression connected to a port, with the widths of the nets and
+    // those of their elements selected by an index
+    fn width(&mut self, widths: &HashMap<String, (i32, i32)>) -> Option<i64> {
+        let token = self.tokens.get(self.pos)?.clone();
+        self.pos += 1;
+        match token {
+            Token::Sized(size, _) => Some(size),
+            // an unsized number has 32 bits
+            Token::Number(_) => Some(32),
+            Token::Ident(name) if name == "$signed" || name == "$unsigned" => {
+                self.expect("(")?;
+                let width = self.width(widths)?;
+                self.expect(")")?;
+                Some(width)
+            }
+            Token::Ident(name) => {
+                let &(width, elem) = widths.get(&name)?;
+                if self.peek_op() != Some("[") {
+                    return Some(width as i64);
+                }
+                self.pos += 1;
+                let width = self.select(elem)?;
+                // a select of an element is not followed
+                if self.peek_op() == Some("[") {
+                    return None;
+                }
+                Some(width)
+            }
+            Token::Op("(") => {
+                let width = self.width(widths)?;
+                self.expect(")")?;
+                Some(width)
+            }
+            Token::Op("{") => {
+                // a replication begins with a constant count
+                let begin = self.pos;
+                if let Some(count) = self.expression().filter(|_| self.peek_op() == Some("{")) {
+                    self.pos += 1;
+                    let width = self.concatenation(widths)?;
+                    self.expect("}")?;
+                    self.expect("}")?;
+                    return count.checked_mul(width);
+                }
+                self.pos = begin;
+                let width = self.concatenation(widths)?;
+                self.expect("}")?;
+                Some(width)
+            }
+            _ => None
+        }
+    }
+
+    fn concatenation(&mut self, widths: &HashMap<String, (i32, i32)>) -> Option<i64> {
+        let mut width = self.width(widths)?;
+        while self.peek_op() == Some(",") {
+            self.pos += 1;
+            width = width.checked_add(self.width(widths)?)?;
+        }
+        Some(width)
+    }
+
+    // width of a select after "[" up to "]": an element, a range of elements, or an
+    // indexed range; the index of an element or the base of an indexed range need
+    // not be constant
+    fn select(&mut self, elem: i32) -> Option<i64> {
+        let begin = self.pos;
+        let mut depth = 0;
+        let mut conds = 0;
+        let mut sep = None;
+        loop {
+            match self.tokens.get(self.pos)? {
+                Token::Op("(" | "[" | "{") => depth += 1,
+                Token::Op("]") if depth == 0 => break,
+                Token::Op(")" | "]" | "}") => depth -= 1,
+                Token::Op("?") if depth == 0 => conds += 1,
+                // the colon of a conditional operator does not separate the range
+                Token::Op(":") if depth == 0 && conds > 0 => conds -= 1,
+                Token::Op(x @ (":" | "+:" | "-:")) if depth == 0 => sep = Some((self.pos, *x)),
+                _ => ()
+            }
+            self.pos += 1;
+        }
+        let end = self.pos;
+        self.pos += 1;
+        let constant = |from: usize, to: usize| {
+            let mut e = Evaluator { tokens: self.tokens[from..to].to_vec(), pos: 0, scope: self.scope, depth: self.depth };
+            e.expression().filter(|_| e.pos == e.tokens.len())
+        };
+        let count = match sep {
+            None => 1,
+            Some((pos, ":")) => constant(begin, pos)?.checked_sub(constant(pos + 1, end)?)?.checked_abs()? + 1,
+            Some((pos, _)) => constant(pos + 1, end)?
+        };
+        count.checked_mul(elem as i64)
+    }
+}
+
+// ceiling of log2, as $clog2 returns
+fn clog2(
+    value: i64
+) -> i64 {
+    let mut ret = 0;
+    while ret < 64 && (1i128 << ret) < value as i128 {
+        ret += 1;
+    }
+    ret
+}
+
+// evaluate the whole text with `f`, e.g. as an expression or a data type
+fn eval_depth<'a>(
+    text: &str,
+    scope: Scope<'a>,
+    depth: usize,
+    f: fn(&mut Evaluator<'a>) -> Option<i64>
+) -> Option<i64> {
+    let mut e = Evaluator { tokens: tokenize(text)?, pos: 0, scope, depth };
+    let value = f(&mut e)?;
+    if e.pos != e.tokens.len() {
+        return None;
+    }
+    Some(value)
+}
+
+// value of a constant expression
+pub fn eval(
+    text: &str,
+    scope: Scope
+) -> Option<i64> {
+    eval_depth(text, scope, 0, Evaluator::expression)
+}
+
+// msb and lsb of a packed range
+pub fn eval_range(
+    msb: &str,
+    lsb: &str,
+    scope: Scope
+) -> Option<(i32, i32)> {
+    let msb = i32::try_from(eval(msb, scope)?).ok()?;
+    let lsb = i32::try_from(eval(lsb, scope)?).ok()?;
+    Some((msb, lsb))
+}
+
+// packed width of a data type
+pub fn eval_type(
+    text: &str,
+    scope: Scope
+) -> Option<i32> {
+    i32::try_from(eval_depth(text, scope, 0, Evaluator::type_width)?).ok()
+}
+
+// width of an element selected by an index from a data type: the rest of a packed
+// array without its first dimension (e.g. 8 for logic [3:0][7:0]), or a bit of a
+// type without any packed dimension
+pub fn elem_width(
+    text: &str,
+    scope: Scope
+) -> Option<i32> {
+    let mut tokens = tokenize(text)?;
+    // the first dimension after the base type, outside the members of a struct
+    let mut depth = 0;
+    let mut begin = None;
+    for (i, x) in tokens.iter().enumerate() {
+        match x {
+            Token::Op("{") => depth += 1,
+            Token::Op("}") => depth -= 1,
+            Token::Op("[") if depth == 0 => {
+                begin = Some(i);
+                break;
+            }
+            _ => ()
+        }
+    }
+    let Some(begin) = begin else { return Some(1); };
+    let end = begin + tokens[begin..].iter().position(|x| *x == Token::Op("]"))?;
+    tokens.drain(begin..=end);
+    let mut e = Evaluator { tokens, pos: 0, scope, depth: 0 };
+    let width = e.type_width()?;
+    if e.pos != e.tokens.len() {
+        return None;
+    }
+    i32::try_from(width).ok()
+}
+
+// width of an expression connected to a port: a net in `widths` (with the width of
+// its element selected by an index) and its select, a sized or unsized number, and
+// their concatenations and replications; an operator, an unknown identifier such as
+// a parameter, or a literal without a width such as '0 makes it unknown
+pub fn expr_width(
+    text: &str,
+    scope: Scope,
+    widths: &HashMap<String, (i32, i32)>
+) -> Option<i32> {
+    let mut e = Evaluator { tokens: tokenize(text)?, pos: 0, scope, depth: 0 };
+    let width = e.width(widths)?;
+    if e.pos != e.tokens.len() {
+        return None;
+    }
+    i32::try_from(width).ok()
+}
+
+// number of iterations of a generate loop, given the texts of its header
+pub fn loop_count(
+    genvar: &str,
+    init: &str,
+    cond: &str,
+    step: &str,
+    scope: Scope
+) -> Option<i64> {
+    loop_values(genvar, init, cond, step, scope).map(|x| x.len() as i64)
+}
+
+// values of the genvar in the iterations of a generate loop
+pub fn loop_values(
+    genvar: &str,
+    init: &str,
+    cond: &str,
+    step: &str,
+    scope: Scope
+) -> Option<Vec<i64>> {
+    // the next value of the genvar as an expression
+    let step = step.replace(' ', "");
+    let next = if step == format!("{}++", genvar) || step == format!("++{}", genvar) {
+        format!("{}+1", genvar)
+    } else if step == format!("{}--", genvar) || step == format!("--{}", genvar) {
+        format!("{}-1", genvar)
+    } else {
+        let (op, expr) = step.strip_prefix(genvar)?.split_once('=')?;
+        match op {
+            "" => String::from(expr),
+            _ => format!("{}{}({})", genvar, op, expr)
+        }
+    };
+    let mut params: Vec<ParamDef> = scope.params.iter()
+        .filter(|x| x.name != genvar)
+        .map(|x| ParamDef { name: x.name.clone(), value: x.value.clone(), description: None, is_local: x.is_local })
+        .collect();
+    let mut value = eval(init, scope)?;
+    params.push(ParamDef { name: String::from(genvar), value: Some(value.to_string()), description: None, is_local: true });
+    let mut values = vec![];
+    for _ in 0..=MAX_LOOP_COUNT {
+        let scope = Scope { params: &params, typedefs: scope.typedefs };
+        if eval(cond, scope)? == 0 {
+            return Some(values);
+        }
+        values.push(value);
+        value = eval(&next, scope)?;
+        params.last_mut()?.value = Some(value.to_string());
+    }
+    None
+}
+
+// whether a data type is a built-in integer type, whose packed range gives the bounds
+pub fn is_integer_type(
+    text: &str
+) -> bool {
+    match tokenize(text).as_deref() {
+        Some([Token::Ident(name), ..]) => INTEGER_TYPES.iter().any(|(x, _)| x == name),
+        _ => false
+    }
+}
+
+// name of a user-defined data type, with its package if any
+pub fn type_name(
+    text: &str
+) -> Option<String> {
+    let tokens = tokenize(text)?;
+    let Some(Token::Ident(name)) = tokens.first() else { return None; };
+    let keywords = ["struct", "union", "enum", "signed", "unsigned", "string", "real", "shortreal", "realtime"];
+    if name.starts_with('$') || keywords.contains(&name.as_str()) || INTEGER_TYPES.iter().any(|(x, _)| x == name) {
+        return None;
+    }
+    match (tokens.get(1), tokens.get(2)) {
+        (Some(Token::Op("::")), Some(Token::Ident(x))) => Some(format!("{}::{}", name, x)),
+        _ => Some(name.clone())
+    }
+}
+
+// number of bits in a packed range
+pub fn range_width(
+    range: Option<(i32, i32)>
+) -> i32 {
+    match range {
+        Some((msb, lsb)) => i32::try_from(msb.abs_diff(lsb)).map_or(i32::MAX, |x| x.saturating_add(1)),
+        None => 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    fn param(name: &str, value: &str) -> ParamDef {
+        ParamDef { name: String::from(name), value: Some(String::from(value)), description: None, is_local: false }
+    }
+
+    fn eval_with(text: &str, params: &[ParamDef], typedefs: &[(&str, &str)]) -> Option<i64> {
+        let typedefs: HashMap<String, String> = typedefs.iter().map(|(x, y)| (String::from(*x), String::from(*y))).collect();
+        eval(text, Scope { params, typedefs: &typedefs })
+    }
+
+    fn type_with(text: &str, params: &[ParamDef], typedefs: &[(&str, &str)]) -> Option<i32> {
+        let typedefs: HashMap<String, String> = typedefs.iter().map(|(x, y)| (String::from(*x), String::from(*y))).collect();
+        eval_type(text, Scope { params, typedefs: &typedefs })
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(eval_with("1+2*3", &[], &[]), Some(7));
+        assert_eq!(eval_with("(1+2)*3", &[], &[]), Some(9));
+        assert_eq!(eval_with("2**10-1", &[], &[]), Some(1023));
+        assert_eq!(eval_with("17/5+17%5", &[], &[]), Some(5));
+        assert_eq!(eval_with("1<<4|3", &[], &[]), Some(19));
+        assert_eq!(eval_with("-8>>>1", &[], &[]), Some(-4));
+        assert_eq!(eval_with("~0&'hf^5", &[], &[]), Some(10));
+        assert_eq!(eval_with("!0&&3>=3||0", &[], &[]), Some(1));
+        assert_eq!(eval_with("8'hff+4'b1010+'o7", &[], &[]), Some(272));
+        assert_eq!(eval_with("1/0", &[], &[]), None);
+        assert_eq!(eval_with("4'bx1", &[], &[]), None);
+        assert_eq!(eval_with("1+", &[], &[]), None);
+    }
+
+    #[test]
+    fn system_functions() {
+        assert_eq!(eval_with("$clog2(1)", &[], &[]), Some(0));
+        assert_eq!(eval_with("$clog2(5)", &[], &[]), Some(3));
+        assert_eq!(eval_with("$clog2(1024)", &[], &[]), Some(10));
+        assert_eq!(eval_with("$bits(logic[7:0])", &[], &[]), Some(8));
+        assert_eq!(eval_with("$bits(word_t)*2", &[], &[("word_t", "logic[3:0][7:0]")]), Some(64));
+        assert_eq!(eval_with("$signed(3)+$unsigned(4)", &[], &[]), Some(7));
+        assert_eq!(eval_with("$random(1)", &[], &[]), None);
+    }
+
+    #[test]
+    fn conditionals() {
+        let params = [param("W", "8"), param("MODE", "2")];
+        assert_eq!(eval_with("W>4?W:4", &params, &[]), Some(8));
+        assert_eq!(eval_with("MODE==0?1:MODE==1?2:3", &params, &[]), Some(3));
+        assert_eq!(eval_with("(W<4)?1:0", &params, &[]), Some(0));
+        assert_eq!(eval_with("W?1", &params, &[]), None);
+    }
+
+    #[test]
+    fn parameters_and_packages() {
+        let params = [param("W", "D*2"), param("D", "4"), param("LOOP", "LOOP+1")];
+        assert_eq!(eval_with("W-1", &params, &[]), Some(7));
+        assert_eq!(eval_with("pkg::N+1", &params, &[("pkg::N", "3")]), Some(4));
+        assert_eq!(eval_with("UNKNOWN", &params, &[]), None);
+        assert_eq!(eval_with("LOOP", &params, &[]), None);
+        assert_eq!(eval_range("W-1", "0", Scope { params: &params, typedefs: &HashMap::new() }), Some((7, 0)));
+        assert_eq!(range_width(Some((0, 7))), 8);
+        assert_eq!(range_width(None), 1);
+    }
+
+    #[test]
+    fn type_widths() {
+        let params = [param("W", "4")];
+        assert_eq!(type_with("logic[W-1:0]", &params, &[]), Some(4));
+        assert_eq!(type_with("int unsigned", &params, &[]), Some(32));
+        assert_eq!(type_with("logic[1:0][7:0]", &params, &[]), Some(16));
+        assert_eq!(type_with("struct packed{logic a;logic[6:0]b,c;}", &params, &[]), Some(15));
+        assert_eq!(type_with("enum logic[1:0]{A,B}", &params, &[]), Some(2));
+        assert_eq!(type_with("pkg::t[2:0]", &params, &[("pkg::t", "byte")]), Some(24));
+        assert_eq!(elem_width("logic[3:0][7:0]", Scope { params: &params, typedefs: &HashMap::new() }), Some(8));
+    }
+
+    #[test]
+    fn loops() {
+        let params = [param("N", "4")];
+        let scope = Scope { params: &params, typedefs: &HashMap::new() };
+        assert_eq!(loop_values("i", "0", "i<N", "i++", scope), Some(vec![0, 1, 2, 3]));
+        assert_eq!(loop_values("i", "N", "i>0", "i-=2", scope), Some(vec![4, 2]));
+        assert_eq!(loop_values("i", "1", "i<=N", "i=i*2", scope), Some(vec![1, 2, 4]));
+        assert_eq!(loop_count("i", "0", "i<N", "i=i", scope), None);
+    }
+}
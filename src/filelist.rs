@@ -0,0 +1,454 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{canonicalize, read_to_string, write};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use tracing::{error, warn};
+use crate::logging::{self, LogOpt};
+use crate::{find_defs, read_defines, Interner, Opt};
+
+#[derive(StructOpt)]
+#[structopt(name = "filelist", about = "Write the resolved input files as a deduplicated filelist")]
+pub struct FilelistOpt {
+    /// A list of source files
+    #[structopt(parse(from_os_str))]
+    pub files: Vec<PathBuf>,
+
+    /// Filelist whose paths are relative to the current directory
+    #[structopt(short = "f", multiple = true, number_of_values = 1)]
+    pub filelists: Vec<PathBuf>,
+
+    /// Filelist whose paths are relative to the filelist itself
+    #[structopt(short = "F", multiple = true, number_of_values = 1)]
+    pub rel_filelists: Vec<PathBuf>,
+
+    /// Define
+    #[structopt(short = "d", long = "define", multiple = true, number_of_values = 1)]
+    pub defines: Vec<String>,
+
+    /// Include path
+    #[structopt(short = "i", long = "include", multiple = true, number_of_values = 1)]
+    pub includes: Vec<PathBuf>,
+
+    /// Library file
+    #[structopt(short = "v", multiple = true, number_of_values = 1)]
+    pub lib_files: Vec<PathBuf>,
+
+    /// Library directory searched for the modules not defined in the files
+    #[structopt(short = "y", multiple = true, number_of_values = 1)]
+    pub lib_dirs: Vec<PathBuf>,
+
+    /// Extension of the files in the library directories (.v and .sv by default)
+    #[structopt(long = "libext", multiple = true, number_of_values = 1)]
+    pub libexts: Vec<String>,
+
+    /// Dialect of the filelist
+    #[structopt(long = "flavor", possible_values = &["vcs", "questa", "xcelium"], default_value = "vcs")]
+    pub flavor: String,
+
+    /// Output file (standard output by default)
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    pub log: LogOpt
+}
+
+// the entries of the filelists and the command line
+#[derive(Default)]
+struct Entries {
+    files: Vec<PathBuf>,
+    defines: Vec<String>,
+    includes: Vec<PathBuf>,
+    lib_files: Vec<PathBuf>,
+    lib_dirs: Vec<PathBuf>,
+    libexts: Vec<String>,
+    ignore_include: bool,
+    visited: HashSet<PathBuf>
+}
+
+// expand $NAME and ${NAME} with the environment variables
+fn expand_env(
+    text: &str
+) -> String {
+    let mut ret = String::new();
+    let mut rest = text;
+    while let Some(i) = rest.find('$') {
+        ret.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        let (name, len) = match rest.strip_prefix('{') {
+            Some(x) => match x.find('}') {
+                Some(j) => (&x[..j], j + 2),
+                None => ("", 0)
+            },
+            None => {
+                let j = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+                (&rest[..j], j)
+            }
+        };
+        match std::env::var(name) {
+            Ok(x) if !name.is_empty() => ret.push_str(&x),
+            _ => {
+                ret.push('$');
+                continue;
+            }
+        }
+        rest = &rest[len..];
+    }
+    ret.push_str(rest);
+    ret
+}
+
+// arguments in a line separated by whitespace, where a quoted part may have whitespace
+fn split_args(
+    line: &str
+) -> Vec<String> {
+    let mut ret = vec![];
+    let mut arg = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                arg.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => ret.extend(arg.take()),
+            c => arg.get_or_insert_with(String::new).push(c)
+        }
+    }
+    ret.extend(arg);
+    ret
+}
+
+// read a filelist in any of the dialects; the paths are relative to the base
+// directory if given
+fn read_filelist(
+    path: &Path,
+    base: Option<&Path>,
+    entries: &mut Entries
+) -> bool {
+    let canonical_path = canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if !entries.visited.insert(canonical_path) {
+        warn!("filelist included recursively: {:?}", path);
+        return true;
+    }
+    let Ok(text) = read_to_string(path) else {
+        error!("failed to read: {:?}", path);
+        return false;
+    };
+    let resolve = |x: &str| match base {
+        Some(base) if Path::new(x).is_relative() => base.join(x),
+        _ => PathBuf::from(x)
+    };
+    let mut tokens = vec![];
+    for line in text.lines() {
+        let line = line.split("//").next().unwrap_or_default();
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        tokens.extend(split_args(line).iter().map(|x| expand_env(x)));
+    }
+    let mut ok = true;
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        if let Some(x) = token.strip_prefix("+incdir+") {
+            entries.includes.extend(x.split('+').filter(|x| !x.is_empty()).map(resolve));
+        } else if let Some(x) = token.strip_prefix("+define+") {
+            entries.defines.extend(x.split('+').filter(|x| !x.is_empty()).map(String::from));
+        } else if let Some(x) = token.strip_prefix("+libext+") {
+            entries.libexts.extend(x.split('+').filter(|x| !x.is_empty()).map(String::from));
+        } else if ["-incdir", "-define", "-v", "-y", "-f", "-F"].contains(&token.as_str()) {
+            let Some(arg) = tokens.next() else {
+                error!("missing argument of {} in {:?}", token, path);
+                ok = false;
+                break;
+            };
+            match token.as_str() {
+                "-incdir" => entries.includes.push(resolve(&arg)),
+                "-define" => entries.defines.push(arg),
+                "-v" => entries.lib_files.push(resolve(&arg)),
+                "-y" => entries.lib_dirs.push(resolve(&arg)),
+                "-f" => ok &= read_filelist(&resolve(&arg), base, entries),
+                _ => {
+                    let path = resolve(&arg);
+                    let dir = path.parent().map(|x| x.to_path_buf()).unwrap_or_default();
+                    ok &= read_filelist(&path, Some(&dir), entries);
+                }
+            }
+        } else if token.starts_with('+') || token.starts_with('-') {
+            // an option of the simulator, which is assumed to take no argument
+            warn!("unknown option ignored: {}", token);
+        } else {
+            entries.files.push(resolve(&token));
+        }
+    }
+    ok
+}
+
+// remove the paths reachable via multiple paths, keeping the first one
+fn dedup_paths(
+    paths: &mut Vec<PathBuf>
+) {
+    let mut seen = HashSet::new();
+    paths.retain(|x| seen.insert(canonicalize(x).unwrap_or_else(|_| x.clone())));
+}
+
+// add the files of the library directories which define the modules instantiated
+// but not defined in the files or the library files, until no more module is found
+fn find_lib_modules(
+    entries: &mut Entries
+) {
+    let libexts = if entries.libexts.is_empty() { vec![String::from(".v"), String::from(".sv")] } else { entries.libexts.clone() };
+    let mut defines = read_defines(&entries.defines);
+    let mut typedefs = HashMap::new();
+    let names = Interner::new();
+    let mut defined = HashSet::new();
+    let mut used = vec![];
+    // a file which cannot be scanned, e.g. for a syntax error, is analyzed later but
+    // its modules and instances are not known here
+    let (includes, ignore_include) = (entries.includes.clone(), entries.ignore_include);
+    let scan = |path: &Path, defines: &mut _, typedefs: &mut _| {
+        let defs = find_defs(path, defines, typedefs, &includes, ignore_include, &names);
+        if defs.is_none() {
+            warn!("failed to find the modules for the library directories: {:?}", path);
+        }
+        defs.unwrap_or_default()
+    };
+    // the modules instantiated in the library files may be in the library directories
+    for path in &entries.lib_files {
+        for def in scan(path, &mut defines, &mut typedefs) {
+            used.extend(def.insts.into_iter().map(|x| String::from(&*x.mod_name)));
+            defined.insert(String::from(&*def.name));
+        }
+    }
+    let mut i = 0;
+    loop {
+        while i < entries.files.len() {
+            for def in scan(&entries.files[i], &mut defines, &mut typedefs) {
+                used.extend(def.insts.into_iter().map(|x| String::from(&*x.mod_name)));
+                defined.insert(String::from(&*def.name));
+            }
+            i += 1;
+        }
+        if used.is_empty() {
+            break;
+        }
+        for name in std::mem::take(&mut used) {
+            if defined.contains(&name) {
+                continue;
+            }
+            let path = entries.lib_dirs.iter()
+                .flat_map(|dir| libexts.iter().map(move |ext| (dir, ext)))
+                .map(|(dir, ext)| dir.join(format!("{}{}", name, ext)))
+                .find(|x| x.is_file());
+            match path {
+                Some(path) if !entries.files.contains(&path) => entries.files.push(path),
+                _ => ()
+            }
+            defined.insert(name);
+        }
+    }
+}
+
+// read the filelists given to the analysis by -f and -F into its options, before the
+// entries of the command line
+pub fn merge(
+    opt: &mut Opt
+) -> bool {
+    let mut ok = true;
+    let mut entries = Entries::default();
+    for path in &opt.filelists {
+        ok &= read_filelist(path, None, &mut entries);
+    }
+    for path in &opt.rel_filelists {
+        let dir = path.parent().map(|x| x.to_path_buf()).unwrap_or_default();
+        ok &= read_filelist(path, Some(&dir), &mut entries);
+    }
+    entries.files.append(&mut opt.files);
+    entries.defines.append(&mut opt.defines);
+    entries.includes.append(&mut opt.includes);
+    entries.lib_files.append(&mut opt.lib_files);
+    entries.lib_dirs.append(&mut opt.lib_dirs);
+    entries.libexts.append(&mut opt.libexts);
+    opt.files = entries.files;
+    opt.defines = entries.defines;
+    opt.includes = entries.includes;
+    opt.lib_files = entries.lib_files;
+    opt.lib_dirs = entries.lib_dirs;
+    opt.libexts = entries.libexts;
+    ok
+}
+
+// add the files of the library directories which define the modules instantiated
+// but not defined in the files of the analysis to its library files
+pub fn search_lib_dirs(
+    opt: &mut Opt
+) {
+    let mut entries = Entries {
+        files: opt.files.clone(),
+        defines: opt.defines.clone(),
+        includes: opt.includes.clone(),
+        lib_files: opt.lib_files.clone(),
+        lib_dirs: opt.lib_dirs.clone(),
+        libexts: opt.libexts.clone(),
+        ignore_include: opt.ignore_include,
+        ..Entries::default()
+    };
+    let count = entries.files.len();
+    find_lib_modules(&mut entries);
+    opt.lib_files.extend(entries.files.drain(count..));
+}
+
+// an argument of the filelist, quoted if it has whitespace
+fn quote(
+    text: &str
+) -> String {
+    if text.contains(char::is_whitespace) {
+        format!("\"{}\"", text.replace('"', "\\\""))
+    } else {
+        String::from(text)
+    }
+}
+
+fn format_filelist(
+    entries: &Entries,
+    flavor: &str
+) -> String {
+    let mut text = String::new();
+    // a plusarg is quoted as a whole, and an option separately from its argument
+    let mut push = |option: &str, arg: &str| {
+        match option.strip_suffix(' ') {
+            Some(x) => text.push_str(&format!("{} {}", x, quote(arg))),
+            None => text.push_str(&quote(&format!("{}{}", option, arg)))
+        }
+        text.push('\n');
+    };
+    let (incdir, define) = if flavor == "xcelium" { ("-incdir ", "-define ") } else { ("+incdir+", "+define+") };
+    for include in &entries.includes {
+        push(incdir, &include.to_string_lossy());
+    }
+    for define_arg in &entries.defines {
+        push(define, define_arg);
+    }
+    for lib_file in &entries.lib_files {
+        push("-v ", &lib_file.to_string_lossy());
+    }
+    for file in &entries.files {
+        push("", &file.to_string_lossy());
+    }
+    text
+}
+
+pub fn run(
+    opt: &FilelistOpt
+) -> i32 {
+    logging::init(&opt.log);
+    let mut exit_code = 0;
+    let mut entries = Entries::default();
+    for path in &opt.filelists {
+        if !read_filelist(path, None, &mut entries) {
+            exit_code = 1;
+        }
+    }
+    for path in &opt.rel_filelists {
+        let dir = path.parent().map(|x| x.to_path_buf()).unwrap_or_default();
+        if !read_filelist(path, Some(&dir), &mut entries) {
+            exit_code = 1;
+        }
+    }
+    entries.files.extend(opt.files.iter().cloned());
+    entries.defines.extend(opt.defines.iter().cloned());
+    entries.includes.extend(opt.includes.iter().cloned());
+    entries.lib_files.extend(opt.lib_files.iter().cloned());
+    entries.lib_dirs.extend(opt.lib_dirs.iter().cloned());
+    entries.libexts.extend(opt.libexts.iter().cloned());
+
+    dedup_paths(&mut entries.files);
+    dedup_paths(&mut entries.includes);
+    dedup_paths(&mut entries.lib_files);
+    let mut seen = HashSet::new();
+    entries.defines.retain(|x| seen.insert(x.clone()));
+    for path in entries.files.iter().chain(&entries.lib_files) {
+        if !path.is_file() {
+            error!("file not found: {:?}", path);
+            exit_code = 1;
+        }
+    }
+    find_lib_modules(&mut entries);
+
+    let text = format_filelist(&entries, &opt.flavor);
+    match &opt.output {
+        Some(path) => {
+            if let Err(x) = write(path, text) {
+                error!("failed to write: {:?} ({})", path, x);
+                return 1;
+            }
+        }
+        None => print!("{}", text)
+    }
+    exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::create_dir_all;
+    use super::*;
+
+    #[test]
+    fn filelists_are_read_from_their_bases() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path();
+        create_dir_all(base.join("ip/rtl")).unwrap();
+        write(base.join("top.f"), "// the top\n-F ip/ip.f  // the ip\n+incdir+inc+inc2 +define+A=1+B\n# a comment\ntop.sv\n\
+            -f sub.f\n-define C -incdir \"dir with space\"\n").unwrap();
+        write(base.join("ip/ip.f"), "rtl/ip.sv\n+libext+.v+.sv\n-v rtl/cells.v -y rtl\n").unwrap();
+        write(base.join("sub.f"), "sub.sv\n").unwrap();
+        let mut entries = Entries::default();
+        assert!(read_filelist(&base.join("top.f"), Some(base), &mut entries));
+        // a filelist by -F is relative to itself, and one by -f to the current base
+        assert_eq!(entries.files, [base.join("ip/rtl/ip.sv"), base.join("top.sv"), base.join("sub.sv")]);
+        assert_eq!(entries.includes, [base.join("inc"), base.join("inc2"), base.join("dir with space")]);
+        assert_eq!(entries.defines, ["A=1", "B", "C"]);
+        assert_eq!(entries.libexts, [".v", ".sv"]);
+        assert_eq!(entries.lib_files, [base.join("ip/rtl/cells.v")]);
+        assert_eq!(entries.lib_dirs, [base.join("ip/rtl")]);
+    }
+
+    #[test]
+    fn recursive_and_missing_filelists() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path();
+        write(base.join("a.f"), "-F a.f\na.sv\n").unwrap();
+        let mut entries = Entries::default();
+        assert!(read_filelist(&base.join("a.f"), Some(base), &mut entries));
+        assert_eq!(entries.files, [base.join("a.sv")]);
+        assert!(!read_filelist(&base.join("missing.f"), None, &mut entries));
+        write(base.join("b.f"), "b.sv -v\n").unwrap();
+        assert!(!read_filelist(&base.join("b.f"), Some(base), &mut entries));
+    }
+
+    #[test]
+    fn lib_dirs_are_searched_until_no_more_module() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path();
+        create_dir_all(base.join("lib")).unwrap();
+        write(base.join("top.sv"), "module top; a u_a (); lcell u_cell (); missing u_missing (); endmodule\n").unwrap();
+        write(base.join("cells.v"), "module lcell; b u_b (); endmodule\n").unwrap();
+        write(base.join("lib/a.sv"), "module a; c u_c (); endmodule\n").unwrap();
+        write(base.join("lib/b.v"), "module b; endmodule\n").unwrap();
+        write(base.join("lib/c.v"), "module c; endmodule\n").unwrap();
+        write(base.join("lib/unused.v"), "module unused; endmodule\n").unwrap();
+        let mut entries = Entries {
+            files: vec![base.join("top.sv")],
+            lib_files: vec![base.join("cells.v")],
+            lib_dirs: vec![base.join("lib")],
+            ..Entries::default()
+        };
+        find_lib_modules(&mut entries);
+        // the dependencies of the library files and of the files found are searched
+        assert_eq!(entries.files, [base.join("top.sv"), base.join("lib/b.v"), base.join("lib/a.sv"), base.join("lib/c.v")]);
+        entries.files.truncate(1);
+        entries.libexts = vec![String::from(".v")];
+        find_lib_modules(&mut entries);
+        assert_eq!(entries.files, [base.join("top.sv"), base.join("lib/b.v")]);
+    }
+}
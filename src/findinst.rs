@@ -0,0 +1,133 @@
+// Query of the elaborated instance paths: the hierarchy under each top module is
+// elaborated, and the paths matching a glob (`*` and `?`, where `*` also matches
+// the dots between the levels) or a regular expression are printed with the
+// module names and the locations of the instantiations.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use regex::Regex;
+use structopt::StructOpt;
+use tracing::{error, warn};
+use crate::hier::{self, HierNode};
+use crate::logging::{self, LogOpt};
+use crate::{escape_str, find_defs, read_defines, special, DefKind, Interner, ModuleDef, Symbol};
+
+#[derive(StructOpt)]
+#[structopt(name = "find-inst", about = "Find the instances whose hierarchical paths match a pattern")]
+pub struct FindInstOpt {
+    /// Pattern of the instance paths, e.g. 'top.*.u_fifo*'
+    pub pattern: String,
+
+    /// A list of source files
+    #[structopt(required = true, parse(from_os_str))]
+    pub files: Vec<PathBuf>,
+
+    /// Define
+    #[structopt(short = "d", long = "define", multiple = true, number_of_values = 1)]
+    pub defines: Vec<String>,
+
+    /// Top module; the modules which are not instantiated by default
+    #[structopt(long = "top", multiple = true, number_of_values = 1)]
+    pub tops: Vec<String>,
+
+    /// Regard the pattern as a regular expression matching the whole path
+    #[structopt(long = "regex")]
+    pub regex: bool,
+
+    #[structopt(flatten)]
+    pub log: LogOpt
+}
+
+// print the instances matching a pattern under a node; the number of them is returned
+fn print_matches(
+    node: &HierNode,
+    is_match: &dyn Fn(&str) -> bool
+) -> usize {
+    let mut ret = 0;
+    if is_match(&node.path) {
+        let mod_name = node.inst.map(|x| &*x.mod_name).or(node.def.map(|x| &*x.name)).unwrap_or_default();
+        println!("  - inst_path: {}", escape_str(&node.path));
+        println!("    mod_name: {}", escape_str(mod_name));
+        // the location of the instantiation, or the definition for the top module
+        let location = match (node.inst, node.def) {
+            (Some(inst), _) => Some((&inst.file, inst.line)),
+            (None, Some(def)) => Some((&def.file, def.line)),
+            _ => None
+        };
+        if let Some((file, line)) = location {
+            println!("    file_name: {}", escape_str(file.to_str().unwrap()));
+            println!("    line: {}", line);
+        }
+        ret += 1;
+    }
+    for child in &node.children {
+        ret += print_matches(child, is_match);
+    }
+    ret
+}
+
+pub fn run(
+    opt: &FindInstOpt
+) -> i32 {
+    logging::init(&opt.log);
+    let mut exit_code = 0;
+    let regex = if opt.regex {
+        match Regex::new(&format!("^(?:{})$", opt.pattern)) {
+            Ok(x) => Some(x),
+            Err(x) => {
+                error!("invalid regular expression: {} ({})", opt.pattern, x);
+                return 1;
+            }
+        }
+    } else {
+        None
+    };
+    let is_match = |path: &str| match &regex {
+        Some(x) => x.is_match(path),
+        None => special::glob_match(opt.pattern.as_bytes(), path.as_bytes())
+    };
+
+    let mut defines = read_defines(&opt.defines);
+    let mut typedefs = HashMap::new();
+    let names = Interner::new();
+    let mut defs: Vec<ModuleDef> = vec![];
+    for path in &opt.files {
+        match find_defs(path, &mut defines, &mut typedefs, &[], true, &names) {
+            Some(x) => defs.extend(x),
+            None => {
+                error!("failed to find the modules: {:?}", path);
+                exit_code = 1;
+            }
+        }
+    }
+    let defs: Vec<&ModuleDef> = defs.iter().collect();
+
+    // the modules which are not instantiated are the tops unless given
+    let tops: Vec<&str> = if opt.tops.is_empty() {
+        let used: HashSet<Symbol> = defs.iter().flat_map(|x| &x.insts).map(|x| x.mod_symbol).collect();
+        defs.iter().filter(|x| x.kind == DefKind::Module && !used.contains(&x.symbol)).map(|x| &*x.name).collect()
+    } else {
+        opt.tops.iter().map(|x| x.as_str()).collect()
+    };
+
+    let mut count = 0;
+    println!("insts:");
+    for top in tops {
+        let mut cycles = vec![];
+        match hier::elaborate(&defs, top, &HashMap::new(), &HashMap::new(), &HashSet::new(), false, &mut cycles) {
+            Some(root) => count += print_matches(&root, &is_match),
+            None => {
+                error!("top module not found: {}", top);
+                exit_code = 1;
+            }
+        }
+        for cycle in &cycles {
+            warn!("instantiation cycle: {}", cycle.join(" -> "));
+        }
+    }
+    // no match is a failure as grep
+    if count == 0 && exit_code == 0 {
+        exit_code = 1;
+    }
+    exit_code
+}
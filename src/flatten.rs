@@ -0,0 +1,424 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{read, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{debug, error, warn};
+use crate::{Library, Opt};
+
+// include directives with a literal file name, and the directives of macros and
+// conditional compilation with a macro name, in a text as (beginning, end,
+// directive, name); the name is empty for `else and `endif
+fn find_directives(
+    text: &str
+) -> Vec<(usize, usize, &str, String)> {
+    let b = text.as_bytes();
+    let mut ret = vec![];
+    let mut i = 0;
+    while i < b.len() {
+        if b[i..].starts_with(b"//") {
+            i = b[i..].iter().position(|&c| c == b'\n').map_or(b.len(), |x| i + x);
+        } else if b[i..].starts_with(b"/*") {
+            i = b[i + 2..].windows(2).position(|x| x == b"*/").map_or(b.len(), |x| i + x + 4);
+        } else if b[i] == b'"' {
+            i += 1;
+            while i < b.len() && b[i] != b'"' && b[i] != b'\n' {
+                i += if b[i] == b'\\' { 2 } else { 1 };
+            }
+            i += 1;
+        } else if b[i] == b'`' {
+            let begin = i;
+            i += 1;
+            let len = b[i..].iter().position(|c| !c.is_ascii_alphanumeric() && *c != b'_').unwrap_or(b.len() - i);
+            let directive = &text[i..i + len];
+            i += len;
+            if !["include", "define", "undef", "ifdef", "ifndef", "elsif", "else", "endif"].contains(&directive) {
+                continue;
+            }
+            if directive == "else" || directive == "endif" {
+                ret.push((begin, i, directive, String::new()));
+                continue;
+            }
+            while i < b.len() && (b[i] == b' ' || b[i] == b'\t') {
+                i += 1;
+            }
+            if directive != "include" {
+                let len = b[i..].iter().position(|c| !c.is_ascii_alphanumeric() && *c != b'_' && *c != b'$').unwrap_or(b.len() - i);
+                ret.push((begin, i + len, directive, String::from(&text[i..i + len])));
+                i += len;
+                continue;
+            }
+            let close = match b.get(i) {
+                Some(b'"') => b'"',
+                Some(b'<') => b'>',
+                _ => continue
+            };
+            let Some(len) = b[i + 1..].iter().position(|&c| c == close || c == b'\n') else { continue; };
+            if b[i + 1 + len] != close {
+                continue;
+            }
+            ret.push((begin, i + len + 2, directive, String::from(&text[i + 1..i + 1 + len])));
+            i += len + 2;
+        } else {
+            i += 1;
+        }
+    }
+    ret
+}
+
+// include directives with a literal file name in a text as (beginning, end, name)
+pub fn find_includes(
+    text: &str
+) -> Vec<(usize, usize, String)> {
+    find_directives(text).into_iter()
+        .filter(|x| x.2 == "include")
+        .map(|(begin, end, _, name)| (begin, end, name))
+        .collect()
+}
+
+// an `ifdef or `ifndef being read: whether the code outside it is active, whether
+// any of its branches has been taken, and whether the current branch is active
+struct Conditional {
+    outer: bool,
+    taken: bool,
+    active: bool
+}
+
+// an include file is searched in the current directory, the directory of the
+// including file, and the include paths in this order
+fn resolve_include(
+    name: &str,
+    from: &Path,
+    includes: &[PathBuf]
+) -> Option<PathBuf> {
+    let dir = from.parent().map(|x| x.to_path_buf()).unwrap_or_default();
+    std::iter::once(PathBuf::from(name))
+        .chain(std::iter::once(dir.join(name)))
+        .chain(includes.iter().map(|x| x.join(name)))
+        .find(|x| x.is_file())
+}
+
+// candidates of an include file in the order the preprocessor probes them: the
+// name itself (relative to the current directory), and the name in each include
+// path if it is relative and not found there, each with whether it exists
+fn include_probes(
+    name: &str,
+    includes: &[PathBuf]
+) -> Vec<(PathBuf, bool)> {
+    let path = PathBuf::from(name);
+    let mut ret = vec![(path.clone(), path.exists())];
+    if path.is_relative() && !path.exists() {
+        for dir in includes {
+            let found = dir.join(&path).exists();
+            ret.push((dir.join(&path), found));
+            if found {
+                break;
+            }
+        }
+    }
+    ret
+}
+
+// trace the search of the include files of a file and of the include files found,
+// nested with dots like `gcc -H`: every candidate probed and the one chosen
+pub fn trace_includes(
+    path: &Path,
+    includes: &[PathBuf],
+    stack: &mut Vec<PathBuf>
+) {
+    let Ok(bytes) = read(path) else { return; };
+    let text = String::from_utf8_lossy(&bytes).into_owned();
+    stack.push(path.to_path_buf());
+    let dots = ".".repeat(stack.len());
+    for (begin, _, name) in find_includes(&text) {
+        let line = text[..begin].matches('\n').count() + 1;
+        debug!("{} include \"{}\" at {:?}:{}", dots, name, path, line);
+        let probes = include_probes(&name, includes);
+        for (candidate, found) in &probes {
+            debug!("{}   probed {:?}{}", dots, candidate, if *found { " (found)" } else { "" });
+        }
+        let Some((file, _)) = probes.into_iter().find(|x| x.1) else {
+            debug!("{}   not found", dots);
+            continue;
+        };
+        let canonical = file.canonicalize().unwrap_or_else(|_| file.clone());
+        if !stack.iter().any(|x| x.canonicalize().ok().as_ref() == Some(&canonical)) {
+            trace_includes(&file, includes, stack);
+        }
+    }
+    stack.pop();
+}
+
+// text of a file with the include files inlined recursively; the conditional
+// compilation is followed with the macros defined so far, and an include directive
+// in the inactive code is kept as is
+pub fn flatten(
+    path: &Path,
+    includes: &[PathBuf],
+    every_occurrence: bool,
+    defined: &mut HashSet<String>,
+    included: &mut Vec<PathBuf>,
+    stack: &mut Vec<PathBuf>
+) -> Option<String> {
+    let text = String::from_utf8_lossy(&read(path).ok()?).into_owned();
+    stack.push(path.to_path_buf());
+    let mut ret = String::new();
+    let mut pos = 0;
+    let mut conds: Vec<Conditional> = vec![];
+    for (begin, end, directive, name) in find_directives(&text) {
+        let active = conds.last().is_none_or(|x| x.active);
+        match (directive, conds.last_mut()) {
+            ("include", _) if active => (),
+            ("define", _) if active => {
+                defined.insert(name);
+                continue;
+            }
+            ("undef", _) if active => {
+                defined.remove(&name);
+                continue;
+            }
+            ("ifdef" | "ifndef", _) => {
+                let cond = defined.contains(&name) == (directive == "ifdef");
+                conds.push(Conditional { outer: active, taken: cond, active: active && cond });
+                continue;
+            }
+            ("elsif", Some(x)) => {
+                let cond = !x.taken && defined.contains(&name);
+                x.active = x.outer && cond;
+                x.taken |= cond;
+                continue;
+            }
+            ("else", Some(x)) => {
+                x.active = x.outer && !x.taken;
+                x.taken = true;
+                continue;
+            }
+            ("endif", _) => {
+                conds.pop();
+                continue;
+            }
+            _ => continue
+        }
+        ret.push_str(&text[pos..begin]);
+        pos = end;
+        let Some(file) = resolve_include(&name, path, includes) else {
+            warn!("include file not found: {} ({:?})", name, path);
+            ret.push_str(&text[begin..end]);
+            continue;
+        };
+        let canonical = file.canonicalize().unwrap_or_else(|_| file.clone());
+        if stack.iter().any(|x| x.canonicalize().ok().as_ref() == Some(&canonical)) {
+            warn!("include cycle: {:?} ({:?})", file, path);
+            ret.push_str(&text[begin..end]);
+            continue;
+        }
+        if !every_occurrence && included.contains(&canonical) {
+            continue;
+        }
+        included.push(canonical);
+        match flatten(&file, includes, every_occurrence, defined, included, stack) {
+            Some(x) => ret.push_str(&x),
+            None => {
+                error!("failed to read include file: {:?}", file);
+                ret.push_str(&text[begin..end]);
+            }
+        }
+    }
+    ret.push_str(&text[pos..]);
+    stack.pop();
+    Some(ret)
+}
+
+// location (file and line) of the last `define of each macro in a file and its
+// include files, which are followed at the include directives
+pub fn define_locations(
+    path: &Path,
+    includes: &[PathBuf],
+    stack: &mut Vec<PathBuf>,
+    locations: &mut HashMap<String, (PathBuf, usize)>
+) {
+    let Ok(bytes) = read(path) else { return; };
+    let text = String::from_utf8_lossy(&bytes).into_owned();
+    stack.push(path.to_path_buf());
+    let mut directives = find_includes(&text).into_iter().peekable();
+    let mut offset = 0;
+    for (i, line) in text.split('\n').enumerate() {
+        let mut words = line.split_whitespace();
+        if words.next() == Some("`define") {
+            if let Some(name) = words.next().and_then(|x| x.split('(').next()).filter(|x| !x.is_empty()) {
+                locations.insert(String::from(name), (path.to_path_buf(), i + 1));
+            }
+        }
+        offset += line.len() + 1;
+        while let Some((_, _, name)) = directives.next_if(|x| x.0 < offset) {
+            let Some(file) = resolve_include(&name, path, includes) else { continue; };
+            let canonical = file.canonicalize().unwrap_or_else(|_| file.clone());
+            if !stack.iter().any(|x| x.canonicalize().ok().as_ref() == Some(&canonical)) {
+                define_locations(&file, includes, stack, locations);
+            }
+        }
+    }
+    stack.pop();
+}
+
+// whether a file is guarded by `ifndef and `define of the same macro, so that its
+// inclusion into itself stops
+pub fn has_include_guard(
+    text: &str
+) -> bool {
+    let mut words = text.lines()
+        .map(|x| x.split("//").next().unwrap_or_default())
+        .flat_map(|x| x.split_whitespace());
+    match (words.next(), words.next(), words.next(), words.next()) {
+        (Some("`ifndef"), Some(x), Some("`define"), Some(y)) => x == y,
+        _ => false
+    }
+}
+
+// the deepest chain of include files from a file, or a cycle of include files
+// which are not guarded; the chains found are memoized
+fn include_chain(
+    path: &Path,
+    includes: &[PathBuf],
+    stack: &mut Vec<PathBuf>,
+    memo: &mut HashMap<PathBuf, Vec<PathBuf>>
+) -> Result<Vec<PathBuf>, Vec<PathBuf>> {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if let Some(x) = memo.get(&canonical) {
+        return Ok(x.clone());
+    }
+    let text = String::from_utf8_lossy(&read(path).unwrap_or_default()).into_owned();
+    stack.push(path.to_path_buf());
+    let mut deepest = vec![];
+    for (_, _, name) in find_includes(&text) {
+        // an include file not found is reported by the preprocessor
+        let Some(file) = resolve_include(&name, path, includes) else { continue; };
+        let file_canonical = file.canonicalize().unwrap_or_else(|_| file.clone());
+        if let Some(i) = stack.iter().position(|x| x.canonicalize().ok().as_ref() == Some(&file_canonical)) {
+            let guarded = stack[i..].iter().any(|x| read(x).is_ok_and(|x| has_include_guard(&String::from_utf8_lossy(&x))));
+            if guarded {
+                continue;
+            }
+            let mut cycle = stack[i..].to_vec();
+            cycle.push(file);
+            stack.pop();
+            return Err(cycle);
+        }
+        let chain = include_chain(&file, includes, stack, memo).inspect_err(|_| { stack.pop(); })?;
+        if chain.len() > deepest.len() {
+            deepest = chain;
+        }
+    }
+    stack.pop();
+    deepest.insert(0, path.to_path_buf());
+    memo.insert(canonical, deepest.clone());
+    Ok(deepest)
+}
+
+// check that the include files of a file are nested up to the depth without a
+// cycle; the chain of the files is returned otherwise
+pub fn check_includes(
+    path: &Path,
+    includes: &[PathBuf],
+    max_depth: usize
+) -> Result<(), String> {
+    let format_chain = |x: &[PathBuf]| x.iter().map(|x| x.to_string_lossy()).collect::<Vec<_>>().join(" -> ");
+    match include_chain(path, includes, &mut vec![], &mut HashMap::new()) {
+        Ok(chain) if chain.len() > max_depth + 1 => Err(format!("include depth exceeds {}: {}", max_depth, format_chain(&chain))),
+        Ok(_) => Ok(()),
+        Err(cycle) => Err(format!("include cycle: {}", format_chain(&cycle)))
+    }
+}
+
+
+// write the files with the include files inlined, concatenated into the output file
+// or the standard output; false if any file is not read or written
+pub fn write_files(
+    files: &[(&PathBuf, usize)],
+    libraries: &[Library],
+    opt: &Opt
+) -> bool {
+    let mut ok = true;
+    let mut text = String::new();
+    let mut included = vec![];
+    // the macros defined so far in the output, which decide the active code
+    let mut defined = HashSet::new();
+    for (path, lib) in files {
+        defined.extend(libraries[*lib].defines.iter().filter(|x| x.1.is_some()).map(|x| x.0.clone()));
+        match flatten(path, &libraries[*lib].includes, opt.flatten_every_include, &mut defined, &mut included, &mut vec![]) {
+            Some(x) => {
+                text.push_str(&x);
+                if !x.ends_with('\n') {
+                    text.push('\n');
+                }
+            }
+            None => {
+                error!("failed to read: {:?}", path);
+                ok = false;
+            }
+        }
+    }
+    match &opt.output {
+        Some(path) => {
+            if let Err(x) = File::create(path).and_then(|mut x| x.write_all(text.as_bytes())) {
+                error!("failed to write: {:?} ({})", path, x);
+                ok = false;
+            }
+        }
+        None => out!("{}", text)
+    }
+    ok
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, write};
+    use super::*;
+
+    fn flatten_file(
+        path: &Path,
+        includes: &[PathBuf],
+        every_occurrence: bool
+    ) -> String {
+        flatten(path, includes, every_occurrence, &mut HashSet::new(), &mut vec![], &mut vec![]).unwrap()
+    }
+
+    #[test]
+    fn includes_are_inlined() {
+        let dir = tempfile::tempdir().unwrap();
+        create_dir_all(dir.path().join("inc")).unwrap();
+        write(dir.path().join("top.sv"), "`include \"a.svh\"\n`include \"b.svh\"\nmodule top; endmodule\n").unwrap();
+        write(dir.path().join("a.svh"), "`define A 1\n").unwrap();
+        write(dir.path().join("inc/b.svh"), "`ifdef A\n`include \"c.svh\"\n`else\n`include \"missing.svh\"\n`endif\n").unwrap();
+        write(dir.path().join("inc/c.svh"), "typedef int c_t;\n").unwrap();
+        let text = flatten_file(&dir.path().join("top.sv"), &[dir.path().join("inc")], false);
+        // the include file of the inactive branch is kept as is
+        assert_eq!(text, "`define A 1\n\n`ifdef A\ntypedef int c_t;\n\n`else\n`include \"missing.svh\"\n`endif\n\nmodule top; endmodule\n");
+    }
+
+    #[test]
+    fn guarded_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("top.sv"), "`include \"g.svh\"\n`include \"g.svh\"\n").unwrap();
+        write(dir.path().join("g.svh"), "`ifndef G_SVH\n`define G_SVH\n`include \"g.svh\"\ntypedef int g_t;\n`endif\n").unwrap();
+        let once = "`ifndef G_SVH\n`define G_SVH\n`include \"g.svh\"\ntypedef int g_t;\n`endif\n";
+        // the inclusion into itself is kept for the guard of the preprocessor
+        assert_eq!(flatten_file(&dir.path().join("top.sv"), &[], false), format!("{}\n\n", once));
+        assert_eq!(flatten_file(&dir.path().join("top.sv"), &[], true), format!("{}\n{}\n", once, once));
+        assert!(has_include_guard(once));
+        assert!(!has_include_guard("typedef int g_t;\n`ifndef G_SVH\n`define G_SVH\n`endif\n"));
+    }
+
+    #[test]
+    fn include_cycles_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let top = dir.path().join("top.sv");
+        write(&top, "`include \"a.svh\"\n").unwrap();
+        write(dir.path().join("a.svh"), "`include \"b.svh\"\n").unwrap();
+        write(dir.path().join("b.svh"), "`include \"a.svh\"\n").unwrap();
+        assert_eq!(flatten_file(&top, &[], false), "`include \"a.svh\"\n\n\n");
+        let err = check_includes(&top, &[], 10).unwrap_err();
+        let chain: Vec<String> = ["a.svh", "b.svh", "a.svh"].iter().map(|x| dir.path().join(x).to_string_lossy().into_owned()).collect();
+        assert_eq!(err, format!("include cycle: {}", chain.join(" -> ")));
+        assert!(check_includes(&dir.path().join("b.svh"), &[], 0).is_err());
+    }
+}
@@ -0,0 +1,437 @@
+use std::fs::{read_to_string, write};
+use std::path::PathBuf;
+use structopt::StructOpt;
+use tracing::error;
+use crate::logging::{self, LogOpt};
+use crate::{escape_str, scan};
+
+#[derive(StructOpt)]
+#[structopt(name = "fmt", about = "Reformat the indentation and alignment of the files")]
+pub struct FmtOpt {
+    /// A list of source files
+    #[structopt(parse(from_os_str), required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Number of spaces per indentation level
+    #[structopt(long = "indent", default_value = "4")]
+    pub indent: usize,
+
+    /// Maximum length of a line, over which a line is wrapped at a comma (0 for no limit)
+    #[structopt(long = "max-width", default_value = "100")]
+    pub max_width: usize,
+
+    /// Do not align port declarations and continuous assignments
+    #[structopt(long = "no-align")]
+    pub no_align: bool,
+
+    /// Only report the files which would be changed, without writing them
+    #[structopt(long = "check")]
+    pub check: bool,
+
+    #[structopt(flatten)]
+    pub log: LogOpt
+}
+
+// words which open and close a block
+const OPENERS: &[&str] = &[
+    "begin", "fork", "case", "casex", "casez", "randcase", "module", "macromodule",
+    "interface", "program", "package", "class", "function", "task", "generate",
+    "covergroup", "property", "sequence", "clocking", "specify", "config", "primitive",
+    "table", "checker",
+];
+
+// words which make a declaration in the statement a prototype without a body
+const PROTOTYPES: &[&str] = &["extern", "import", "export", "pure", "typedef"];
+
+// statements whose body may follow on the next line without begin
+const HANGING: &[&str] = &[
+    "if", "else", "for", "foreach", "while", "repeat", "forever", "always", "always_ff",
+    "always_comb", "always_latch", "initial", "final",
+];
+
+#[derive(PartialEq, Clone, Copy)]
+enum Kind {
+    Word,
+    Symbol,
+    Other
+}
+
+// code tokens of a line as (beginning, end, kind); comments are omitted
+fn line_tokens(
+    line: &str,
+    in_comment: &mut bool
+) -> Vec<(usize, usize, Kind)> {
+    let b = line.as_bytes();
+    let mut ret = vec![];
+    let mut i = 0;
+    while i < b.len() {
+        let begin = i;
+        if *in_comment {
+            match line[i..].find("*/") {
+                Some(x) => {
+                    i += x + 2;
+                    *in_comment = false;
+                }
+                None => i = b.len()
+            }
+        } else if b[i..].starts_with(b"//") {
+            i = b.len();
+        } else if b[i..].starts_with(b"/*") {
+            i += 2;
+            *in_comment = true;
+        } else if b[i] == b'"' {
+            i += 1;
+            while i < b.len() && b[i] != b'"' {
+                i += if b[i] == b'\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(b.len());
+            ret.push((begin, i, Kind::Other));
+        } else if b[i].is_ascii_whitespace() {
+            i += 1;
+        } else if b[i].is_ascii_alphanumeric() || b[i] == b'_' || b[i] == b'$' || b[i] == b'`' || b[i] == b'\\' {
+            let escaped = b[i] == b'\\';
+            i += 1;
+            while i < b.len() && (if escaped { !b[i].is_ascii_whitespace() } else { b[i].is_ascii_alphanumeric() || b[i] == b'_' || b[i] == b'$' || b[i] == b'\'' }) {
+                i += 1;
+            }
+            let kind = if b[begin].is_ascii_digit() || b[begin] == b'`' { Kind::Other } else { Kind::Word };
+            ret.push((begin, i, kind));
+        } else {
+            i += 1;
+            ret.push((begin, i, Kind::Symbol));
+        }
+    }
+    ret
+}
+
+fn is_closer(
+    word: &str
+) -> bool {
+    (word.starts_with("end") && scan::KEYWORDS.contains(&word)) || word.starts_with("join")
+}
+
+// openers (true) and closers (false) of blocks and brackets in a line
+fn block_events(
+    line: &str,
+    tokens: &[(usize, usize, Kind)]
+) -> Vec<bool> {
+    let text = |i: usize| &line[tokens[i].0..tokens[i].1];
+    let mut ret = vec![];
+    for i in 0..tokens.len() {
+        let x = text(i);
+        let prev = if i > 0 { text(i - 1) } else { "" };
+        let next = if i + 1 < tokens.len() { text(i + 1) } else { "" };
+        if x == "(" || x == "{" {
+            ret.push(true);
+        } else if x == ")" || x == "}" {
+            ret.push(false);
+        } else if tokens[i].2 != Kind::Word || prev == "." {
+            continue;
+        } else if is_closer(x) {
+            ret.push(false);
+        } else if OPENERS.contains(&x) {
+            // prototypes and references without a body, such as `import "DPI-C" function`
+            // and `extern virtual task`, where the words may be apart in the statement
+            let is_prototype = (0..i).rev().map(text).take_while(|x| *x != ";").any(|x| PROTOTYPES.contains(&x));
+            let no_body = is_prototype || ["wait", "disable"].contains(&prev) ||
+                (x == "interface" && (next == "class" || prev == "virtual"));
+            if !no_body {
+                ret.push(true);
+            }
+        }
+    }
+    ret
+}
+
+// column at which the declared name starts in a port declaration
+fn name_column(
+    line: &str,
+    tokens: &[(usize, usize, Kind)]
+) -> Option<usize> {
+    let mut depth = 0;
+    for (i, &(b, e, kind)) in tokens.iter().enumerate() {
+        let x = &line[b..e];
+        if x == "[" || x == "(" {
+            depth += 1;
+        } else if x == "]" || x == ")" {
+            depth -= 1;
+        } else if depth == 0 && kind == Kind::Word && !scan::KEYWORDS.contains(&x) {
+            let next = tokens.get(i + 1).map(|&(b, e, _)| &line[b..e]);
+            if next.is_none_or(|x| x == "," || x == ";" || x == "=" || x == "[" || x == ")") {
+                return Some(b);
+            }
+        }
+    }
+    None
+}
+
+// column of the first assignment in a continuous assignment
+fn assign_column(
+    line: &str,
+    tokens: &[(usize, usize, Kind)]
+) -> Option<usize> {
+    let mut depth = 0;
+    for (i, &(b, e, _)) in tokens.iter().enumerate() {
+        let x = &line[b..e];
+        if x == "[" || x == "(" || x == "{" {
+            depth += 1;
+        } else if x == "]" || x == ")" || x == "}" {
+            depth -= 1;
+        } else if depth == 0 && x == "=" && tokens.get(i + 1).is_none_or(|&(b, e, _)| &line[b..e] != "=") {
+            return Some(b);
+        }
+    }
+    None
+}
+
+// pad the lines of a group so that the given columns are aligned
+fn align(
+    lines: &mut [String],
+    group: &[(usize, usize)]
+) {
+    let Some(max) = group.iter().map(|&(i, col)| lines[i][..col].trim_end().len()).max() else { return; };
+    for &(i, col) in group {
+        let head = lines[i][..col].trim_end();
+        lines[i] = format!("{}{} {}", head, " ".repeat(max - head.len()), &lines[i][col..]);
+    }
+}
+
+// split a line at the commas before the maximum width; a line split in brackets
+// is continued with one more level of indentation as the brackets open a level
+fn wrap(
+    line: &str,
+    unit: &str,
+    max_width: usize
+) -> Vec<String> {
+    let mut ret = vec![];
+    let mut rest = String::from(line);
+    loop {
+        if max_width == 0 || rest.len() <= max_width {
+            ret.push(rest);
+            return ret;
+        }
+        let mut in_comment = false;
+        let tokens = line_tokens(&rest, &mut in_comment);
+        let indent = &rest[..rest.len() - rest.trim_start().len()];
+        let mut depth = 0;
+        let mut split = None;
+        for &(b, e, _) in &tokens {
+            match &rest[b..e] {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" => depth -= 1,
+                "," if e < max_width && e > indent.len() + unit.len() && !rest[e..].trim().is_empty() && !rest[e..].trim_start().starts_with("//") => split = Some((e, depth > 0)),
+                _ => ()
+            }
+        }
+        let Some((split, in_brackets)) = split else {
+            ret.push(rest);
+            return ret;
+        };
+        let continuation = format!("{}{}", indent, if in_brackets { unit } else { "" });
+        ret.push(String::from(&rest[..split]));
+        rest = format!("{}{}", continuation, rest[split..].trim_start());
+    }
+}
+
+// reformat a text; None if the result would not have the same tokens
+pub fn format(
+    text: &str,
+    opt: &FmtOpt
+) -> Option<String> {
+    let unit = " ".repeat(opt.indent);
+    let mut lines: Vec<String> = vec![];
+    let mut ports = vec![];
+    let mut assigns = vec![];
+    let mut groups: Vec<Vec<(usize, usize)>> = vec![];
+    // the openers of a line make a level of (unclosed openers, indentation of the line)
+    let mut levels: Vec<(usize, usize)> = vec![];
+    let mut hang = 0;
+    // hanging indentations of the unmatched if statements of each level, to which else is aligned
+    let mut if_hangs: Vec<Vec<usize>> = vec![vec![]];
+    let mut in_comment = false;
+    let mut continued = false;
+    for org in text.lines() {
+        let org = org.trim_end();
+        let starts_in_comment = in_comment;
+        let tokens = line_tokens(org, &mut in_comment);
+        // a line in a comment or a macro definition is kept as is
+        if starts_in_comment || continued {
+            continued = org.ends_with('\\');
+            lines.push(String::from(org));
+            continue;
+        }
+        continued = org.ends_with('\\');
+        let code = org.trim_start();
+        if code.is_empty() {
+            lines.push(String::new());
+            groups.push(std::mem::take(&mut ports));
+            groups.push(std::mem::take(&mut assigns));
+            continue;
+        }
+        let first = tokens.first().map(|&(b, e, _)| &org[b..e]).unwrap_or("");
+        let last = tokens.last().map(|&(b, e, _)| &org[b..e]).unwrap_or("");
+        let starts_with_closer = first == ")" || first == "}" || is_closer(first);
+        let else_at = tokens.iter().position(|&(b, e, kind)| kind == Kind::Word && &org[b..e] == "else");
+        let else_if = else_at.and_then(|i| tokens.get(i + 1)).is_some_and(|&(b, e, _)| &org[b..e] == "if");
+        if else_at == Some(0) {
+            hang = if_hangs[levels.len()].pop().unwrap_or(hang);
+        }
+        if first == "if" && else_at.is_none() {
+            if_hangs[levels.len()].push(hang);
+        }
+        let level = match levels.last() {
+            Some(&(_, x)) if starts_with_closer => x,
+            _ => levels.last().map_or(0, |x| x.1 + 1) + if first == "begin" { 0 } else { hang }
+        };
+        let line = format!("{}{}", unit.repeat(level), code);
+        let shift = line.len() - code.len();
+        let offset = org.len() - code.len();
+        let mut opened = 0;
+        let mut balanced = true;
+        let mut reopened = false;
+        for is_open in block_events(org, &tokens) {
+            if is_open {
+                opened += 1;
+            } else if opened > 0 {
+                opened -= 1;
+            } else if let Some(top) = levels.last_mut() {
+                balanced = false;
+                top.0 -= 1;
+                reopened = top.0 > 0;
+                if top.0 == 0 {
+                    levels.pop();
+                }
+            }
+        }
+        // a line which continues a level, such as ") (", opens no new level
+        let outer = levels.len();
+        if opened > 0 {
+            balanced = false;
+            match levels.last_mut() {
+                Some(top) if reopened => top.0 += opened,
+                _ => levels.push((opened, level))
+            }
+        }
+        if_hangs.resize(levels.len() + 1, vec![]);
+        // else after the end of a block, such as "end else"
+        if else_at.is_some_and(|x| x > 0) && first != "if" {
+            hang = if_hangs[outer].pop().unwrap_or(0);
+        }
+        if else_if {
+            if_hangs[outer].push(hang);
+        }
+        // the next line is indented if this one starts a statement without its body
+        let is_hanging = HANGING.contains(&first) || else_at.is_some_and(|_| first != "if");
+        hang = if is_hanging && opened == 0 && last != ";" && last != "begin" && !is_closer(last) {
+            hang + 1
+        } else if first == "begin" || last == ";" || last == "begin" || is_closer(last) {
+            0
+        } else {
+            hang
+        };
+        let i = lines.len();
+        let is_directive = code.starts_with('`');
+        if !opt.no_align && !is_directive && ["input", "output", "inout"].contains(&first) {
+            if let Some(col) = name_column(org, &tokens) {
+                ports.push((i, col - offset + shift));
+            }
+        } else {
+            groups.push(std::mem::take(&mut ports));
+        }
+        if !opt.no_align && !is_directive && first == "assign" && balanced {
+            if let Some(col) = assign_column(org, &tokens) {
+                assigns.push((i, col - offset + shift));
+            }
+        } else {
+            groups.push(std::mem::take(&mut assigns));
+        }
+        lines.push(line);
+    }
+    groups.push(ports);
+    groups.push(assigns);
+    for group in groups {
+        if group.len() > 1 {
+            align(&mut lines, &group);
+        }
+    }
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut ret = String::new();
+    for line in lines {
+        let wrapped = if line.trim_start().starts_with('`') || line.ends_with('\\') { vec![line] } else { wrap(&line, &unit, opt.max_width) };
+        for x in wrapped {
+            ret.push_str(&x);
+            ret.push_str(newline);
+        }
+    }
+    // only the whitespace may be changed
+    let tokens = |x: &str| scan::lex(x).map(|x| x.into_iter().map(|(_, x, _)| x).collect::<Vec<_>>());
+    if tokens(text)? != tokens(&ret)? {
+        return None;
+    }
+    Some(ret)
+}
+
+pub fn run(
+    opt: &FmtOpt
+) -> i32 {
+    logging::init(&opt.log);
+    let mut exit_code = 0;
+    let mut any_changed = false;
+    println!("formatted:");
+    for path in &opt.files {
+        let Ok(text) = read_to_string(path) else {
+            error!("failed to read: {:?}", path);
+            exit_code = 1;
+            continue;
+        };
+        let Some(formatted) = format(&text, opt) else {
+            error!("failed to format: {:?}", path);
+            exit_code = 1;
+            continue;
+        };
+        let is_changed = formatted != text;
+        if is_changed && !opt.check {
+            if let Err(x) = write(path, &formatted) {
+                error!("failed to write: {:?} ({})", path, x);
+                exit_code = 1;
+                continue;
+            }
+        }
+        any_changed |= is_changed;
+        println!("  - file_name: {}", escape_str(path.to_str().unwrap()));
+        println!("    changed: {}", is_changed);
+    }
+    // the check fails if any file is not formatted
+    if opt.check && any_changed {
+        exit_code = 1;
+    }
+    exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use structopt::StructOpt;
+    use super::*;
+
+    fn fmt(text: &str) -> String {
+        format(text, &FmtOpt::from_iter(["fmt", "x.sv"])).unwrap()
+    }
+
+    #[test]
+    fn imports_and_exports_have_no_body() {
+        let text = "module m;\nimport \"DPI-C\" function int c_add(int a, int b);\nimport \"DPI-C\" context task c_wait();\n\
+            export \"DPI-C\" function sv_add;\ninitial begin\nc_wait();\nend\nendmodule\nmodule n;\nendmodule\n";
+        assert_eq!(fmt(text), "module m;\n    import \"DPI-C\" function int c_add(int a, int b);\n    import \"DPI-C\" context task c_wait();\n\
+            \x20   export \"DPI-C\" function sv_add;\n    initial begin\n        c_wait();\n    end\nendmodule\nmodule n;\nendmodule\n");
+    }
+
+    #[test]
+    fn prototypes_have_no_body() {
+        let text = "extern module e(input a);\nvirtual class c;\npure virtual function void f();\nextern protected task t();\n\
+            virtual function void g();\nf();\nendfunction\nendclass\ninterface class i;\nendclass\nmodule e(input a);\n\
+            virtual interface bus_if vif;\nendmodule\n";
+        assert_eq!(fmt(text), "extern module e(input a);\nvirtual class c;\n    pure virtual function void f();\n    extern protected task t();\n\
+            \x20   virtual function void g();\n        f();\n    endfunction\nendclass\ninterface class i;\nendclass\nmodule e(input a);\n\
+            \x20   virtual interface bus_if vif;\nendmodule\n");
+    }
+}
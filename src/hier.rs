@@ -0,0 +1,636 @@
+use std::collections::{HashMap, HashSet};
+use tracing::{error, warn};
+use crate::{escape_str, eval, pinout, GenScope, InstDef, InstKind, ModuleDef, Opt, ParamDef, PortDef};
+
+// an instance in the elaborated hierarchy
+pub struct HierNode<'a> {
+    pub path: String,
+    pub def: Option<&'a ModuleDef>,
+    pub inst: Option<&'a InstDef>,
+    // effective parameter values after the overrides
+    pub params: Vec<ParamDef>,
+    // a blackbox, whose children are not elaborated
+    pub blackbox: bool,
+    pub children: Vec<HierNode<'a>>
+}
+
+impl<'a> HierNode<'a> {
+    fn mod_name(&self) -> &'a str {
+        match (self.inst, self.def) {
+            (Some(inst), _) => &inst.mod_name,
+            (None, Some(def)) => &def.name,
+            _ => ""
+        }
+    }
+}
+
+fn find_def<'a>(
+    defs: &[&'a ModuleDef],
+    name: &str
+) -> Option<&'a ModuleDef> {
+    defs.iter().find(|x| *x.name == *name).copied()
+}
+
+// items of a parameter override "#(...)" as (name, value); the name is None for
+// an ordered assignment
+fn override_items(
+    text: &str
+) -> Vec<(Option<&str>, &str)> {
+    let text = text.strip_prefix('#').unwrap_or(text);
+    let text = text.strip_prefix('(').and_then(|x| x.strip_suffix(')')).unwrap_or(text);
+    let mut items = vec![];
+    let mut depth = 0;
+    let mut begin = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(&text[begin..i]);
+                begin = i + 1;
+            }
+            _ => ()
+        }
+    }
+    items.push(&text[begin..]);
+    items.into_iter()
+        .filter(|x| !x.is_empty())
+        .map(|x| match x.strip_prefix('.').and_then(|x| x.split_once('(')) {
+            Some((name, value)) => (Some(name), value.strip_suffix(')').unwrap_or(value)),
+            None => (None, x)
+        })
+        .collect()
+}
+
+// value of a parameter evaluated in a scope, or its text if it cannot be evaluated
+fn eval_value(
+    value: &Option<String>,
+    params: &[ParamDef]
+) -> Option<String> {
+    let scope = eval::Scope { params, typedefs: &HashMap::new() };
+    value.as_ref().map(|x| eval::eval(x, scope).map_or_else(|| x.clone(), |x| x.to_string()))
+}
+
+// parameter values given by a file (--param-file), by the hierarchical path of an
+// instance or the name of a module, and then by the name of the parameter
+pub type ScopedOverrides = HashMap<String, HashMap<String, String>>;
+
+// parameter values of a module instance: the overrides are evaluated in the scope
+// of the parent, and then all the values in the scope of the module; `overrides`
+// replace the default values as -G does, and `scoped` replace the overrides of the
+// instance as defparam does; the local parameters are not overridden, and an ordered
+// assignment is bound to the parameters which are not local
+pub fn effective_params(
+    def: &ModuleDef,
+    inst: Option<&InstDef>,
+    parent: &[ParamDef],
+    overrides: &HashMap<String, String>,
+    scoped: &[&HashMap<String, String>]
+) -> Vec<ParamDef> {
+    let items = inst.and_then(|x| x.params.as_deref()).map(override_items).unwrap_or_default();
+    let mut index = 0;
+    let params: Vec<ParamDef> = def.params.iter().map(|param| {
+        if param.is_local {
+            return ParamDef { name: param.name.clone(), value: param.value.clone(), description: None, is_local: true };
+        }
+        let i = index;
+        index += 1;
+        if let Some(x) = scoped.iter().find_map(|x| x.get(&param.name)) {
+            return ParamDef { name: param.name.clone(), value: Some(x.clone()), description: None, is_local: false };
+        }
+        let value = items.iter()
+            .filter(|x| x.0.is_some_and(|x| x == param.name))
+            .chain(items.get(i).filter(|x| x.0.is_none()))
+            .next()
+            .filter(|x| !x.1.is_empty())
+            .map_or_else(|| overrides.get(&param.name).cloned().or_else(|| param.value.clone()), |x| eval_value(&Some(String::from(x.1)), parent));
+        ParamDef { name: param.name.clone(), value, description: None, is_local: false }
+    }).collect();
+    params.iter()
+        .map(|x| ParamDef { name: x.name.clone(), value: eval_value(&x.value, &params), description: None, is_local: x.is_local })
+        .collect()
+}
+
+// width of a port with the parameter values of an instance
+pub fn port_width(
+    port: &PortDef,
+    params: &[ParamDef]
+) -> i32 {
+    let typedefs = HashMap::new();
+    let scope = eval::Scope { params, typedefs: &typedefs };
+    if let Some(width) = port.type_expr.as_ref().and_then(|x| eval::eval_type(x, scope)) {
+        return width;
+    }
+    match &port.range_expr {
+        Some((msb, lsb)) => eval::range_width(eval::eval_range(msb, lsb, scope)),
+        None => port.width
+    }
+}
+
+// definitions, parameter overrides, and blackboxes used in an elaboration, and the
+// modules being elaborated on the way; with `eval_generates`, the instances in the
+// generate branches whose conditions are false are left out
+struct Elaboration<'a, 'b> {
+    defs: &'b [&'a ModuleDef],
+    overrides: &'b HashMap<String, String>,
+    scoped: &'b ScopedOverrides,
+    blackboxes: &'b HashSet<String>,
+    eval_generates: bool,
+    stack: Vec<&'a str>,
+    cycles: &'b mut Vec<Vec<String>>
+}
+
+fn elaborate_inst<'a>(
+    e: &mut Elaboration<'a, '_>,
+    def: Option<&'a ModuleDef>,
+    inst: Option<&'a InstDef>,
+    path: String,
+    parent: &[ParamDef]
+) -> HierNode<'a> {
+    let mut children = vec![];
+    let name = inst.map(|x| &*x.mod_name).or(def.map(|x| &*x.name));
+    // the values for the path take precedence over those for the module
+    let scoped: Vec<&HashMap<String, String>> = [Some(path.as_str()), name].iter()
+        .filter_map(|x| e.scoped.get((*x)?))
+        .collect();
+    let params = def.map(|x| effective_params(x, inst, parent, e.overrides, &scoped)).unwrap_or_default();
+    let blackbox = name.is_some_and(|x| e.blackboxes.contains(x));
+    if let Some(def) = def.filter(|_| !blackbox) {
+        // do not descend into a module that is already being elaborated
+        if let Some(pos) = e.stack.iter().position(|&x| x == &*def.name) {
+            let mut cycle: Vec<String> = e.stack[pos..].iter().map(|&x| String::from(x)).collect();
+            cycle.push(String::from(&*def.name));
+            if !e.cycles.contains(&cycle) {
+                e.cycles.push(cycle);
+            }
+            return HierNode { path, def: Some(def), inst, params, blackbox, children };
+        }
+        e.stack.push(&def.name);
+        for x in &def.insts {
+            // an instance in a generate-for loop is elaborated in each iteration, with
+            // the value of the genvar as a parameter of the parent
+            for (scope, genvars) in expand_scope(def, &x.scope, &params) {
+                let params: Vec<ParamDef> = params.iter().cloned().chain(genvars).collect();
+                if e.eval_generates && x.cond.as_ref().and_then(|x| eval_value(&Some(x.clone()), &params)).is_some_and(|x| x == "0") {
+                    continue;
+                }
+                let child_path = format!("{}.{}{}", path, scope, x.inst_name);
+                children.push(elaborate_inst(e, e.defs.iter().find(|y| y.symbol == x.mod_symbol).copied(), Some(x), child_path, &params));
+            }
+        }
+        e.stack.pop();
+    }
+    HierNode { path, def, inst, params, blackbox, children }
+}
+
+// names of the generate scopes of an instance (e.g. "gen_lanes[3].") with the
+// values of the genvars in them; a loop whose iterations cannot be evaluated is
+// named with its genvar as the index
+fn expand_scope(
+    def: &ModuleDef,
+    scope: &[GenScope],
+    params: &[ParamDef]
+) -> Vec<(String, Vec<ParamDef>)> {
+    let mut ret = vec![(String::new(), vec![])];
+    for x in scope {
+        let Some(genvar) = &x.genvar else {
+            ret = ret.into_iter().map(|(prefix, genvars)| (format!("{}{}.", prefix, x.label), genvars)).collect();
+            continue;
+        };
+        let header = def.generates.iter().find(|y| y.label.as_ref() == Some(&x.label) && y.genvar == *genvar);
+        let mut next = vec![];
+        for (prefix, genvars) in ret {
+            let outer: Vec<ParamDef> = params.iter().cloned().chain(genvars.iter().cloned()).collect();
+            let scope = eval::Scope { params: &outer, typedefs: &HashMap::new() };
+            match header.and_then(|y| eval::loop_values(&y.genvar, &y.init, &y.cond, &y.step, scope)) {
+                Some(values) => {
+                    for value in values {
+                        let mut genvars = genvars.clone();
+                        genvars.push(ParamDef { name: genvar.clone(), value: Some(value.to_string()), description: None, is_local: true });
+                        next.push((format!("{}{}[{}].", prefix, x.label, value), genvars));
+                    }
+                }
+                None => next.push((format!("{}{}[{}].", prefix, x.label, genvar), genvars))
+            }
+        }
+        ret = next;
+    }
+    ret
+}
+
+// build the instance tree under the top module with the parameter overrides of
+// a configuration and of a file if any; instantiation cycles found on the way are
+// stored in `cycles`
+pub fn elaborate<'a>(
+    defs: &[&'a ModuleDef],
+    top: &str,
+    overrides: &HashMap<String, String>,
+    scoped: &ScopedOverrides,
+    blackboxes: &HashSet<String>,
+    eval_generates: bool,
+    cycles: &mut Vec<Vec<String>>
+) -> Option<HierNode<'a>> {
+    let def = find_def(defs, top)?;
+    let mut e = Elaboration { defs, overrides, scoped, blackboxes, eval_generates, stack: vec![], cycles };
+    Some(elaborate_inst(&mut e, Some(def), None, String::from(top), &[]))
+}
+
+// effective parameter values and port widths of an instance
+fn print_params(
+    node: &HierNode,
+    indent: &str
+) {
+    if let Some(def) = node.def {
+        print_resolved(def, &node.params, indent);
+    }
+}
+
+// parameter values and the port widths resolved with them
+fn print_resolved(
+    def: &ModuleDef,
+    params: &[ParamDef],
+    indent: &str
+) {
+    if !params.is_empty() {
+        outln!("{}params:", indent);
+    }
+    for param in params {
+        outln!("{}  - param_name: {}", indent, escape_str(&param.name));
+        if let Some(value) = &param.value {
+            outln!("{}    param_value: {}", indent, escape_str(value));
+        }
+    }
+    if !def.ports.is_empty() {
+        outln!("{}ports:", indent);
+    }
+    for port in &def.ports {
+        outln!("{}  - port_name: {}", indent, escape_str(&port.name));
+        outln!("{}    port_width: {}", indent, port_width(port, params));
+    }
+}
+
+// parameter values and port widths of each module with the parameter overrides of
+// a configuration
+pub fn print_config_defs(
+    defs: &[&ModuleDef],
+    overrides: &HashMap<String, String>
+) {
+    for def in defs {
+        outln!("      - mod_name: {}", escape_str(&def.name));
+        print_resolved(def, &effective_params(def, None, &[], overrides, &[]), "        ");
+    }
+}
+
+pub fn print_hierarchy(
+    node: &HierNode,
+    depth: usize,
+    show_params: bool
+) {
+    let indent = "  ".repeat(depth);
+    outln!("{}- inst_path: {}", indent, escape_str(&node.path));
+    outln!("{}  mod_name: {}", indent, escape_str(node.mod_name()));
+    if node.blackbox {
+        outln!("{}  blackbox: true", indent);
+    }
+    if show_params {
+        print_params(node, &format!("{}  ", indent));
+    }
+    if node.children.is_empty() {
+        outln!("{}  insts: []", indent);
+    } else {
+        outln!("{}  insts:", indent);
+    }
+    for child in &node.children {
+        print_hierarchy(child, depth + 2, show_params);
+    }
+}
+
+// module name followed by the parameters whose values differ from the defaults, as
+// synthesis tools name the uniquified modules (e.g. fifo__DEPTH16_W32)
+fn uniq_name(
+    node: &HierNode
+) -> String {
+    let Some(def) = node.def.filter(|_| !node.blackbox) else { return String::from(node.mod_name()); };
+    let defaults = effective_params(def, None, &[], &HashMap::new(), &[]);
+    let parts: Vec<String> = node.params.iter().zip(&defaults)
+        .filter(|(x, y)| x.value != y.value)
+        .map(|(x, _)| {
+            let value = x.value.as_deref().unwrap_or_default().trim_matches('"');
+            let value: String = value.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+            format!("{}{}", x.name, value)
+        })
+        .collect();
+    if parts.is_empty() {
+        String::from(&*def.name)
+    } else {
+        format!("{}__{}", def.name, parts.join("_"))
+    }
+}
+
+pub fn print_flat_paths(
+    node: &HierNode,
+    show_params: bool,
+    uniquify: bool
+) {
+    outln!("  - inst_path: {}", escape_str(&node.path));
+    outln!("    mod_name: {}", escape_str(node.mod_name()));
+    if uniquify {
+        outln!("    uniq_name: {}", escape_str(&uniq_name(node)));
+    }
+    if node.blackbox {
+        outln!("    blackbox: true");
+    }
+    // the location of the instantiation, or the definition for the top module
+    let location = match (node.inst, node.def) {
+        (Some(inst), _) => Some((&inst.file, inst.line)),
+        (None, Some(def)) => Some((&def.file, def.line)),
+        _ => None
+    };
+    if let Some((file, line)) = location {
+        outln!("    file_name: {}", escape_str(file.to_str().unwrap()));
+        outln!("    line: {}", line);
+    }
+    if show_params {
+        print_params(node, "    ");
+    }
+    for child in &node.children {
+        print_flat_paths(child, show_params, uniquify);
+    }
+}
+
+// number of instances of a module and its distinct sets of effective parameter
+// values, each named as --flatten-uniquify does
+struct InstCount<'a> {
+    mod_name: &'a str,
+    count: usize,
+    params: HashSet<String>
+}
+
+fn count_insts<'a>(
+    node: &HierNode<'a>,
+    counts: &mut Vec<InstCount<'a>>
+) {
+    let mod_name = node.mod_name();
+    let params = uniq_name(node);
+    match counts.iter_mut().find(|x| x.mod_name == mod_name) {
+        Some(x) => {
+            x.count += 1;
+            x.params.insert(params);
+        }
+        None => counts.push(InstCount { mod_name, count: 1, params: HashSet::from([params]) })
+    }
+    for child in &node.children {
+        count_insts(child, counts);
+    }
+}
+
+pub fn print_inst_counts(
+    node: &HierNode
+) {
+    let mut counts = vec![];
+    count_insts(node, &mut counts);
+    for x in &counts {
+        outln!("  - mod_name: {}", escape_str(x.mod_name));
+        outln!("    count: {}", x.count);
+        outln!("    param_sets: {}", x.params.len());
+    }
+}
+
+// kind of a leaf of the hierarchy: a blackbox, a cell of the Liberty files, a
+// module without instances, or a module which is not defined
+fn leaf_kind(
+    node: &HierNode
+) -> &'static str {
+    if node.blackbox {
+        "blackbox"
+    } else if node.inst.is_some_and(|x| x.kind == InstKind::Cell) {
+        "cell"
+    } else if node.def.is_some() {
+        "module"
+    } else {
+        "unknown"
+    }
+}
+
+fn count_leaves<'a>(
+    node: &HierNode<'a>,
+    counts: &mut Vec<(&'a str, &'static str, usize)>
+) {
+    if node.children.is_empty() {
+        let mod_name = node.mod_name();
+        match counts.iter_mut().find(|x| x.0 == mod_name) {
+            Some(x) => x.2 += 1,
+            None => counts.push((mod_name, leaf_kind(node), 1))
+        }
+    }
+    for child in &node.children {
+        count_leaves(child, counts);
+    }
+}
+
+// warn on the instances deeper than `max` levels below the top; only the first
+// instance beyond the limit is reported on each path
+pub fn check_depth(
+    node: &HierNode,
+    depth: usize,
+    max: usize
+) {
+    if depth > max {
+        let inst = node.inst.map(|x| format!("{:?}:{}: ", x.file, x.line)).unwrap_or_default();
+        warn!("{}{} ({}) is {} levels below the top (more than {})", inst, node.path, node.mod_name(), depth, max);
+        return;
+    }
+    for child in &node.children {
+        check_depth(child, depth + 1, max);
+    }
+}
+
+// histogram of the leaf modules and cells, from the most instantiated one
+pub fn print_leaf_cells(
+    node: &HierNode
+) {
+    let mut counts = vec![];
+    count_leaves(node, &mut counts);
+    counts.sort_by(|x, y| y.2.cmp(&x.2).then(x.0.cmp(y.0)));
+    for (mod_name, kind, count) in &counts {
+        outln!("  - mod_name: {}", escape_str(mod_name));
+        outln!("    kind: {}", escape_str(kind));
+        outln!("    count: {}", count);
+    }
+}
+
+// the hierarchy under the top module and what is desired of it; false if the top
+// module is not found or is in an instantiation cycle
+pub fn print_top(
+    defs: &[&ModuleDef],
+    top: &str,
+    scoped: &ScopedOverrides,
+    blackboxes: &HashSet<String>,
+    opt: &Opt
+) -> bool {
+    let mut ok = true;
+    let mut cycles = vec![];
+    match elaborate(defs, top, &HashMap::new(), scoped, blackboxes, opt.eval_generates, &mut cycles) {
+        Some(root) => {
+            if opt.flat_paths || opt.flatten_uniquify {
+                outln!("flat_paths:");
+                print_flat_paths(&root, opt.inst_params, opt.flatten_uniquify);
+            } else {
+                outln!("hierarchy:");
+                print_hierarchy(&root, 1, opt.inst_params);
+            }
+            if opt.inst_counts {
+                outln!("inst_counts:");
+                print_inst_counts(&root);
+            }
+            if opt.leaf_cells {
+                outln!("leaf_cells:");
+                print_leaf_cells(&root);
+            }
+            if let Some(max) = opt.max_hier_depth {
+                check_depth(&root, 0, max);
+            }
+            if let Some(def) = root.def.filter(|_| opt.pinout) {
+                outln!("pinout:");
+                pinout::print_pinout(def, opt.port_clocks);
+            }
+        }
+        None => {
+            error!("top module not found: {}", top);
+            ok = false;
+        }
+    }
+    for cycle in &cycles {
+        error!("instantiation cycle: {}", cycle.join(" -> "));
+        ok = false;
+    }
+    ok
+}
+
+// the port widths and the hierarchy resolved for each configuration of --config
+pub fn print_configs(
+    defs: &[&ModuleDef],
+    configs: &[(String, HashMap<String, String>)],
+    scoped: &ScopedOverrides,
+    blackboxes: &HashSet<String>,
+    opt: &Opt
+) {
+    outln!("configs:");
+    for (name, overrides) in configs {
+        outln!("  - config_name: {}", escape_str(name));
+        let mut names: Vec<&String> = overrides.keys().collect();
+        names.sort();
+        outln!("    overrides:");
+        for x in names {
+            outln!("      - param_name: {}", escape_str(x));
+            outln!("        param_value: {}", escape_str(&overrides[x]));
+        }
+        outln!("    defs:");
+        print_config_defs(defs, overrides);
+        let root = opt.top.as_ref().and_then(|x| elaborate(defs, x, overrides, scoped, blackboxes, opt.eval_generates, &mut vec![]));
+        if let Some(root) = root {
+            outln!("    hierarchy:");
+            print_hierarchy(&root, 3, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use crate::analyze_text;
+    use super::*;
+
+    const LOCAL_BETWEEN: &str = "
+        module m #(parameter W = 4, localparam X = W * 2, parameter Y = 7) ();
+            parameter P = 1;
+        endmodule
+        module top;
+            m #(5, 9) u_ordered ();
+            m #(.X(3), .Y(1)) u_named ();
+            m u_default ();
+        endmodule
+    ";
+
+    fn param(node: &HierNode, name: &str) -> String {
+        node.params.iter().find(|x| x.name == name).and_then(|x| x.value.clone()).unwrap()
+    }
+
+    fn elaborate_top(
+        overrides: &[(&str, &str)],
+        scoped: &ScopedOverrides
+    ) -> Vec<(String, String, String, String)> {
+        let overrides: HashMap<String, String> = overrides.iter().map(|(x, y)| (String::from(*x), String::from(*y))).collect();
+        let defs = analyze_text(LOCAL_BETWEEN, &overrides);
+        let defs: Vec<&ModuleDef> = defs.iter().collect();
+        let root = elaborate(&defs, "top", &overrides, scoped, &HashSet::new(), false, &mut vec![]).unwrap();
+        root.children.iter()
+            .map(|x| (param(x, "W"), param(x, "X"), param(x, "Y"), param(x, "P")))
+            .collect()
+    }
+
+    fn values(w: &str, x: &str, y: &str, p: &str) -> (String, String, String, String) {
+        (String::from(w), String::from(x), String::from(y), String::from(p))
+    }
+
+    #[test]
+    fn localparam_not_overridden_by_instance() {
+        let insts = elaborate_top(&[], &ScopedOverrides::new());
+        // the ordered assignment skips the localparam
+        assert_eq!(insts[0], values("5", "10", "9", "1"));
+        assert_eq!(insts[1], values("4", "8", "1", "1"));
+        assert_eq!(insts[2], values("4", "8", "7", "1"));
+    }
+
+    #[test]
+    fn localparam_not_overridden_globally() {
+        let mut scoped = ScopedOverrides::new();
+        scoped.insert(String::from("top.u_default"), HashMap::from([(String::from("X"), String::from("6"))]));
+        let insts = elaborate_top(&[("X", "100"), ("Y", "2"), ("P", "3")], &scoped);
+        assert_eq!(insts[0], values("5", "10", "9", "1"));
+        assert_eq!(insts[2], values("4", "8", "2", "1"));
+    }
+
+    const CYCLES: &str = "
+        module cyc_a; cyc_b u_b (); endmodule
+        module cyc_b; cyc_a u_a (); endmodule
+        module self_inst; self_inst u_self (); endmodule
+        module gen_a;
+            for (genvar i = 0; i < 2; i++) begin : gen_lanes
+                gen_b u_b ();
+            end
+        endmodule
+        module gen_b;
+            if (1) begin : gen_loop
+                gen_a u_a ();
+            end
+        endmodule
+    ";
+
+    fn cycles_from(
+        top: &str
+    ) -> (usize, Vec<Vec<String>>) {
+        let defs = analyze_text(CYCLES, &HashMap::new());
+        let defs: Vec<&ModuleDef> = defs.iter().collect();
+        let mut cycles = vec![];
+        let root = elaborate(&defs, top, &HashMap::new(), &ScopedOverrides::new(), &HashSet::new(), false, &mut cycles).unwrap();
+        (root.children.len(), cycles)
+    }
+
+    fn cycle(names: &[&str]) -> Vec<String> {
+        names.iter().map(|x| String::from(*x)).collect()
+    }
+
+    #[test]
+    fn instantiation_cycles_are_found() {
+        assert_eq!(cycles_from("cyc_a"), (1, vec![cycle(&["cyc_a", "cyc_b", "cyc_a"])]));
+        assert_eq!(cycles_from("cyc_b"), (1, vec![cycle(&["cyc_b", "cyc_a", "cyc_b"])]));
+        assert_eq!(cycles_from("self_inst"), (1, vec![cycle(&["self_inst", "self_inst"])]));
+    }
+
+    #[test]
+    fn cycles_through_generate_blocks_are_found() {
+        // each iteration of the loop stops at the cycle, which is reported once
+        assert_eq!(cycles_from("gen_a"), (2, vec![cycle(&["gen_a", "gen_b", "gen_a"])]));
+    }
+}
@@ -0,0 +1,147 @@
+use std::fs::{create_dir_all, read, write};
+use std::path::{Path, PathBuf};
+use crate::scan::KEYWORDS;
+use crate::{archive, FileResult};
+
+const STYLE: &str = "\
+body { font-family: monospace; }
+pre { line-height: 1.3; }
+.ln { color: #999; user-select: none; }
+.kw { color: #00f; font-weight: bold; }
+.num { color: #098658; }
+.str { color: #a31515; }
+.cmt { color: #008000; font-style: italic; }
+.dir { color: #af00db; }
+.sys { color: #795e26; }
+:target { background: #ff0; }
+";
+
+// identifier to be given an anchor; the first occurrence at or after the line is used
+struct Anchor {
+    line: usize,
+    name: String,
+    id: String,
+    done: bool
+}
+
+fn escape_html(
+    text: &str
+) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// name of the HTML file of a source file, which is unique among the input files
+fn html_name(
+    path: &Path
+) -> String {
+    let name: String = path.to_string_lossy().chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect();
+    format!("{}.html", name)
+}
+
+// length and class of the token at the beginning of the text
+fn next_token(
+    b: &[u8]
+) -> (usize, Option<&'static str>) {
+    let is_ident = |c: u8| c.is_ascii_alphanumeric() || c == b'_' || c == b'$';
+    let find = |from: usize, pred: &dyn Fn(u8) -> bool| b[from..].iter().position(|&c| pred(c)).map_or(b.len(), |x| x + from);
+    match b {
+        [b'/', b'/', ..] => (find(2, &|c| c == b'\n'), Some("cmt")),
+        [b'/', b'*', ..] => (b.windows(2).skip(2).position(|x| x == b"*/").map_or(b.len(), |x| x + 4), Some("cmt")),
+        [b'"', ..] => {
+            let mut i = 1;
+            while i < b.len() && b[i] != b'"' && b[i] != b'\n' {
+                i += if b[i] == b'\\' { 2 } else { 1 };
+            }
+            ((i + 1).min(b.len()), Some("str"))
+        }
+        [b'`', c, ..] if is_ident(*c) => (find(1, &|c| !is_ident(c)), Some("dir")),
+        [b'$', c, ..] if is_ident(*c) => (find(1, &|c| !is_ident(c)), Some("sys")),
+        [b'\\', ..] => (find(1, &|c| c.is_ascii_whitespace()), None),
+        [c, ..] if c.is_ascii_digit() => (find(1, &|c| !(is_ident(c) || c == b'\'' || c == b'.' || c == b'?')), Some("num")),
+        [b'\'', c, ..] if c.is_ascii_alphanumeric() => (find(1, &|c| !(is_ident(c) || c == b'?')), Some("num")),
+        [c, ..] if is_ident(*c) => (find(1, &|c| !is_ident(c)), None),
+        _ => (1, None)
+    }
+}
+
+// render a source file with line numbers, highlighting, and anchors
+fn render(
+    path: &Path,
+    text: &str,
+    anchors: &mut [Anchor]
+) -> String {
+    let mut body = String::new();
+    let mut line = 1;
+    let line_number = |line: usize| format!("<span class=\"ln\" id=\"L{}\">{:5}</span> ", line, line);
+    body.push_str(&line_number(line));
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (len, class) = next_token(rest.as_bytes());
+        // keep the token on a character boundary
+        let len = (len..=rest.len()).find(|&x| rest.is_char_boundary(x)).unwrap_or(rest.len());
+        let (token, next) = rest.split_at(len);
+        rest = next;
+        let is_word = token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '\\');
+        let class = match class {
+            None if is_word && KEYWORDS.contains(&token) => Some("kw"),
+            x => x
+        };
+        let anchor = if is_word && class.is_none() {
+            anchors.iter_mut().find(|x| !x.done && x.line <= line && x.name == token)
+        } else {
+            None
+        };
+        if let Some(anchor) = anchor {
+            anchor.done = true;
+            body.push_str(&format!("<a id=\"{}\">{}</a>", escape_html(&anchor.id), escape_html(token)));
+            continue;
+        }
+        // a token over multiple lines is split so that each line has its number
+        for (i, part) in token.split('\n').enumerate() {
+            if i > 0 {
+                line += 1;
+                body.push('\n');
+                body.push_str(&line_number(line));
+            }
+            match class {
+                Some(class) if !part.is_empty() => body.push_str(&format!("<span class=\"{}\">{}</span>", class, escape_html(part))),
+                _ => body.push_str(&escape_html(part))
+            }
+        }
+    }
+    let title = escape_html(&path.to_string_lossy());
+    format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n<h1>{}</h1>\n<pre>{}</pre>\n</body>\n</html>\n",
+        title, STYLE, title, body)
+}
+
+// write the HTML file of each input file and an index of them into the directory
+pub fn write_html(
+    dir: &Path,
+    results: &[FileResult]
+) -> std::io::Result<()> {
+    create_dir_all(dir)?;
+    let mut index = String::new();
+    for result in results {
+        let mut anchors = vec![];
+        for def in result.defs.iter().filter(|x| *x.file == *result.path) {
+            anchors.push(Anchor { line: def.line, name: String::from(&*def.name), id: format!("mod-{}", def.name), done: false });
+            for port in &def.ports {
+                anchors.push(Anchor { line: def.line, name: String::from(&*port.name), id: format!("port-{}-{}", def.name, port.name), done: false });
+            }
+            for inst in def.insts.iter().filter(|x| *x.file == *result.path) {
+                anchors.push(Anchor { line: inst.line, name: String::from(&*inst.inst_name), id: format!("inst-{}-{}", def.name, inst.inst_name), done: false });
+            }
+        }
+        let text = String::from_utf8_lossy(&read(archive::resolve(&result.path))?).into_owned();
+        let name = html_name(&result.path);
+        write(PathBuf::from(dir).join(&name), render(&result.path, &text, &mut anchors))?;
+        index.push_str(&format!("<li><a href=\"{}\">{}</a>\n<ul>\n", escape_html(&name), escape_html(&result.path.to_string_lossy())));
+        for def in result.defs.iter().filter(|x| *x.file == *result.path) {
+            index.push_str(&format!("<li><a href=\"{}#mod-{}\">{}</a></li>\n", escape_html(&name), escape_html(&def.name), escape_html(&def.name)));
+        }
+        index.push_str("</ul>\n</li>\n");
+    }
+    write(PathBuf::from(dir).join("index.html"), format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Sources</title>\n</head>\n<body>\n<ul>\n{}</ul>\n</body>\n</html>\n", index))
+}
@@ -0,0 +1,94 @@
+// Interned identifiers and file paths of the model, stored once in the table of an
+// `Interner` owned by the caller; each identifier has a `Symbol` to compare it by.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+pub type Name = Arc<str>;
+
+// id of an identifier in the interner which interned it, numbered in the order of
+// interning
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+pub struct Interner {
+    names: Mutex<Names>,
+    paths: Mutex<HashSet<Arc<Path>>>
+}
+
+#[derive(Default)]
+struct Names {
+    symbols: HashMap<Name, Symbol>,
+    names: Vec<Name>
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    pub fn name(
+        &self,
+        text: &str
+    ) -> Name {
+        self.intern(text).0
+    }
+
+    // identifier and its symbol
+    pub fn intern(
+        &self,
+        text: &str
+    ) -> (Name, Symbol) {
+        let mut names = self.names.lock().unwrap_or_else(|x| x.into_inner());
+        if let Some((x, &symbol)) = names.symbols.get_key_value(text) {
+            return (x.clone(), symbol);
+        }
+        let ret: Name = Arc::from(text);
+        let symbol = Symbol(names.names.len() as u32);
+        names.symbols.insert(ret.clone(), symbol);
+        names.names.push(ret.clone());
+        (ret, symbol)
+    }
+
+    pub fn path(
+        &self,
+        path: &Path
+    ) -> Arc<Path> {
+        let mut paths = self.paths.lock().unwrap_or_else(|x| x.into_inner());
+        if let Some(x) = paths.get(path) {
+            return x.clone();
+        }
+        let ret: Arc<Path> = Arc::from(path);
+        paths.insert(ret.clone());
+        ret
+    }
+
+    // symbol of an identifier, if it has been interned
+    pub fn symbol(
+        &self,
+        text: &str
+    ) -> Option<Symbol> {
+        let names = self.names.lock().unwrap_or_else(|x| x.into_inner());
+        names.symbols.get(text).copied()
+    }
+
+    // identifier of a symbol, if it has been given by this interner
+    pub fn lookup(
+        &self,
+        symbol: Symbol
+    ) -> Option<Name> {
+        let names = self.names.lock().unwrap_or_else(|x| x.into_inner());
+        names.names.get(symbol.0 as usize).cloned()
+    }
+
+    // number of the interned identifiers
+    pub fn len(&self) -> usize {
+        self.names.lock().unwrap_or_else(|x| x.into_inner()).names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,3607 @@
+//! Analysis of the modules and their ports in Verilog/SystemVerilog files. The
+//! command line tool is a thin wrapper of `run`; other tools can analyze a file
+//! with `analyze_file` and read the definitions from the returned `FileResult`.
+//! The names of the definitions, ports, and instances are interned by an
+//! `Interner` owned by the caller, in which each of them has a `Symbol`.
+
+// deep enough to check that the syntax tree can be sent to another thread (--jobs)
+#![recursion_limit = "256"]
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, canonicalize, create_dir_all, read, read_dir, read_to_string};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::{cmp, process};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use structopt::StructOpt;
+use sv_parser::{parse_sv, parse_sv_pp, parse_sv_str, preprocess, preprocess_str, PreprocessedText, SyntaxTree, unwrap_node, Locate, RefNode, Define, DefineText};
+use sv_parser_syntaxtree::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use doc::DocComment;
+use tempfile::NamedTempFile;
+use tracing::{debug, error, warn};
+pub use sv_parser::{Defines, Error};
+pub use intern::{Interner, Name, Symbol};
+
+// the output is written to the standard output, or captured in a buffer of the
+// thread while one is set (e.g. for the response of --stdin-batch)
+thread_local! {
+    static CAPTURE: RefCell<Option<String>> = const { RefCell::new(None) };
+    // command line of the run in progress, shown in the provenance header (--meta)
+    static COMMAND_LINE: RefCell<Vec<String>> = const { RefCell::new(vec![]) };
+}
+
+fn write_out(
+    text: &str
+) {
+    CAPTURE.with(|x| match x.borrow_mut().as_mut() {
+        Some(buf) => buf.push_str(text),
+        None => print!("{}", text)
+    });
+}
+
+macro_rules! out {
+    ($($arg:tt)*) => { crate::write_out(&format!($($arg)*)) }
+}
+
+macro_rules! outln {
+    () => { crate::write_out("\n") };
+    ($($arg:tt)*) => { crate::write_out(&format!("{}\n", format_args!($($arg)*))) }
+}
+
+mod bench;
+mod archive;
+mod bus;
+mod check;
+mod conn;
+mod directive;
+mod doc;
+mod eval;
+mod filelist;
+mod findinst;
+mod flatten;
+mod fmt;
+mod hier;
+mod html;
+mod intern;
+mod liberty;
+mod logging;
+mod manifest;
+mod netlistsvg;
+mod obfuscate;
+mod parallel;
+mod pinout;
+mod project;
+mod reduce;
+mod rename;
+mod report;
+mod scan;
+mod schema;
+mod source;
+mod special;
+mod split;
+mod strip;
+mod tokens;
+mod unit;
+
+#[derive(StructOpt)]
+struct Opt {
+    pub files: Vec<PathBuf>,
+
+    /// Define
+    #[structopt(short = "d", long = "define", multiple = true, number_of_values = 1)]
+    pub defines: Vec<String>,
+
+    /// Include path
+    #[structopt(short = "i", long = "include", multiple = true, number_of_values = 1)]
+    pub includes: Vec<PathBuf>,
+
+    /// Parameter override (NAME=VALUE), applied to the parameter of every module
+    #[structopt(short = "G", multiple = true, number_of_values = 1)]
+    pub overrides: Vec<String>,
+
+    /// YAML or JSON file of parameter values for the elaboration, keyed by the hierarchical paths of the instances or the names of the modules and the parameter names (e.g. top.u_core.WIDTH: 64)
+    #[structopt(long = "param-file")]
+    pub param_file: Option<PathBuf>,
+
+    /// Parameter override of a configuration (NAME:PARAM=VALUE); the modules and the hierarchy are reported for each configuration
+    #[structopt(long = "config", multiple = true, number_of_values = 1)]
+    pub configs: Vec<String>,
+
+    /// File of the names of the modules treated as blackboxes, one per line; the hierarchy does not descend into them and they are not reported as undefined
+    #[structopt(long = "blackbox")]
+    pub blackbox: Option<PathBuf>,
+
+    /// Liberty file whose cells are instantiated; the undefined modules which are not the cells are reported
+    #[structopt(long = "liberty", multiple = true, number_of_values = 1)]
+    pub liberty: Vec<PathBuf>,
+
+    /// Library file, parsed together with the other files
+    #[structopt(short = "v", multiple = true, number_of_values = 1)]
+    pub lib_files: Vec<PathBuf>,
+
+    /// Library directory searched for the modules not defined in the files, whose files found are library files
+    #[structopt(short = "y", multiple = true, number_of_values = 1)]
+    pub lib_dirs: Vec<PathBuf>,
+
+    /// Extension of the files in the library directories (.v and .sv by default)
+    #[structopt(long = "libext", multiple = true, number_of_values = 1)]
+    pub libexts: Vec<String>,
+
+    /// Report all the modules of the library files rather than those instantiated by the other files
+    #[structopt(long = "all-lib-defs")]
+    pub all_lib_defs: bool,
+
+    /// Filelist whose paths are relative to the current directory
+    #[structopt(short = "f", multiple = true, number_of_values = 1)]
+    pub filelists: Vec<PathBuf>,
+
+    /// Filelist whose paths are relative to the filelist itself
+    #[structopt(short = "F", multiple = true, number_of_values = 1)]
+    pub rel_filelists: Vec<PathBuf>,
+
+    /// Ignore any include
+    #[structopt(long = "ignore-include")]
+    pub ignore_include: bool,
+
+    /// Show the full syntax tree rather than just module instantiation
+    #[structopt(long = "full-tree")]
+    pub full_tree: bool,
+
+    /// Show the definitions as well as the full syntax tree of --full-tree
+    #[structopt(long = "defs")]
+    pub defs: bool,
+
+    /// Include whitespace in output syntax tree
+    #[structopt(long = "include-whitespace")]
+    pub include_whitespace: bool,
+
+    /// Show at most the given number of modules, counted across the files in the order of the output
+    #[structopt(long = "limit")]
+    pub limit: Option<usize>,
+
+    /// Skip the given number of modules before those shown, counted in the same order as --limit
+    #[structopt(long = "offset", default_value = "0")]
+    pub offset: usize,
+
+    /// Show only the given number of levels of the full syntax tree
+    #[structopt(long = "max-depth")]
+    pub max_depth: Option<usize>,
+
+    /// Show only the subtrees of the given node kinds in the full syntax tree
+    #[structopt(long = "tree-filter", use_delimiter = true, require_delimiter = true)]
+    pub tree_filter: Vec<String>,
+
+    /// Show the tokens of each file after preprocessing instead of parsing it
+    #[structopt(long = "tokens")]
+    pub tokens: bool,
+
+    /// Format of the full syntax tree
+    #[structopt(long = "tree-format", possible_values = &["yaml", "json"], default_value = "yaml")]
+    pub tree_format: String,
+
+    /// Format of the output: netlistsvg prints the modules and the connections of their instances as JSON for netlistsvg
+    #[structopt(long = "format", possible_values = &["yaml", "netlistsvg"], default_value = "yaml")]
+    pub format: String,
+ 
+    /// Show the macro definitions after processing each file
+    #[structopt(long = "show-macro-defs")]
+    pub show_macro_defs: bool,
+
+    /// Treatment of non-ASCII characters: replace them with '?', strip them, keep them as UTF-8, or report an error
+    #[structopt(long = "sanitize", possible_values = &["replace", "strip", "keep", "error"], default_value = "replace")]
+    pub sanitize: String,
+
+    /// Show the macros added, removed, or changed by each file
+    #[structopt(long = "show-macro-delta")]
+    pub show_macro_delta: bool,
+
+    /// Treat each file as completely separate, not updating define variables after each file
+    #[structopt(long = "separate")]
+    pub separate: bool,
+
+    /// Allow incomplete
+    #[structopt(long = "allow_incomplete")]
+    pub allow_incomplete: bool,
+
+    /// Treat all files as a single compilation unit
+    #[structopt(long = "single-unit")]
+    pub single_unit: bool,
+
+    /// Number of threads parsing the compilation units in parallel
+    #[structopt(short = "j", long = "jobs", default_value = "1")]
+    pub jobs: usize,
+
+    /// Elaborate the hierarchy under the specified top module
+    #[structopt(long = "top")]
+    pub top: Option<String>,
+
+    /// List the hierarchy as flattened instance paths (requires --top)
+    #[structopt(long = "flat-paths")]
+    pub flat_paths: bool,
+
+    /// List the hierarchy as flattened instance paths with the module names uniquified by the parameter values (requires --top)
+    #[structopt(long = "flatten-uniquify")]
+    pub flatten_uniquify: bool,
+
+    /// Report the number of instances and parameterizations of each module in the hierarchy of --top, or the number of instantiations and parents of each module name without it
+    #[structopt(long = "inst-counts")]
+    pub inst_counts: bool,
+
+    /// Report the number of instances of each leaf module or cell in the hierarchy of --top
+    #[structopt(long = "leaf-cells")]
+    pub leaf_cells: bool,
+
+    /// Report the bus interfaces (AXI4, AXI4-Lite, AXI4-Stream, APB, Avalon-MM) of each module found by the port names
+    #[structopt(long = "buses")]
+    pub buses: bool,
+
+    /// File of additional bus patterns for --buses, in YAML or JSON
+    #[structopt(long = "bus-patterns", multiple = true, number_of_values = 1)]
+    pub bus_patterns: Vec<PathBuf>,
+
+    /// File of the name patterns of special cells such as clock gates and synchronizers, whose instances are reported per clock domain
+    #[structopt(long = "special-cells")]
+    pub special_cells: Option<PathBuf>,
+
+    /// Evaluate the conditions of generate-if and generate-case with the parameter values, reporting which instances are active and leaving out the inactive ones from the hierarchy
+    #[structopt(long = "eval-generates")]
+    pub eval_generates: bool,
+
+    /// Report the ports of the top module with their roles (requires --top)
+    #[structopt(long = "pinout")]
+    pub pinout: bool,
+
+    /// Report the effective parameter values and port widths of each instance (requires --top)
+    #[structopt(long = "inst-params")]
+    pub inst_params: bool,
+
+    /// Report the drivers and the loads of each net in the modules
+    #[structopt(long = "connectivity")]
+    pub connectivity: bool,
+
+    /// Show the clock of each port: the clock of the edge-sensitive blocks where the port is sampled or driven
+    #[structopt(long = "port-clocks")]
+    pub port_clocks: bool,
+
+    /// Report the continuous assignments of each module with the assigned net and the expression
+    #[structopt(long = "assigns")]
+    pub assigns: bool,
+
+    /// Report the input ports of the instances tied to constants such as 1'b0 and '1
+    #[structopt(long = "const-pins")]
+    pub const_pins: bool,
+
+    /// Name pattern (with * and ?) of the ports reported by --const-pins, e.g. scan_en
+    #[structopt(long = "const-pin-pattern", multiple = true, number_of_values = 1)]
+    pub const_pin_patterns: Vec<String>,
+
+    /// Report the expression connected to each port of the instances with its inferred width and the width of the port
+    #[structopt(long = "pin-widths")]
+    pub pin_widths: bool,
+
+    /// Report the fan-out of each net and the nets which are never driven or never read
+    #[structopt(long = "net-report")]
+    pub net_report: bool,
+
+    /// Layout of the definitions: port-v1 lists all in defs, and port-v2 lists modules, interfaces, programs, and packages separately
+    #[structopt(long = "schema", possible_values = &["port-v1", "port-v2"], default_value = "port-v1")]
+    pub schema: String,
+
+    /// Report the macros each file depends on, i.e. expanded or tested by `ifdef
+    #[structopt(long = "used-defines")]
+    pub used_defines: bool,
+
+    /// List the packages of each file with their parameters, type definitions, and functions
+    #[structopt(long = "package-decls")]
+    pub package_decls: bool,
+
+    /// Report the ranges where `unconnected_drive is active with the modules in them, and the `resetall directives
+    #[structopt(long = "drive-directives")]
+    pub drive_directives: bool,
+
+    /// Report the declarations outside any module, interface, program, or package ($unit) of each compilation unit
+    #[structopt(long = "unit-decls")]
+    pub unit_decls: bool,
+
+    /// Show the size, SHA-256, modification time, analysis time, encoding, and number of modules of each file
+    #[structopt(long = "file-meta")]
+    pub file_meta: bool,
+
+    /// Begin the output with a provenance header: the versions of the tool and the parser, the command line, the time, and the hash of the inputs
+    #[structopt(long = "meta")]
+    pub meta: bool,
+
+    /// Write the interface and body hashes of the modules to a JSON manifest
+    #[structopt(long = "emit-manifest")]
+    pub emit_manifest: Option<PathBuf>,
+
+    /// Report the modules changed from a manifest written by --emit-manifest
+    #[structopt(long = "baseline")]
+    pub baseline: Option<PathBuf>,
+
+    /// Write a Tcl script adding the files, include paths, defines, and top module to a Vivado project
+    #[structopt(long = "emit-vivado-tcl")]
+    pub emit_vivado_tcl: Option<PathBuf>,
+
+    /// Write the assignments of the files, search paths, macros, and top level entity for a Quartus .qsf file
+    #[structopt(long = "emit-qsf")]
+    pub emit_qsf: Option<PathBuf>,
+
+    /// Write an sv_library target of Bazel for each module with the modules instantiated in it as deps
+    #[structopt(long = "emit-bazel")]
+    pub emit_bazel: Option<PathBuf>,
+
+    /// Label of the .bzl file loaded for sv_library by --emit-bazel
+    #[structopt(long = "bazel-rules", default_value = "//tools/bazel:sv.bzl")]
+    pub bazel_rules: String,
+
+    /// Write CMake variables of the files in compile order, include paths, defines, and top modules
+    #[structopt(long = "emit-cmake")]
+    pub emit_cmake: Option<PathBuf>,
+
+    /// Write the input files with the include files inlined instead of parsing them
+    #[structopt(long = "flatten-includes")]
+    pub flatten_includes: bool,
+
+    /// Inline an include file at every occurrence rather than only at the first one
+    #[structopt(long = "flatten-every-include")]
+    pub flatten_every_include: bool,
+
+    /// Write the input files with the given constructs removed instead of parsing them
+    #[structopt(long = "strip", possible_values = &["comments", "pragmas", "attributes"], use_delimiter = true, require_delimiter = true)]
+    pub strip: Vec<String>,
+
+    /// Keep the line numbers in --strip by leaving the removed lines empty
+    #[structopt(long = "keep-lines")]
+    pub keep_lines: bool,
+
+    /// Output file of --flatten-includes and --strip (standard output by default)
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+
+    /// Show the byte offsets of the beginning and the end of each module
+    #[structopt(long = "src-spans")]
+    pub src_spans: bool,
+
+    /// Show the source text of each module together with its byte offsets
+    #[structopt(long = "emit-module-text")]
+    pub emit_module_text: bool,
+
+    /// Write the input files as HTML with syntax highlighting into the directory
+    #[structopt(long = "emit-html-src")]
+    pub emit_html_src: Option<PathBuf>,
+
+    /// Warn on files with multiple modules and/or modules not named after the file
+    #[structopt(long = "check-file-names", possible_values = &["multiple", "mismatch", "both"])]
+    pub check_file_names: Option<String>,
+
+    /// Warn on ports wider than the given number of bits
+    #[structopt(long = "max-port-width")]
+    pub max_port_width: Option<i32>,
+
+    /// Warn on modules with more than the given number of ports
+    #[structopt(long = "max-port-count")]
+    pub max_port_count: Option<usize>,
+
+    /// Warn on instances more than the given number of levels below the top module of --top
+    #[structopt(long = "max-hier-depth")]
+    pub max_hier_depth: Option<usize>,
+
+    /// Report the port and instantiation constructs that the analyzer cannot interpret as errors
+    #[structopt(long = "strict-defs")]
+    pub strict_defs: bool,
+
+    /// Only parse the files, reporting nothing but the failures
+    #[structopt(long = "check-syntax")]
+    pub check_syntax: bool,
+
+    /// Stop at the first file that fails to parse or the first error of --strict-defs
+    #[structopt(long = "fail-fast")]
+    pub fail_fast: bool,
+
+    /// Warn on modules connected by name in some instantiations and by position in others, or with different numbers of connections
+    #[structopt(long = "check-conn-styles")]
+    pub check_conn_styles: bool,
+
+    /// Library of files: NAME=PATH, where PATH is a file or a directory
+    #[structopt(long = "lib", multiple = true, number_of_values = 1)]
+    pub libs: Vec<String>,
+
+    /// Define for a library: NAME:DEFINE
+    #[structopt(long = "lib-define", multiple = true, number_of_values = 1)]
+    pub lib_defines: Vec<String>,
+
+    /// Include path for a library: NAME:PATH
+    #[structopt(long = "lib-include", multiple = true, number_of_values = 1)]
+    pub lib_includes: Vec<String>,
+
+    /// Extract the definitions with a lightweight scanner, parsing only the files it cannot handle
+    #[structopt(long = "scan-only")]
+    pub scan_only: bool,
+
+    /// Maximum nesting level of include files
+    #[structopt(long = "max-include-depth", default_value = "64")]
+    pub max_include_depth: usize,
+
+    /// Substitute an empty file for each include which cannot be resolved, and report it
+    #[structopt(long = "permissive-includes")]
+    pub permissive_includes: bool,
+
+    /// Parse each compilation unit in memory with the include files inlined through a virtual file system, instead of through a temporary file
+    #[structopt(long = "vfs")]
+    pub vfs: bool,
+
+    /// Text of a file in place of it, given as PATH=FILE (e.g. an unsaved buffer of an editor); implies --vfs
+    #[structopt(long = "overlay", multiple = true, number_of_values = 1)]
+    pub overlays: Vec<String>,
+
+    /// Read requests as JSON lines from the standard input and write a JSON response for each line
+    #[structopt(long = "stdin-batch")]
+    pub stdin_batch: bool,
+
+    /// Write the messages of each compilation unit as soon as they are issued, rather than at once per unit in the order of the input files
+    #[structopt(long = "unordered")]
+    pub unordered: bool,
+
+    /// Keyword set used unless overridden by `begin_keywords in the file
+    #[structopt(long = "std", possible_values = &["1364-2005", "1800-2012", "1800-2017"])]
+    pub std: Option<String>,
+
+    #[structopt(flatten)]
+    pub log: logging::LogOpt
+}
+
+// set by the first interrupt (Ctrl-C); the analysis stops after the compilation
+// unit in progress, and the results so far are reported
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// exit code after an interrupt, as in shells (128 + SIGINT)
+const EXIT_INTERRUPTED: i32 = 130;
+
+// run the command line tool with the arguments of the process and return the exit code
+pub fn run() -> i32 {
+    let args = expand_plusargs(std::env::args());
+    COMMAND_LINE.with(|x| *x.borrow_mut() = std::env::args().collect());
+    // subcommands are given as the first argument
+    match args.get(1).map(|x| x.as_str()) {
+        Some("bench") => bench::run(&bench::BenchOpt::from_iter(&args[1..])),
+        Some("filelist") => filelist::run(&filelist::FilelistOpt::from_iter(&args[1..])),
+        Some("find-inst") => findinst::run(&findinst::FindInstOpt::from_iter(&args[1..])),
+        Some("fmt") => fmt::run(&fmt::FmtOpt::from_iter(&args[1..])),
+        Some("reduce") => reduce::run(&reduce::ReduceOpt::from_iter(&args[1..])),
+        Some("rename") => rename::run(&rename::RenameOpt::from_iter(&args[1..])),
+        Some("schema") => schema::run(&schema::SchemaOpt::from_iter(&args[1..])),
+        Some("split") => split::run(&split::SplitOpt::from_iter(&args[1..])),
+        Some("obfuscate") => obfuscate::run(&obfuscate::ObfuscateOpt::from_iter(&args[1..])),
+        _ => run_opt(Opt::from_iter(args), HashMap::new())
+    }
+}
+
+fn run_opt(
+    mut opt: Opt,
+    mut overlays: HashMap<PathBuf, Vec<u8>>
+) -> i32 {
+    logging::init(&opt.log);
+
+    // a second interrupt terminates the process immediately
+    let _ = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            process::exit(EXIT_INTERRUPTED);
+        }
+    });
+    if opt.stdin_batch {
+        return run_batch();
+    }
+
+    // read the filelists, whose entries come before those of the command line
+    let filelists_read = filelist::merge(&mut opt);
+    // add the files of the library directories defining the undefined modules
+    if !opt.lib_dirs.is_empty() {
+        filelist::search_lib_dirs(&mut opt);
+    }
+    let opt = &opt;
+
+    // read in define variables and parameter overrides
+    let defines = read_defines(&opt.defines);
+    let overrides = read_overrides(&opt.overrides);
+    let configs = read_configs(&opt.configs);
+    
+    // flag to determine parsing status
+    let mut exit_code = if filelists_read { 0 } else { 1 };
+
+    // analysis results of all the files
+    let mut results: Vec<FileResult> = vec![];
+    // identifiers and file paths of the results, freed with them
+    let names = Interner::new();
+    let mut unit_scopes: Vec<unit::UnitScope> = vec![];
+    let mut drive_directives = directive::DriveDirectives::new(opt.separate);
+
+    // libraries and the files in them; the files given directly belong to "work"
+    let mut libraries = vec![Library::new("work", &defines, &opt.includes)];
+    let mut inputs: Vec<(PathBuf, usize)> = opt.files.iter().chain(&opt.lib_files).map(|x| (x.clone(), 0)).collect();
+    for lib in &opt.libs {
+        let Some((name, path)) = lib.split_once('=') else {
+            error!("invalid library: {}", lib);
+            exit_code = 1;
+            continue;
+        };
+        let index = find_library(&mut libraries, name, &defines, &opt.includes);
+        inputs.extend(find_sources(Path::new(path)).into_iter().map(|x| (x, index)));
+    }
+    for lib_define in &opt.lib_defines {
+        let Some((name, define)) = lib_define.split_once(':') else { continue; };
+        let index = find_library(&mut libraries, name, &defines, &opt.includes);
+        libraries[index].defines.extend(read_defines(&[String::from(define)]));
+    }
+    for lib_include in &opt.lib_includes {
+        let Some((name, include)) = lib_include.split_once(':') else { continue; };
+        let index = find_library(&mut libraries, name, &defines, &opt.includes);
+        libraries[index].includes.push(PathBuf::from(include));
+    }
+    
+    // extract the archives, whose sources are analyzed in the temporary directories
+    let mut archives = vec![];
+    let mut extracted = vec![];
+    for (path, lib) in inputs {
+        if !archive::is_archive(&path) {
+            extracted.push((path, lib));
+            continue;
+        }
+        match archive::extract(&path) {
+            Ok(x) => {
+                extracted.extend(find_sources(x.dir.path()).into_iter().map(|y| (y, lib)));
+                archives.push(x);
+            }
+            Err(x) => {
+                error!("failed to extract: {:?} ({})", path, x);
+                exit_code = 1;
+            }
+        }
+    }
+    let inputs = extracted;
+
+    // the texts given in place of the files, read through the source provider with
+    // the members of the archives and the file system
+    for overlay in &opt.overlays {
+        let Some((path, file)) = overlay.split_once('=') else {
+            error!("invalid overlay: {}", overlay);
+            exit_code = 1;
+            continue;
+        };
+        match read(file) {
+            Ok(x) => {
+                overlays.insert(PathBuf::from(path), x);
+            }
+            Err(x) => {
+                error!("failed to read: {:?} ({})", file, x);
+                exit_code = 1;
+            }
+        }
+    }
+    let vfs = opt.vfs || !overlays.is_empty();
+    let provider = source::ArchiveProvider {
+        base: source::OverlayProvider {
+            files: overlays.into_iter().map(|(path, text)| (archive::resolve(&path), text)).collect(),
+            base: source::FsProvider
+        }
+    };
+
+    // deduplicate the files reachable via multiple paths
+    let mut files: Vec<(&PathBuf, usize)> = vec![];
+    let mut canonical_paths: HashMap<PathBuf, &PathBuf> = HashMap::new();
+    let mut aliases: HashMap<&PathBuf, Vec<PathBuf>> = HashMap::new();
+    for (path, lib) in &inputs {
+        let canonical_path = canonicalize(path).unwrap_or_else(|_| path.clone());
+        match canonical_paths.get(&canonical_path) {
+            Some(&first) => {
+                debug!("same file as {:?}: {:?}", first, path);
+                aliases.entry(first).or_default().push(path.clone());
+            }
+            None => {
+                canonical_paths.insert(canonical_path, path);
+                files.push((path, *lib));
+            }
+        }
+    }
+
+    // the provenance header of the output
+    let meta = if opt.meta { Some(provenance(&provider, &files)) } else { None };
+
+    // show the token streams instead of parsing the files if desired
+    if opt.tokens {
+        if let Some(meta) = &meta {
+            print_meta(meta);
+        }
+        if !tokens::print_files(&files, &mut libraries, opt) {
+            exit_code = 1;
+        }
+        return exit_code;
+    }
+
+    // write the files with comments, pragmas, and/or attributes removed if desired
+    if !opt.strip.is_empty() {
+        if !strip::write_files(&files, opt) {
+            exit_code = 1;
+        }
+        return exit_code;
+    }
+
+    // write the files with the include files inlined instead of parsing them if desired
+    if opt.flatten_includes {
+        if !flatten::write_files(&files, &libraries, opt) {
+            exit_code = 1;
+        }
+        return exit_code;
+    }
+
+    // group the files into compilation units
+    let mut units: Vec<(Vec<&PathBuf>, usize)> = vec![];
+    for (path, lib) in files {
+        match units.iter_mut().find(|x| opt.single_unit && x.1 == lib) {
+            Some(unit) => unit.0.push(path),
+            None => units.push((vec![path], lib))
+        }
+    }
+
+    // try the lightweight scanner first if desired, falling back to the parser
+    // the scanner does not find the connectivity or the declarations in $unit
+    let needs_parser = opt.connectivity || opt.net_report || opt.unit_decls || opt.special_cells.is_some() ||
+        opt.format == "netlistsvg" || opt.eval_generates || opt.check_syntax || opt.port_clocks || opt.assigns || opt.const_pins ||
+        opt.pin_widths;
+    // the scanner knows only the keywords of IEEE 1800-2017, so --std is left to the parser
+    let scanning = opt.scan_only && !opt.full_tree && !needs_parser && opt.std.is_none();
+
+    // units parsed ahead on the threads of --jobs, which are analyzed in order
+    let mut prefetched: HashMap<usize, parallel::Prefetched> = HashMap::new();
+
+    // parse files; the messages of each unit are buffered and written at once after
+    // the unit unless --unordered
+    for (i, (unit, lib)) in units.iter().enumerate() {
+        if !opt.unordered {
+            logging::write_buffered(&logging::take_buffer());
+            logging::begin_buffer();
+        }
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            let skipped: usize = units[i..].iter().map(|x| x.0.len()).sum();
+            warn!("interrupted; {} files are not analyzed", skipped);
+            break;
+        }
+        if opt.fail_fast && exit_code != 0 {
+            let skipped: usize = units[i..].iter().map(|x| x.0.len()).sum();
+            warn!("stopped at the first failure; {} files are not analyzed", skipped);
+            logging::write_buffered(&logging::take_buffer());
+            return exit_code;
+        }
+        if opt.jobs > 1 && !scanning && !prefetched.contains_key(&i) {
+            prefetched = parallel::prefetch(&units, i, &libraries, &archives, &provider, opt, vfs);
+        }
+        let ahead = prefetched.remove(&i);
+        let library = &mut libraries[*lib];
+        let mut start = Instant::now();
+        let unit_includes = unit_includes(unit, &library.includes, &archives);
+
+        // number of non-ASCII bytes in each file
+        let mut non_ascii: HashMap<PathBuf, usize> = HashMap::new();
+
+        // find the macros used in each file with the include files inlined, from the
+        // defines before the unit
+        let mut used_defines: HashMap<PathBuf, Vec<String>> = HashMap::new();
+        if opt.used_defines {
+            let mut defines = library.defines.clone();
+            for &path in unit {
+                let text = if opt.ignore_include {
+                    read_source_from(&provider, path, &opt.sanitize).ok().map(|x| x.0)
+                } else {
+                    let mut defined = defines.iter().filter(|x| x.1.is_some()).map(|x| x.0.clone()).collect();
+                    flatten::flatten(path, &unit_includes, true, &mut defined, &mut vec![], &mut vec![])
+                };
+                if let Some(names) = text.and_then(|x| scan::used_defines(&x, &mut defines)) {
+                    used_defines.insert(path.clone(), names);
+                }
+            }
+        }
+
+        // track the `unconnected_drive and `resetall directives in the files, from the
+        // defines before the unit
+        if opt.drive_directives {
+            let mut defines = library.defines.clone();
+            for &path in unit {
+                let text = read_source_from(&provider, path, &opt.sanitize).ok().map(|x| x.0);
+                match text.and_then(|x| scan::line_directives(&x, &mut defines)) {
+                    Some(x) => drive_directives.add(&archive::label(path).unwrap_or_else(|| path.clone()), &x),
+                    None => warn!("failed to find the directives: {:?}", path)
+                }
+                drive_directives.end_file();
+            }
+        }
+
+        // try the lightweight scanner first if desired
+        if scanning {
+            let mut scan_defines = library.defines.clone();
+            let mut scan_typedefs = library.typedefs.clone();
+            let scanned: Option<Vec<(Vec<ModuleDef>, Vec<PackageDef>)>> = unit.iter()
+                .map(|path| {
+                    let (text, count) = read_source_from(&provider, path, &opt.sanitize).ok()?;
+                    non_ascii.insert(PathBuf::from(path), count);
+                    scan::scan(path, &text, &mut scan_defines, &overrides, &mut scan_typedefs, &names)
+                })
+                .collect();
+            if scanned.is_none() {
+                debug!("scanner cannot handle {:?}, falling back to the parser", unit);
+            }
+            if let Some(scanned) = scanned {
+                let mut delta = if opt.show_macro_delta { Some(macro_delta(&library.defines, &scan_defines)) } else { None };
+                if !opt.separate {
+                    if opt.show_macro_defs {
+                        library.update_origins(&scan_defines, unit);
+                    }
+                    library.defines = scan_defines;
+                    library.typedefs = scan_typedefs;
+                }
+                for (i, (path, (defs, packages))) in unit.iter().zip(scanned).enumerate() {
+                    results.push(FileResult {
+                        path: PathBuf::from(path),
+                        aliases: aliases.remove(path).unwrap_or_default(),
+                        non_ascii: non_ascii.get(*path).copied().unwrap_or_default(),
+                        library: library.name.clone(),
+                        unresolved_includes: vec![],
+                        parsed_until: None,
+                        missing_defs: vec![],
+                        defs,
+                        packages,
+                        used_defines: used_defines.remove(*path),
+                        full_tree: None,
+                        macro_defs: if opt.show_macro_defs { Some(library.defines.clone()) } else { None },
+                        macro_origins: if opt.show_macro_defs { Some(library.origins.clone()) } else { None },
+                        macro_delta: if i == 0 { delta.take() } else { delta.as_ref().map(|_| vec![]) },
+                        elapsed: start.elapsed()
+                    });
+                }
+                continue;
+            }
+        }
+
+        // check the nesting of the include files, which the preprocessor cannot report;
+        // the virtual file system reports the depth and the cycles while inlining them
+        if !opt.ignore_include && !vfs {
+            let checked: Result<Vec<()>, String> = unit.iter()
+                .map(|path| flatten::check_includes(path, &unit_includes, opt.max_include_depth))
+                .collect();
+            if let Err(x) = checked {
+                error!("{}", x);
+                exit_code = 1;
+                continue;
+            }
+        }
+
+        // take the unit parsed ahead if it was from the same defines, or parse it now;
+        // the rest parsed ahead is dropped if not, since they follow from this one
+        let (prepared, parsed, unresolved_includes) = match ahead.filter(|x| x.defines == library.defines) {
+            Some(x) => {
+                start -= x.elapsed;
+                (x.prepared, Some(x.parsed), x.unresolved_includes)
+            }
+            None => {
+                prefetched.clear();
+                let Some(x) = prepare_unit(unit, &provider, &unit_includes, opt, vfs) else { continue; };
+                (x, None, vec![])
+            }
+        };
+        for x in &prepared.errors {
+            error!("{}", x);
+            exit_code = 1;
+        }
+        if prepared.sources.segments.is_empty() {
+            continue;
+        }
+        debug!("parsing {:?} with include paths {:?}", unit, unit_includes);
+        // trace the search of the include files with --log-level debug
+        if tracing::enabled!(tracing::Level::DEBUG) && !opt.ignore_include && !vfs {
+            for path in unit {
+                flatten::trace_includes(path, &unit_includes, &mut vec![]);
+            }
+        }
+        let (parsed, unresolved_includes) = match parsed {
+            Some(x) => (x, unresolved_includes),
+            None => {
+                let (preprocessed, unresolved_includes) = preprocess_unit(&prepared, &library.defines, &unit_includes, opt);
+                (preprocessed.and_then(|(text, defines)| parse_sv_pp(text, defines, opt.allow_incomplete)), unresolved_includes)
+            }
+        };
+        for name in &unresolved_includes {
+            warn!("include file not found, substituted by an empty file: {:?}", name);
+        }
+        let PreparedUnit { tmpfile, mut sources, non_ascii: read_non_ascii, .. } = prepared;
+        non_ascii.extend(read_non_ascii);
+
+        debug!("parsed {:?} in {} ms", unit, start.elapsed().as_millis());
+        match parsed {
+            Ok((syntax_tree, new_defines)) => {
+                if let Some(x) = tmpfile {
+                    let _ = x.close();
+                }
+                if opt.check_syntax {
+                    if !opt.separate {
+                        library.defines = new_defines;
+                    }
+                    continue;
+                }
+                // distribute the definitions to the files they come from
+                let mut file_defs: Vec<Vec<ModuleDef>> = sources.segments.iter().map(|_| vec![]).collect();
+                let mut file_packages: Vec<Vec<PackageDef>> = sources.segments.iter().map(|_| vec![]).collect();
+                let mut typedefs = library.typedefs.clone();
+                if !opt.full_tree || opt.defs || opt.top.is_some() {
+                    let (defs, packages) = analyze_defs(&syntax_tree, &mut sources, &overrides, &mut typedefs, &names);
+                    let mut current = 0;
+                    for def in defs {
+                        if let Some(i) = sources.segments.iter().position(|x| *x.path == *def.file) {
+                            current = i;
+                        }
+                        file_defs[current].push(def);
+                    }
+                    let mut current = 0;
+                    for package in packages {
+                        if let Some(i) = sources.segments.iter().position(|x| *x.path == *package.file) {
+                            current = i;
+                        }
+                        file_packages[current].push(package);
+                    }
+                }
+                if opt.unit_decls {
+                    unit_scopes.push(unit::UnitScope {
+                        files: sources.segments.iter().map(|x| x.path.clone()).collect(),
+                        decls: unit::analyze(&syntax_tree, &mut sources)
+                    });
+                }
+                // find the part of the unit which was not parsed
+                let parsed_end = if opt.allow_incomplete { find_parsed_end(&syntax_tree, &sources) } else { None };
+                // the delta is shown as a part of the first file like the full tree
+                let mut delta = if opt.show_macro_delta { Some(macro_delta(&library.defines, &new_defines)) } else { None };
+                // update the preprocessor state if desired
+                if !opt.separate {
+                    if opt.show_macro_defs {
+                        library.update_origins(&new_defines, unit);
+                    }
+                    library.defines = new_defines;
+                    library.typedefs = typedefs;
+                }
+                // the full tree is shown as a part of the first file
+                let mut full_tree = match (opt.full_tree, opt.tree_format.as_str()) {
+                    (false, _) => None,
+                    (true, "json") => Some(format_full_tree_json(&syntax_tree, TreeFilter::new(opt), &mut sources)),
+                    (true, _) => Some(format_full_tree(&syntax_tree, TreeFilter::new(opt), &mut sources))
+                };
+                for (i, ((segment, defs), packages)) in sources.segments.iter().zip(file_defs).zip(file_packages).enumerate() {
+                    let full_tree = if i == 0 { full_tree.take() } else { None };
+                    let unparsed = parsed_end.and_then(|x| segment.unparsed(sources.segment_pos(segment, x)));
+                    results.push(FileResult {
+                        path: segment.path.clone(),
+                        aliases: aliases.remove(&segment.path).unwrap_or_default(),
+                        non_ascii: non_ascii.get(&segment.path).copied().unwrap_or_default(),
+                        library: library.name.clone(),
+                        unresolved_includes: if i == 0 { unresolved_includes.clone() } else { vec![] },
+                        parsed_until: unparsed.as_ref().map(|x| x.0),
+                        missing_defs: unparsed.map(|x| x.1).unwrap_or_default(),
+                        defs,
+                        packages,
+                        used_defines: used_defines.remove(&segment.path),
+                        full_tree,
+                        macro_defs: if opt.show_macro_defs { Some(library.defines.clone()) } else { None },
+                        macro_origins: if opt.show_macro_defs { Some(library.origins.clone()) } else { None },
+                        macro_delta: if i == 0 { delta.take() } else { delta.as_ref().map(|_| vec![]) },
+                        elapsed: start.elapsed()
+                    });
+                }
+            }
+            Err(x) => {
+                let unit_name = sources.segments.iter()
+                    .map(|x| format!("{:?}", archive::label(&x.path).unwrap_or_else(|| x.path.clone())))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                match x {
+                    sv_parser_error::Error::Parse(Some((origin_path, origin_pos))) => {
+                        // the text is that of the provider, e.g. of an overlay or a member of an archive
+                        let (origin_path, origin_pos) = sources.origin(&origin_path, origin_pos);
+                        let text = String::from(sources.text(&origin_path));
+                        let label = archive::label(&origin_path).unwrap_or(origin_path);
+                        error!("parse failed: {}\n{}", unit_name, format_parse_error(&label, &text, &origin_pos));
+                    }
+                    x => error!("parse failed: {} ({}){}", unit_name, x, logging::causes(&x))
+                }
+                exit_code = 1;
+            }
+        }
+    }
+    logging::write_buffered(&logging::take_buffer());
+    if opt.fail_fast && exit_code != 0 {
+        return exit_code;
+    }
+    if !archives.is_empty() {
+        label_archive_members(&mut results, &mut unit_scopes, &names);
+    }
+
+    // the modules of the library files are used only if instantiated, as -v of the
+    // simulators, unless all of them are desired
+    if !opt.lib_files.is_empty() && !opt.all_lib_defs {
+        prune_lib_defs(&mut results, &opt.files, &opt.lib_files);
+    }
+
+    // nothing is printed on success when only the syntax is checked
+    if opt.check_syntax {
+        return if INTERRUPTED.load(Ordering::SeqCst) { EXIT_INTERRUPTED } else { exit_code };
+    }
+    
+    // the patterns of the bus interfaces; those of the files take precedence
+    let mut bus_patterns = vec![];
+    if opt.buses {
+        for path in &opt.bus_patterns {
+            match bus::read_patterns(path) {
+                Ok(x) => bus_patterns.extend(x),
+                Err(x) => {
+                    error!("failed to read bus patterns: {:?} ({})", path, x);
+                    exit_code = 1;
+                }
+            }
+        }
+        bus_patterns.extend(bus::builtin_patterns());
+    }
+
+    // read the names of the blackboxes
+    let mut blackboxes = HashSet::new();
+    if let Some(path) = &opt.blackbox {
+        match read_blackboxes(path) {
+            Ok(x) => blackboxes = x,
+            Err(x) => {
+                error!("failed to read blackboxes: {:?} ({})", path, x);
+                exit_code = 1;
+            }
+        }
+    }
+
+    // read the parameter values for the instances if desired
+    let mut scoped_overrides = hier::ScopedOverrides::new();
+    if let Some(path) = &opt.param_file {
+        match read_param_file(path) {
+            Ok(x) => scoped_overrides = x,
+            Err(x) => {
+                error!("failed to read parameter values: {:?} ({})", path, x);
+                exit_code = 1;
+            }
+        }
+    }
+
+    // read the cells of the Liberty files
+    let mut cells = HashSet::new();
+    for path in &opt.liberty {
+        match liberty::read_cells(path) {
+            Ok(x) => cells.extend(x),
+            Err(x) => {
+                error!("failed to read Liberty file: {:?} ({})", path, x);
+                exit_code = 1;
+            }
+        }
+    }
+
+    // report the constructs which are not interpreted if desired
+    if opt.strict_defs {
+        for def in results.iter().flat_map(|x| &x.defs) {
+            for (line, message) in &def.issues {
+                error!("{:?}:{}: {} in {}", def.file, line, message, def.name);
+                exit_code = 1;
+                if opt.fail_fast {
+                    return exit_code;
+                }
+            }
+        }
+    }
+
+    // print the results
+    merge_duplicate_defs(&mut results, &provider);
+    resolve_inst_kinds(&mut results, &cells);
+    if opt.connectivity || opt.net_report || opt.special_cells.is_some() || opt.format == "netlistsvg" || opt.const_pins {
+        conn::resolve_pins(&mut results);
+    }
+    if opt.format == "netlistsvg" {
+        netlistsvg::print_netlist(&results, opt.top.as_deref(), meta.as_ref());
+        return exit_code;
+    }
+    let used_by = find_used_by(&results);
+    if let Some(meta) = &meta {
+        print_meta(meta);
+    }
+    report::print_files(&results, &used_by, &bus_patterns, &blackboxes, opt);
+
+    // report the references to the modules neither defined nor in the Liberty files
+    if !opt.liberty.is_empty() {
+        report::print_unknown_refs(&results, &blackboxes);
+    }
+
+    // summarize the instantiations without the hierarchy if desired
+    if opt.inst_counts && opt.top.is_none() {
+        report::print_inst_summary(&used_by);
+    }
+
+    // report the instances of the special cells for each clock domain if desired
+    if let Some(path) = &opt.special_cells {
+        match special::read_special_cells(path) {
+            Ok(cells) => {
+                outln!("special_cells:");
+                special::print_special_cells(&results, &cells);
+            }
+            Err(x) => {
+                error!("failed to read special cells: {:?} ({})", path, x);
+                exit_code = 1;
+            }
+        }
+    }
+
+    // report the declarations in $unit if desired
+    if opt.unit_decls {
+        if unit_scopes.iter().all(|x| x.decls.is_empty()) {
+            outln!("unit_decls: []");
+        } else {
+            outln!("unit_decls:");
+            unit::print_unit_decls(&unit_scopes);
+        }
+    }
+
+    // report the effects of `unconnected_drive and `resetall if desired
+    if opt.drive_directives {
+        outln!("drive_directives:");
+        directive::print_drive_directives(&drive_directives, &results);
+    }
+
+    // check the file names, the port connections, and the ports if desired
+    check::check_defs(&results, &used_by, opt);
+
+    // compare with and write the manifest if desired
+    if !manifest::compare_and_write(&results, opt) {
+        exit_code = 1;
+    }
+
+    // write the project scripts of Vivado and Quartus and the build targets if desired
+    if !project::write_projects(&results, opt) {
+        exit_code = 1;
+    }
+
+    // render the sources as HTML if desired
+    if let Some(dir) = &opt.emit_html_src {
+        if let Err(x) = html::write_html(dir, &results) {
+            error!("failed to write HTML: {:?} ({})", dir, x);
+            exit_code = 1;
+        }
+    }
+
+    // report the ports tied to constants if desired
+    if opt.const_pins {
+        conn::print_const_pins(&results, &opt.const_pin_patterns);
+    }
+
+    // report the widths of the port connections if desired
+    if opt.pin_widths {
+        conn::print_pin_widths(&results, &overrides);
+    }
+
+    // report the fan-out and the floating nets if desired
+    if opt.net_report {
+        outln!("net_report:");
+        conn::print_net_report(&results);
+    }
+
+    // elaborate the hierarchy and resolve it for each configuration if desired
+    let all_defs: Vec<&ModuleDef> = results.iter().flat_map(|x| &x.defs).collect();
+    if let Some(top) = &opt.top {
+        if !hier::print_top(&all_defs, top, &scoped_overrides, &blackboxes, opt) {
+            exit_code = 1;
+        }
+    }
+    if !configs.is_empty() {
+        hier::print_configs(&all_defs, &configs, &scoped_overrides, &blackboxes, opt);
+    }
+
+    // return exit code
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        return EXIT_INTERRUPTED;
+    }
+    exit_code
+}
+
+// request of --stdin-batch: the options and the files as on the command line
+#[derive(Deserialize)]
+struct BatchRequest {
+    #[serde(default)]
+    id: serde_json::Value,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    files: Vec<String>,
+    // texts in place of the files of the paths, as the unsaved buffers of an editor
+    #[serde(default)]
+    overlays: HashMap<String, String>
+}
+
+// response of --stdin-batch: the output as JSON if it is YAML, or as the text otherwise
+#[derive(Serialize)]
+struct BatchResponse {
+    id: serde_json::Value,
+    exit_code: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    // messages of the request, which are not written to the standard error
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    diagnostics: Vec<String>
+}
+
+// serve the requests in the standard input one by one; the process stays resident
+// until the end of the input or an interrupt
+fn run_batch() -> i32 {
+    let stdin = std::io::stdin();
+    for line in stdin.lock().lines() {
+        let Ok(line) = line else { break; };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<BatchRequest>(&line) {
+            Ok(request) => {
+                let args: Vec<String> = std::iter::once(String::from("svinst_port")).chain(request.args).chain(request.files).collect();
+                match Opt::from_iter_safe(expand_plusargs(args.iter().cloned())) {
+                    Ok(opt) if opt.stdin_batch => BatchResponse {
+                        id: request.id, exit_code: 1, result: None, output: None, error: Some(String::from("nested --stdin-batch")), diagnostics: vec![]
+                    },
+                    Ok(opt) => {
+                        CAPTURE.with(|x| *x.borrow_mut() = Some(String::new()));
+                        COMMAND_LINE.with(|x| *x.borrow_mut() = args);
+                        logging::begin_capture();
+                        let overlays = request.overlays.into_iter().map(|(k, v)| (PathBuf::from(k), v.into_bytes())).collect();
+                        let exit_code = run_opt(opt, overlays);
+                        let output = CAPTURE.with(|x| x.borrow_mut().take()).unwrap_or_default();
+                        let diagnostics = logging::take_capture();
+                        let result = serde_yaml::from_str::<serde_json::Value>(&output).ok().filter(|x| x.is_object());
+                        BatchResponse {
+                            id: request.id,
+                            exit_code,
+                            output: if result.is_none() { Some(output) } else { None },
+                            result,
+                            error: None,
+                            diagnostics
+                        }
+                    }
+                    Err(x) => BatchResponse { id: request.id, exit_code: 1, result: None, output: None, error: Some(x.message), diagnostics: vec![] }
+                }
+            }
+            Err(x) => BatchResponse {
+                id: serde_json::Value::Null, exit_code: 1, result: None, output: None, error: Some(format!("invalid request: {}", x)), diagnostics: vec![]
+            }
+        };
+        let mut stdout = std::io::stdout().lock();
+        let _ = writeln!(stdout, "{}", serde_json::to_string(&response).unwrap_or_default());
+        let _ = stdout.flush();
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            return EXIT_INTERRUPTED;
+        }
+    }
+    0
+}
+
+// include paths of a unit; the include files of the sources in an archive are
+// searched relative to the archive root and to the directories of the sources
+fn unit_includes(
+    unit: &[&PathBuf],
+    includes: &[PathBuf],
+    archives: &[archive::Archive]
+) -> Vec<PathBuf> {
+    let mut ret = includes.to_vec();
+    for x in archives.iter().filter(|x| unit.iter().any(|y| y.starts_with(x.dir.path()))) {
+        ret.push(x.dir.path().to_path_buf());
+    }
+    for dir in unit.iter().filter(|x| archives.iter().any(|y| x.starts_with(y.dir.path()))).filter_map(|x| x.parent()) {
+        if !ret.iter().any(|x| x == dir) {
+            ret.push(dir.to_path_buf());
+        }
+    }
+    ret
+}
+
+// text of a compilation unit to be parsed, in a temporary file to sanitize non-ASCII
+// characters, or in memory with the include files inlined through the virtual file
+// system; the errors in reading the files are reported with the messages of the unit
+struct PreparedUnit {
+    tmpfile: Option<NamedTempFile>,
+    text: String,
+    sources: SourceMap,
+    non_ascii: HashMap<PathBuf, usize>,
+    errors: Vec<String>
+}
+
+// the text of a unit, or None if the temporary file cannot be created
+fn prepare_unit(
+    unit: &[&PathBuf],
+    provider: &dyn source::SourceProvider,
+    includes: &[PathBuf],
+    opt: &Opt,
+    vfs: bool
+) -> Option<PreparedUnit> {
+    let mut tmpfile = if vfs { None } else { Some(NamedTempFile::new().ok()?) };
+    let unit_path = tmpfile.as_ref().map_or_else(|| PathBuf::from(VFS_UNIT_PATH), |x| x.path().to_path_buf());
+    let mut sources = SourceMap::new(&unit_path);
+    let mut non_ascii = HashMap::new();
+    let mut errors = vec![];
+    let read_include = |path: &Path| read_source_from(provider, path, &opt.sanitize).map(|x| x.0);
+    let mut inliner = source::Inliner::new(provider, includes, opt.max_include_depth, &read_include);
+    let mut text = String::new();
+    // select the keyword set; the directive is put on the first line so that line numbers are kept
+    if let Some(std) = &opt.std {
+        text.push_str(&format!("`begin_keywords \"{}\" ", std));
+    }
+    for &path in unit {
+        let org_string = match read_source_from(provider, path, &opt.sanitize) {
+            Ok((text, count)) => {
+                non_ascii.insert(path.clone(), count);
+                text
+            }
+            Err(x) => {
+                errors.push(format!("failed to read: {:?} ({})", path, x));
+                continue;
+            }
+        };
+        let begin = text.len();
+        if vfs && !opt.ignore_include {
+            if let Err(x) = inliner.append(path, &org_string, &mut text) {
+                errors.push(x);
+                text.truncate(begin);
+                continue;
+            }
+        } else {
+            text.push_str(&org_string);
+        }
+        text.push('\n');
+        sources.add(path, begin, org_string);
+    }
+    if let Some(x) = &mut tmpfile {
+        let _ = x.write_all(text.as_bytes());
+    }
+    sources.pieces = std::mem::take(&mut inliner.pieces);
+    sources.includes.extend(inliner.texts.drain());
+    Some(PreparedUnit { tmpfile, text, sources, non_ascii, errors })
+}
+
+// preprocess a unit from the defines before it; with --permissive-includes, an empty
+// file is substituted for each include which cannot be resolved, and those includes
+// are returned
+fn preprocess_unit(
+    prepared: &PreparedUnit,
+    defines: &Defines,
+    includes: &[PathBuf],
+    opt: &Opt
+) -> (Result<(PreprocessedText, Defines), Error>, Vec<PathBuf>) {
+    let stub_dir = if opt.permissive_includes { tempfile::tempdir().ok() } else { None };
+    let mut includes = includes.to_vec();
+    if let Some(dir) = &stub_dir {
+        includes.push(dir.path().to_path_buf());
+    }
+    let mut unresolved_includes: Vec<PathBuf> = vec![];
+    loop {
+        let ret = match &prepared.tmpfile {
+            Some(x) => preprocess(x.path(), defines, &includes, false, opt.ignore_include),
+            None => preprocess_str(&prepared.text, &prepared.sources.tmp_path, defines, &includes, opt.ignore_include, false, 0, 0)
+        };
+        if let (Err(x), Some(dir)) = (&ret, &stub_dir) {
+            if let Some(name) = find_missing_include(x) {
+                let stub = dir.path().join(&name);
+                let created = stub.parent().is_some_and(|x| create_dir_all(x).is_ok()) && File::create(&stub).is_ok();
+                if created && !unresolved_includes.contains(&name) {
+                    unresolved_includes.push(name);
+                    continue;
+                }
+            }
+        }
+        return (ret, unresolved_includes);
+    }
+}
+
+// relative path of the include file which caused the error, if any
+fn find_missing_include(
+    err: &sv_parser_error::Error
+) -> Option<PathBuf> {
+    let sv_parser_error::Error::Include { source } = err else { return None; };
+    match source.as_ref() {
+        sv_parser_error::Error::File { path, .. } if path.is_relative() => Some(path.clone()),
+        x => find_missing_include(x)
+    }
+}
+
+// read a file treating non-ASCII characters by the policy of --sanitize: replaced
+// with '?' (which keeps the byte offsets), stripped, kept as UTF-8, or an error;
+// the number of the non-ASCII bytes is returned with the text
+fn read_source(
+    path: &Path,
+    policy: &str
+) -> Result<(String, usize), String> {
+    read_source_from(&source::FsProvider, path, policy)
+}
+
+// read a file through a source provider as read_source does
+fn read_source_from(
+    provider: &dyn source::SourceProvider,
+    path: &Path,
+    policy: &str
+) -> Result<(String, usize), String> {
+    let org = provider.read(path).map_err(|x| x.to_string())?;
+    let count = org.iter().filter(|x| !x.is_ascii()).count();
+    let line_at = |pos: usize| org[..pos].iter().filter(|&&x| x == b'\n').count() + 1;
+    let text = match policy {
+        "strip" => org.iter().filter(|x| x.is_ascii()).map(|&c| c as char).collect(),
+        "keep" => match std::str::from_utf8(&org) {
+            Ok(x) => String::from(x),
+            Err(x) => return Err(format!("invalid UTF-8 at line {}", line_at(x.valid_up_to())))
+        },
+        "error" if count > 0 => {
+            let pos = org.iter().position(|x| !x.is_ascii()).unwrap_or_default();
+            return Err(format!("non-ASCII character at line {}", line_at(pos)));
+        }
+        _ => org.iter().map(|&c| if c < 128 { c as char } else { '?' }).collect()
+    };
+    Ok((text, count))
+}
+
+// read a file replacing non-ASCII characters
+fn read_sanitized(
+    path: &Path
+) -> Option<String> {
+    read_source(path, "replace").ok().map(|x| x.0)
+}
+
+// names of the blackboxes, one per line; the text after "#" is a comment
+fn read_blackboxes(
+    path: &Path
+) -> std::io::Result<HashSet<String>> {
+    let text = std::fs::read_to_string(path)?;
+    Ok(text.lines()
+        .map(|x| x.split('#').next().unwrap_or_default().trim())
+        .filter(|x| !x.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+// parameter overrides given as NAME=VALUE
+fn read_overrides(
+    overrides: &[String]
+) -> HashMap<String, String> {
+    let mut ret = HashMap::new();
+    for x in overrides {
+        let Some((name, value)) = x.split_once('=') else {
+            warn!("invalid parameter override: {}", x);
+            continue;
+        };
+        ret.insert(String::from(name), value.split_whitespace().collect());
+    }
+    ret
+}
+
+// parameter values of a file (--param-file) in YAML or JSON, keyed by the
+// hierarchical paths of the instances or the names of the modules followed by the
+// parameter names (e.g. "top.u_core.WIDTH: 64"); the keys may also be nested
+fn read_param_file(
+    path: &Path
+) -> Result<hier::ScopedOverrides, String> {
+    let text = read_to_string(path).map_err(|x| x.to_string())?;
+    let value: serde_yaml::Value = serde_yaml::from_str(&text).map_err(|x| x.to_string())?;
+    let mut ret = hier::ScopedOverrides::new();
+    add_param_values(&value, "", &mut ret)?;
+    Ok(ret)
+}
+
+fn add_param_values(
+    value: &serde_yaml::Value,
+    key: &str,
+    ret: &mut hier::ScopedOverrides
+) -> Result<(), String> {
+    let text = match value {
+        serde_yaml::Value::Mapping(x) => {
+            for (k, v) in x {
+                let name = match k {
+                    serde_yaml::Value::String(x) => x.clone(),
+                    serde_yaml::Value::Number(x) => x.to_string(),
+                    _ => return Err(format!("invalid key in {:?}", key))
+                };
+                let name = if key.is_empty() { name } else { format!("{}.{}", key, name) };
+                add_param_values(v, &name, ret)?;
+            }
+            return Ok(());
+        }
+        serde_yaml::Value::String(x) => x.split_whitespace().collect(),
+        serde_yaml::Value::Number(x) => x.to_string(),
+        serde_yaml::Value::Bool(x) => String::from(if *x { "1" } else { "0" }),
+        _ => return Err(format!("invalid value of {:?}", key))
+    };
+    let Some((scope, name)) = key.rsplit_once('.') else {
+        return Err(format!("no instance or module for {:?}", key));
+    };
+    ret.entry(String::from(scope)).or_default().insert(String::from(name), text);
+    Ok(())
+}
+
+// parameter overrides of each configuration, in the order of first appearance
+fn read_configs(
+    configs: &[String]
+) -> Vec<(String, HashMap<String, String>)> {
+    let mut ret: Vec<(String, HashMap<String, String>)> = vec![];
+    for x in configs {
+        let Some((name, value)) = x.split_once(':') else {
+            warn!("invalid configuration: {}", x);
+            continue;
+        };
+        let overrides = read_overrides(&[String::from(value)]);
+        match ret.iter_mut().find(|x| x.0 == name) {
+            Some(config) => config.1.extend(overrides),
+            None => ret.push((String::from(name), overrides))
+        }
+    }
+    ret
+}
+
+// rewrite simulator-style +define+, +incdir+, and +libext+ arguments into -d, -i, and
+// --libext options
+fn expand_plusargs(
+    args: impl Iterator<Item = String>
+) -> Vec<String> {
+    let mut ret = vec![];
+    for arg in args {
+        let (flag, rest) = if let Some(x) = arg.strip_prefix("+define+") {
+            ("-d", x)
+        } else if let Some(x) = arg.strip_prefix("+incdir+") {
+            ("-i", x)
+        } else if let Some(x) = arg.strip_prefix("+libext+") {
+            ("--libext", x)
+        } else {
+            ret.push(arg);
+            continue;
+        };
+        for x in rest.split('+').filter(|x| !x.is_empty()) {
+            ret.push(String::from(flag));
+            ret.push(String::from(x));
+        }
+    }
+    ret
+}
+
+fn read_defines(
+    defines: &[String]
+) -> HashMap<String, Option<Define>> {
+    let mut ret = HashMap::new();
+    for define in defines {
+        let mut define = define.splitn(2, '=');
+        let ident = String::from(define.next().unwrap());
+        let text = if let Some(x) = define.next() {
+            let x = enquote::unescape(x, None).unwrap();
+            Some(DefineText::new(x, None))
+        } else {
+            None
+        };
+        let define = Define::new(ident.clone(), vec![], text);
+        ret.insert(ident, Some(define));
+    }
+    ret
+}
+
+// a group of files with its own define variables and include paths
+struct Library {
+    name: String,
+    defines: HashMap<String, Option<Define>>,
+    includes: Vec<PathBuf>,
+    typedefs: HashMap<String, String>,
+    // where each macro is defined, updated only with --show-macro-defs
+    origins: HashMap<String, MacroOrigin>
+}
+
+// where a macro is defined or last redefined; the file is None for the command line
+#[derive(Clone)]
+struct MacroOrigin {
+    file: Option<PathBuf>,
+    line: Option<usize>,
+    // redefined with a different body or arguments at some point
+    redefined: bool
+}
+
+// the coverage constants of IEEE 1800, which are predefined by the preprocessor
+fn is_predefined(
+    name: &str
+) -> bool {
+    name.starts_with("SV_COV_")
+}
+
+// whether two definitions of a macro are the same regardless of where they are
+fn same_define(
+    x: &Define,
+    y: &Define
+) -> bool {
+    x.identifier == y.identifier && x.arguments == y.arguments &&
+        x.text.as_ref().map(|x| &x.text) == y.text.as_ref().map(|x| &x.text)
+}
+
+impl Library {
+    fn new(
+        name: &str,
+        defines: &HashMap<String, Option<Define>>,
+        includes: &[PathBuf]
+    ) -> Library {
+        let origins = defines.iter()
+            .filter(|x| x.1.is_some())
+            .map(|x| (x.0.clone(), MacroOrigin { file: None, line: None, redefined: false }))
+            .collect();
+        Library {
+            name: String::from(name),
+            defines: defines.clone(),
+            includes: includes.to_vec(),
+            typedefs: HashMap::new(),
+            origins
+        }
+    }
+
+    // record where the macros added or changed by a unit are defined, before the
+    // defines are replaced with those after the unit
+    fn update_origins(
+        &mut self,
+        defines: &HashMap<String, Option<Define>>,
+        unit: &[&PathBuf]
+    ) {
+        let mut locations = None;
+        for (name, define) in defines {
+            let Some(define) = define else { continue; };
+            let old = self.defines.get(name).and_then(|x| x.as_ref());
+            if old == Some(define) {
+                continue;
+            }
+            let locations = locations.get_or_insert_with(|| {
+                let mut ret = HashMap::new();
+                for path in unit {
+                    flatten::define_locations(path, &self.includes, &mut vec![], &mut ret);
+                }
+                ret
+            });
+            let (file, line) = match locations.get(name) {
+                Some((file, line)) => (file.clone(), Some(*line)),
+                None if is_predefined(name) => continue,
+                None => (unit.last().map(|x| x.to_path_buf()).unwrap_or_default(), None)
+            };
+            let redefined = old.is_some_and(|x| !same_define(x, define)) || self.origins.get(name).is_some_and(|x| x.redefined);
+            self.origins.insert(name.clone(), MacroOrigin { file: Some(file), line, redefined });
+        }
+        self.origins.retain(|x, _| defines.get(x).is_some_and(|x| x.is_some()));
+    }
+}
+
+// index of the library, which is created if it does not exist yet
+fn find_library(
+    libraries: &mut Vec<Library>,
+    name: &str,
+    defines: &HashMap<String, Option<Define>>,
+    includes: &[PathBuf]
+) -> usize {
+    if let Some(index) = libraries.iter().position(|x| x.name == name) {
+        return index;
+    }
+    libraries.push(Library::new(name, defines, includes));
+    libraries.len() - 1
+}
+
+// source files (*.v, *.sv) in a directory and its subdirectories
+fn find_sources(
+    path: &Path
+) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    let Ok(entries) = read_dir(path) else { return vec![]; };
+    let mut entries: Vec<PathBuf> = entries.flatten().map(|x| x.path()).collect();
+    entries.sort();
+    let mut ret = vec![];
+    for entry in entries {
+        if entry.is_dir() {
+            ret.extend(find_sources(&entry));
+        } else if matches!(entry.extension().and_then(|x| x.to_str()), Some("v" | "sv")) {
+            ret.push(entry);
+        }
+    }
+    ret
+}
+
+// provenance of the output: the versions of the tool and the parser, the command
+// line, the time in seconds since the epoch, and the SHA-256 of the paths and the
+// contents of the input files in order
+fn provenance(
+    provider: &dyn source::SourceProvider,
+    files: &[(&PathBuf, usize)]
+) -> serde_json::Value {
+    let mut hasher = Sha256::new();
+    for (path, _) in files {
+        hasher.update(path.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(provider.read(path).unwrap_or_default());
+        hasher.update([0]);
+    }
+    let timestamp = std::time::SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |x| x.as_secs());
+    serde_json::json!({
+        "tool": env!("CARGO_PKG_NAME"),
+        "tool_version": env!("CARGO_PKG_VERSION"),
+        "sv_parser_version": env!("SV_PARSER_VERSION"),
+        "command_line": COMMAND_LINE.with(|x| x.borrow().clone()),
+        "timestamp": timestamp,
+        "input_hash": format!("{:x}", hasher.finalize())
+    })
+}
+
+// the provenance header as YAML; the values in JSON are also valid in YAML
+fn print_meta(
+    meta: &serde_json::Value
+) {
+    outln!("meta:");
+    for (key, value) in meta.as_object().into_iter().flatten() {
+        outln!("  {}: {}", key, value);
+    }
+}
+
+static CHAR_CR: u8 = 0x0d;
+static CHAR_LF: u8 = 0x0a;
+
+// definitions in a file by the scanner, or by the parser with the include paths if
+// the scanner cannot handle it
+fn find_defs(
+    path: &Path,
+    defines: &mut HashMap<String, Option<Define>>,
+    typedefs: &mut HashMap<String, String>,
+    includes: &[PathBuf],
+    ignore_include: bool,
+    names: &Interner
+) -> Option<Vec<ModuleDef>> {
+    let text = read_sanitized(path)?;
+    let mut scan_defines = defines.clone();
+    if let Some((defs, _)) = scan::scan(path, &text, &mut scan_defines, &HashMap::new(), typedefs, names) {
+        *defines = scan_defines;
+        return Some(defs);
+    }
+    let mut tmpfile = NamedTempFile::new().ok()?;
+    tmpfile.write_all(text.as_bytes()).ok()?;
+    let mut sources = SourceMap::new(tmpfile.path());
+    sources.add(path, 0, text);
+    let (syntax_tree, new_defines) = parse_sv(tmpfile.path(), defines, includes, ignore_include, false).ok()?;
+    *defines = new_defines;
+    Some(analyze_defs(&syntax_tree, &mut sources, &HashMap::new(), typedefs, names).0)
+}
+
+fn format_parse_error(
+    origin_path: &Path,
+    s: &str,
+    origin_pos: &usize
+) -> String {
+    let mut ret = String::new();
+
+    let mut pos = 0;
+    let mut column = 1;
+    let mut last_lf = None;
+    while pos < s.len() {
+        if s.as_bytes()[pos] == CHAR_LF {
+            column += 1;
+            last_lf = Some(pos);
+        }
+        pos += 1;
+
+        if *origin_pos == pos {
+            let row = if let Some(last_lf) = last_lf {
+                pos - last_lf
+            } else {
+                pos + 1
+            };
+            let mut next_crlf = pos;
+            while next_crlf < s.len() {
+                if s.as_bytes()[next_crlf] == CHAR_CR || s.as_bytes()[next_crlf] == CHAR_LF {
+                    break;
+                }
+                next_crlf += 1;
+            }
+
+            let column_len = format!("{}", column).len();
+
+            ret.push_str(&format!(" {}:{}:{}\n", origin_path.to_string_lossy(), column, row));
+
+            ret.push_str(&format!("{}|\n", " ".repeat(column_len + 1)));
+
+            ret.push_str(&format!("{} |", column));
+
+            let beg = if let Some(last_lf) = last_lf {
+                last_lf + 1
+            } else {
+                0
+            };
+            ret.push_str(&format!(
+                " {}\n",
+                String::from_utf8_lossy(&s.as_bytes()[beg..next_crlf])
+            ));
+
+            ret.push_str(&format!("{}|", " ".repeat(column_len + 1)));
+
+            ret.push_str(&format!(
+                " {}{}",
+                " ".repeat(pos - beg),
+                "^".repeat(cmp::min(origin_pos + 1, next_crlf) - origin_pos)
+            ));
+        }
+    }
+    ret
+}
+
+// a macro added, removed, or changed by a compilation unit
+struct MacroChange {
+    name: String,
+    change: &'static str,
+    // body after the change, if any
+    text: Option<String>
+}
+
+// macros which differ between the defines before and after a unit, in the order of the names
+fn macro_delta(
+    old: &HashMap<String, Option<Define>>,
+    new: &HashMap<String, Option<Define>>
+) -> Vec<MacroChange> {
+    let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+    let mut ret = vec![];
+    for name in names.into_iter().filter(|x| !is_predefined(x)) {
+        let old = old.get(name).and_then(|x| x.as_ref());
+        let new = new.get(name).and_then(|x| x.as_ref());
+        let change = match (old, new) {
+            (None, Some(_)) => "added",
+            (Some(_), None) => "removed",
+            (Some(x), Some(y)) if !same_define(x, y) => "changed",
+            _ => continue
+        };
+        let text = new.and_then(|x| x.text.as_ref()).map(|x| String::from(x.text.trim()));
+        ret.push(MacroChange { name: name.clone(), change, text });
+    }
+    ret
+}
+
+// mapping from the sanitized temporary file back to the original files
+struct Segment {
+    path: PathBuf,
+    begin: usize,
+    text: String
+}
+
+impl Segment {
+    // line where the parsing stopped and the modules after that, if the segment
+    // was not parsed to the end; `parsed_end` is a position in the temporary file
+    fn unparsed(
+        &self,
+        parsed_end: usize
+    ) -> Option<(usize, Vec<String>)> {
+        let pos = parsed_end.saturating_sub(self.begin).min(self.text.len());
+        let rest = &self.text[pos..];
+        if !scan::has_tokens(rest) {
+            return None;
+        }
+        // the line of the last character parsed
+        let line = match pos {
+            0 => 0,
+            _ => self.text.as_bytes().iter().take(pos - 1).filter(|&&c| c == CHAR_LF).count() + 1
+        };
+        Some((line, scan::module_names(rest)))
+    }
+}
+
+// end of the last token parsed in the temporary file
+fn find_parsed_end(
+    syntax_tree: &SyntaxTree,
+    sources: &SourceMap
+) -> Option<usize> {
+    let mut ret = None;
+    for node in syntax_tree {
+        let RefNode::Locate(x) = node else { continue; };
+        let Some((path, pos)) = syntax_tree.get_origin(x) else { continue; };
+        if *path == sources.tmp_path {
+            ret = Some(pos + x.len);
+        }
+    }
+    ret
+}
+
+// range of a token in its original file; the end is exclusive
+#[derive(Clone, Copy)]
+struct Span {
+    offset: usize,
+    end_offset: usize,
+    line: usize,
+    column: usize,
+    end_line: usize,
+    end_column: usize
+}
+
+// name of the text of a compilation unit parsed in memory (--vfs)
+const VFS_UNIT_PATH: &str = "<unit>";
+
+struct SourceMap {
+    tmp_path: PathBuf,
+    segments: Vec<Segment>,
+    // parts of the text copied from the files with --vfs, where the include files
+    // are inlined; empty if the files are just concatenated
+    pieces: Vec<source::Piece>,
+    includes: HashMap<PathBuf, String>,
+    line_begins: HashMap<PathBuf, Vec<usize>>
+}
+
+impl SourceMap {
+    fn new(
+        tmp_path: &Path
+    ) -> SourceMap {
+        SourceMap {
+            tmp_path: tmp_path.to_path_buf(),
+            segments: vec![],
+            pieces: vec![],
+            includes: HashMap::new(),
+            line_begins: HashMap::new()
+        }
+    }
+
+    fn add(
+        &mut self,
+        path: &Path,
+        begin: usize,
+        text: String
+    ) {
+        self.segments.push(Segment { path: path.to_path_buf(), begin, text });
+    }
+
+    // original file and byte position of a position in the temporary file
+    fn origin(
+        &self,
+        path: &PathBuf,
+        pos: usize
+    ) -> (PathBuf, usize) {
+        if *path == self.tmp_path && !self.pieces.is_empty() {
+            if let Some(piece) = self.pieces.iter().rev().find(|x| x.begin <= pos) {
+                return (piece.path.clone(), pos - piece.begin + piece.offset);
+            }
+        } else if *path == self.tmp_path {
+            if let Some(segment) = self.segments.iter().rev().find(|x| x.begin <= pos) {
+                return (segment.path.clone(), pos - segment.begin);
+            }
+        }
+        (path.clone(), pos)
+    }
+
+    // position in the temporary file as if the files were concatenated without the
+    // include files inlined, for Segment::unparsed
+    fn segment_pos(
+        &self,
+        segment: &Segment,
+        pos: usize
+    ) -> usize {
+        let Some(i) = self.pieces.iter().rposition(|x| x.begin <= pos && x.path == segment.path) else { return pos; };
+        let piece = &self.pieces[i];
+        let len = self.pieces.get(i + 1).map_or(pos, |x| x.begin) - piece.begin;
+        segment.begin + piece.offset + (pos - piece.begin).min(len)
+    }
+
+    // text of an original file
+    fn text(
+        &mut self,
+        path: &PathBuf
+    ) -> &str {
+        match self.segments.iter().find(|x| x.path == *path) {
+            Some(segment) => &segment.text,
+            None => self.includes.entry(path.clone())
+                .or_insert_with(|| read_to_string(path).unwrap_or_default())
+        }
+    }
+
+    // line and column (both 1-based) of a byte position in an original file
+    fn line_col(
+        &mut self,
+        path: &PathBuf,
+        pos: usize
+    ) -> (usize, usize) {
+        if !self.line_begins.contains_key(path) {
+            let text = self.text(path).as_bytes();
+            let begins = std::iter::once(0)
+                .chain(text.iter().enumerate().filter(|(_, &c)| c == CHAR_LF).map(|(i, _)| i + 1))
+                .collect();
+            self.line_begins.insert(path.clone(), begins);
+        }
+        let begins = &self.line_begins[path];
+        let line = begins.partition_point(|&x| x <= pos);
+        (line, pos - begins[line - 1] + 1)
+    }
+
+    // original file and line number of a node
+    fn resolve(
+        &mut self,
+        syntax_tree: &SyntaxTree,
+        locate: &Locate
+    ) -> Option<(PathBuf, usize)> {
+        let (path, pos) = syntax_tree.get_origin(locate)?;
+        let (path, pos) = self.origin(path, pos);
+        let (line, _) = self.line_col(&path, pos);
+        Some((path, line))
+    }
+
+    // range of a token in the original file
+    fn span(
+        &mut self,
+        syntax_tree: &SyntaxTree,
+        locate: &Locate
+    ) -> Option<Span> {
+        let (path, pos) = syntax_tree.get_origin(locate)?;
+        let (path, pos) = self.origin(path, pos);
+        let end = (pos + locate.len).min(self.text(&path).len());
+        let pos = pos.min(end);
+        let (line, column) = self.line_col(&path, pos);
+        let (end_line, end_column) = self.line_col(&path, end);
+        Some(Span { offset: pos, end_offset: end, line, column, end_line, end_column })
+    }
+}
+
+// ==== rewritten definition analyzer starts from here ====
+pub struct PortDef {
+    pub name: Name,
+    pub dir: String,
+    pub line: usize,
+    pub width: i32,
+    pub range: Option<(i32, i32)>,
+    pub type_name: Option<String>,
+    pub description: Option<String>,
+    // texts of the packed range and the data type, from which the width is
+    // evaluated again with other parameter values
+    range_expr: Option<(String, String)>,
+    type_expr: Option<String>
+}
+
+#[derive(Clone)]
+pub struct ParamDef {
+    pub name: String,
+    pub value: Option<String>,
+    pub description: Option<String>,
+    // a localparam, or a parameter in the body of a module with a parameter port
+    // list, which cannot be overridden
+    pub is_local: bool
+}
+
+// kind of a definition
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum DefKind {
+    Module,
+    Interface,
+    Program
+}
+
+impl DefKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DefKind::Module => "module",
+            DefKind::Interface => "interface",
+            DefKind::Program => "program"
+        }
+    }
+}
+
+// kind of an instantiated module, resolved later: that of its definition, a cell of
+// the Liberty files which is not defined, or unknown
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InstKind {
+    Def(DefKind),
+    Cell,
+    Unknown
+}
+
+impl InstKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            InstKind::Def(x) => x.as_str(),
+            InstKind::Cell => "cell",
+            InstKind::Unknown => "unknown"
+        }
+    }
+}
+
+pub struct InstDef {
+    pub mod_name: Name,
+    // symbol of the module name, by which the definition is looked up
+    pub mod_symbol: Symbol,
+    pub inst_name: Name,
+    pub params: Option<String>,
+    pub kind: InstKind,
+    pub file: Arc<Path>,
+    pub line: usize,
+    // style of the port connections ("named", "ordered", "wildcard" with ".*", or
+    // "none" without any) and their number
+    pub conn_style: &'static str,
+    pub conn_count: usize,
+    // location of the definition of the instantiated module, resolved later
+    pub resolved: Option<(Arc<Path>, usize)>,
+    // conditions of the generate branches containing the instantiation, joined
+    // with "&&"; only the parser finds them
+    pub cond: Option<String>,
+    // labeled generate blocks containing the instantiation, from the outermost one
+    pub scope: Vec<GenScope>
+}
+
+// labeled generate block; the block of a generate-for loop has its genvar, whose
+// values index the scope as in the hierarchical names of the simulators
+#[derive(Clone)]
+pub struct GenScope {
+    pub label: String,
+    pub genvar: Option<String>
+}
+
+impl GenScope {
+    // scope names joined with "." (e.g. "gen_lanes[i].gen_even")
+    pub fn format(
+        scope: &[GenScope]
+    ) -> String {
+        scope.iter()
+            .map(|x| match &x.genvar {
+                Some(genvar) => format!("{}[{}]", x.label, genvar),
+                None => x.label.clone()
+            })
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+// generate-for loop; the texts of the header are those of join_tokens
+struct GenerateLoop {
+    genvar: String,
+    label: Option<String>,
+    init: String,
+    cond: String,
+    step: String,
+    line: usize
+}
+
+// package declaration
+pub struct PackageDef {
+    pub name: String,
+    pub file: Arc<Path>,
+    pub line: usize,
+    // declarations in the package (parameters including localparams, type
+    // definitions, and functions)
+    pub params: Vec<ParamDef>,
+    pub typedefs: Vec<TypedefDef>,
+    pub functions: Vec<FunctionDef>
+}
+
+// type definition in a package; the type is the text of join_tokens
+pub struct TypedefDef {
+    pub name: String,
+    pub type_text: String,
+    pub line: usize
+}
+
+// function in a package; the return type is empty if it is implicit
+pub struct FunctionDef {
+    pub name: String,
+    pub return_type: String,
+    pub line: usize
+}
+
+// alias statement; the texts of the aliased nets are those of join_tokens
+struct AliasDef {
+    nets: Vec<String>,
+    line: usize
+}
+
+// continuous assignment; the texts of the assigned net and the expression are those
+// of join_tokens
+struct AssignDef {
+    lhs: String,
+    rhs: String,
+    line: usize
+}
+
+pub struct ModuleDef {
+    pub name: Name,
+    pub symbol: Symbol,
+    pub kind: DefKind,
+    pub file: Arc<Path>,
+    pub line: usize,
+    pub description: Option<String>,
+    // byte offsets of the beginning and the end (exclusive) of the declaration
+    span: Option<(usize, usize)>,
+    body_hash: u64,
+    pub params: Vec<ParamDef>,
+    pub ports: Vec<PortDef>,
+    pub insts: Vec<InstDef>,
+    generates: Vec<GenerateLoop>,
+    aliases: Vec<AliasDef>,
+    assigns: Vec<AssignDef>,
+    // locations (file and line) of the identical definitions merged into this one
+    pub also_defined_in: Vec<(Arc<Path>, usize)>,
+    // whether the body has continuous assignments, procedural blocks, gates, or aliases
+    pub has_logic: bool,
+    // declared nets and variables, and the references to the nets
+    nets: Vec<String>,
+    conns: Vec<conn::Connection>,
+    const_pins: Vec<conn::ConstPin>,
+    // packed widths of the ports, nets, and variables without unpacked dimensions,
+    // with those of their elements selected by an index, and the expressions
+    // connected to the ports of the instances
+    net_widths: HashMap<String, (i32, i32)>,
+    pin_exprs: Vec<conn::PinExpr>,
+    // constructs which are not interpreted as (line, message), e.g. a port whose
+    // width cannot be evaluated and falls back to 1
+    issues: Vec<(usize, String)>
+}
+
+impl ModuleDef {
+    // a module without any instance or logic, which is a placeholder
+    pub fn is_stub(&self) -> bool {
+        self.kind == DefKind::Module && self.insts.is_empty() && !self.has_logic
+    }
+
+    // hash of the normalized interface (parameters and ports)
+    pub fn iface_hash(&self) -> u64 {
+        let mut text = String::new();
+        for param in &self.params {
+            text.push_str(&format!("param {}={};", param.name, param.value.as_deref().unwrap_or("")));
+        }
+        for port in &self.ports {
+            text.push_str(&format!("port {}:{}:{};", port.name, port.dir, port.width));
+        }
+        fnv1a(text.as_bytes())
+    }
+}
+
+// 64-bit FNV-1a hash, which is stable across platforms and versions
+fn fnv1a(
+    bytes: &[u8]
+) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &x in bytes {
+        hash ^= x as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub struct FileResult {
+    pub path: PathBuf,
+    aliases: Vec<PathBuf>,
+    // number of the bytes treated by --sanitize
+    non_ascii: usize,
+    pub library: String,
+    pub unresolved_includes: Vec<PathBuf>,
+    parsed_until: Option<usize>,
+    missing_defs: Vec<String>,
+    pub defs: Vec<ModuleDef>,
+    pub packages: Vec<PackageDef>,
+    used_defines: Option<Vec<String>>,
+    full_tree: Option<String>,
+    macro_defs: Option<HashMap<String, Option<Define>>>,
+    macro_origins: Option<HashMap<String, MacroOrigin>>,
+    macro_delta: Option<Vec<MacroChange>>,
+    // time spent on the compilation unit of the file
+    pub elapsed: Duration
+}
+
+// instantiation of a module (parent module and instance)
+struct UsedBy<'a> {
+    parent: &'a ModuleDef,
+    inst: &'a InstDef
+}
+
+struct DefsState<'a> {
+    sources: &'a mut SourceMap,
+    names: &'a Interner,
+    overrides: &'a HashMap<String, String>,
+    typedefs: &'a mut HashMap<String, String>,
+    defs: Vec<ModuleDef>,
+    packages: Vec<PackageDef>,
+    is_input: bool,
+    port_width: i32,
+    port_range: Option<(i32, i32)>,
+    port_range_expr: Option<(String, String)>,
+    port_type_expr: Option<String>,
+    port_type: Option<String>,
+    // "range" or "data type" if the width of the ports cannot be evaluated
+    port_unresolved: Option<&'static str>,
+    port_doc: Option<String>,
+    // the current module has a parameter port list, which makes the parameters in
+    // its body local
+    param_ports: bool,
+    // packed width of a net or variable declaration and that of its element, None
+    // if it cannot be evaluated
+    decl_width: Option<(i32, i32)>,
+    // conditions of the generate branches containing each module instantiation,
+    // from the outermost one, keyed by the address of the node
+    gen_conds: HashMap<usize, Vec<String>>,
+    // labeled generate blocks containing each module instantiation, from the
+    // outermost one, keyed by the address of the node
+    gen_scopes: HashMap<usize, Vec<GenScope>>,
+    // parameter declarations in the packages, which do not belong to the modules,
+    // keyed by the address of the node
+    package_params: HashSet<usize>,
+    doc: DocComment
+}
+
+// module, interface, or program definition
+fn process_module_def(
+    syntax_tree: &SyntaxTree,
+    node: RefNode,
+    kind: DefKind,
+    s: &mut DefsState
+) {
+    s.param_ports = false;
+    let body_hash = fnv1a(get_token_str(syntax_tree, node.clone()).as_bytes());
+    let span = get_token_span(syntax_tree, node.clone(), s.sources);
+    let id = match kind {
+        DefKind::Interface => unwrap_node!(node, InterfaceIdentifier),
+        DefKind::Program => unwrap_node!(node, ProgramIdentifier),
+        DefKind::Module => unwrap_node!(node, ModuleIdentifier)
+    };
+    let Some(id) = id else { return; };
+    let Some(id) = get_identifier(id) else { return; };      
+    // Original string can be got by SyntaxTree::get_str(self, node: &RefNode)
+    let Some(name) = syntax_tree.get_str(&id) else { return; }; 
+    let Some((file, line)) = s.sources.resolve(syntax_tree, &id) else { return; };
+    // Declare the new module
+    let (name, symbol) = s.names.intern(name);
+    s.defs.push(ModuleDef {
+        name,
+        symbol,
+        kind,
+        file: s.names.path(&file),
+        line,
+        description: s.doc.text(),
+        span,
+        body_hash,
+        params: vec![],
+        ports: vec![],
+        insts: vec![],
+        generates: vec![],
+        aliases: vec![],
+        assigns: vec![],
+        also_defined_in: vec![],
+        has_logic: false,
+        nets: vec![],
+        conns: vec![],
+        const_pins: vec![],
+        net_widths: HashMap::new(),
+        pin_exprs: vec![],
+        issues: vec![]
+    });
+}
+
+// module, interface, or program instantiation; they cannot be distinguished by
+// the syntax, so the kind is resolved later by the name
+fn process_module_inst(
+    syntax_tree: &SyntaxTree,
+    node: RefNode,
+    s: &mut DefsState
+) {
+    let (cond, scope) = match node {
+        RefNode::ModuleInstantiation(x) => {
+            let key = x as *const ModuleInstantiation as usize;
+            (s.gen_conds.get(&key).map(|x| x.join("&&")), s.gen_scopes.get(&key).cloned().unwrap_or_default())
+        }
+        _ => (None, vec![])
+    };
+    // get the module name
+    let Some(id) = unwrap_node!(node.clone(), ModuleIdentifier, InterfaceIdentifier, ProgramIdentifier) else { return; };
+    let Some(id) = get_identifier(id) else { return; };      
+    let Some(mod_name) = syntax_tree.get_str(&id) else { return; }; 
+    // get the parameter override
+    let params = unwrap_node!(node.clone(), ParameterValueAssignment)
+        .map(|x| get_expr_str(syntax_tree, x));
+    let (conn_style, conn_count) = match unwrap_node!(node.clone(), ListOfPortConnections) {
+        Some(RefNode::ListOfPortConnections(x)) => port_conn_style(x),
+        _ => ("none", 0)
+    };
+    let count = node.clone().into_iter().filter(|x| matches!(x, RefNode::HierarchicalInstance(_))).count();
+    // get the instance name
+    let Some(id) = unwrap_node!(node, InstanceIdentifier) else { return; };
+    let Some(id) = get_identifier(id) else { return; };      
+    let Some(inst_name) = syntax_tree.get_str(&id) else { return; }; 
+    let Some((file, line)) = s.sources.resolve(syntax_tree, &id) else { return; };
+    let Some(def) = s.defs.last_mut() else { return; };
+    if count > 1 {
+        def.issues.push((line, format!("only the first of {} instances is reported: {}", count, inst_name)));
+    }
+    let (mod_name, mod_symbol) = s.names.intern(mod_name);
+    def.insts.push(InstDef {
+        mod_name,
+        mod_symbol,
+        inst_name: s.names.name(inst_name),
+        params,
+        kind: InstKind::Unknown,
+        file: s.names.path(&file),
+        line,
+        conn_style,
+        conn_count,
+        resolved: None,
+        cond,
+        scope
+    });
+}
+
+// style and number of the port connections of an instance; "()" has no connection
+fn port_conn_style(
+    node: &ListOfPortConnections
+) -> (&'static str, usize) {
+    match node {
+        ListOfPortConnections::Ordered(x) => {
+            let items = x.nodes.0.contents();
+            if items.len() == 1 && items[0].nodes.1.is_none() {
+                ("none", 0)
+            } else {
+                ("ordered", items.len())
+            }
+        }
+        ListOfPortConnections::Named(x) => {
+            let items = x.nodes.0.contents();
+            let style = if items.iter().any(|x| matches!(x, NamedPortConnection::Asterisk(_))) { "wildcard" } else { "named" };
+            (style, items.len())
+        }
+    }
+}
+
+// the module has a continuous assignment, a procedural block, a gate, or an alias
+fn set_has_logic(
+    s: &mut DefsState
+) {
+    if let Some(def) = s.defs.last_mut() {
+        def.has_logic = true;
+    }
+}
+
+// references to the nets in a block of the given kind, or in the port connections
+// of an instance
+fn process_conns(
+    syntax_tree: &SyntaxTree,
+    node: RefNode,
+    kind: Option<&str>,
+    s: &mut DefsState
+) {
+    let Some(def) = s.defs.last_mut() else { return; };
+    match kind {
+        Some(kind) => conn::process_block(syntax_tree, node, kind, s.sources, def),
+        None => {
+            let first = def.pin_exprs.len();
+            conn::process_pins(syntax_tree, node, s.sources, def);
+            for i in first..def.pin_exprs.len() {
+                let scope = eval::Scope { params: &def.params, typedefs: s.typedefs };
+                def.pin_exprs[i].width = eval::expr_width(&def.pin_exprs[i].expr, scope, &def.net_widths);
+            }
+        }
+    }
+}
+
+// data type of a net or variable declaration, whose width applies to the following
+// declaration assignments
+fn process_decl_type(
+    syntax_tree: &SyntaxTree,
+    data_type: &DataTypeOrImplicit,
+    s: &mut DefsState
+) {
+    let text = get_token_str(syntax_tree, RefNode::from(data_type));
+    let text = match data_type {
+        DataTypeOrImplicit::ImplicitDataType(_) => format!("logic {}", text),
+        DataTypeOrImplicit::DataType(_) => text
+    };
+    let params = s.defs.last().map(|x| x.params.as_slice()).unwrap_or_default();
+    let scope = eval::Scope { params, typedefs: s.typedefs };
+    s.decl_width = eval::eval_type(&text, scope).zip(eval::elem_width(&text, scope));
+}
+
+// width of a net or variable without unpacked dimensions
+fn process_decl_width(
+    syntax_tree: &SyntaxTree,
+    id: RefNode,
+    unpacked: bool,
+    s: &mut DefsState
+) {
+    let (Some(width), false) = (s.decl_width, unpacked) else { return; };
+    let Some(id) = get_identifier(id) else { return; };
+    let Some(id) = syntax_tree.get_str(&id) else { return; };
+    let Some(def) = s.defs.last_mut() else { return; };
+    def.net_widths.insert(String::from(id), width);
+}
+
+// parameter declaration
+fn process_param_def(
+    syntax_tree: &SyntaxTree,
+    node: RefNode,
+    description: Option<String>,
+    s: &mut DefsState
+) {
+    let Some(def) = s.defs.last_mut() else { return; };
+    // the parameters in the body are local if the module has a parameter port list
+    let in_ports = matches!(node, RefNode::ParameterPortList(_));
+    let mut local = !in_ports && s.param_ports;
+    for x in node {
+        let x = match x {
+            RefNode::LocalParameterDeclaration(_) => {
+                local = true;
+                continue;
+            }
+            RefNode::ParameterDeclaration(_) => {
+                local = !in_ports && s.param_ports;
+                continue;
+            }
+            RefNode::ParamAssignment(x) => x,
+            _ => continue
+        };
+        let Some(id) = get_identifier(RefNode::from(&x.nodes.0)) else { continue; };
+        let Some(name) = syntax_tree.get_str(&id) else { continue; };
+        // a parameter in the parameter port list is also visited as a declaration
+        if let Some(param) = def.params.iter_mut().find(|x| x.name == name) {
+            if param.description.is_none() {
+                param.description = description.clone();
+            }
+            continue;
+        }
+        let value = match s.overrides.get(name).filter(|_| !local) {
+            Some(x) => Some(x.clone()),
+            None => x.nodes.2.as_ref()
+                .map(|(_, x)| get_expr_str(syntax_tree, RefNode::from(x)))
+        };
+        def.params.push(ParamDef {
+            name: String::from(name),
+            value,
+            description: description.clone(),
+            is_local: local
+        });
+    }
+}
+
+// generate-for loop
+fn process_generate_loop(
+    syntax_tree: &SyntaxTree,
+    node: &LoopGenerateConstruct,
+    s: &mut DefsState
+) {
+    let (init, _, cond, _, step) = &node.nodes.1.nodes.1;
+    let Some(id) = get_identifier(RefNode::from(&init.nodes.1)) else { return; };
+    let Some(genvar) = syntax_tree.get_str(&id) else { return; };
+    let label = generate_block_label(syntax_tree, &node.nodes.2);
+    if let Some(label) = &label {
+        add_gen_scope(&node.nodes.2, label, Some(genvar), s);
+    }
+    let Some((_, line)) = s.sources.resolve(syntax_tree, &node.nodes.0.nodes.0) else { return; };
+    let text = |x: RefNode| get_expr_str(syntax_tree, x);
+    let Some(def) = s.defs.last_mut() else { return; };
+    def.generates.push(GenerateLoop {
+        genvar: String::from(genvar),
+        label,
+        init: text(RefNode::from(&init.nodes.3)),
+        cond: text(RefNode::from(cond)),
+        step: text(RefNode::from(step)),
+        line
+    });
+}
+
+// label of a generate block, before or after begin
+fn generate_block_label(
+    syntax_tree: &SyntaxTree,
+    block: &GenerateBlock
+) -> Option<String> {
+    let label = match block {
+        GenerateBlock::Multiple(x) => x.nodes.0.as_ref().map(|x| &x.0).or(x.nodes.2.as_ref().map(|x| &x.1)),
+        _ => None
+    };
+    label
+        .and_then(|x| get_identifier(RefNode::from(x)))
+        .and_then(|x| syntax_tree.get_str(&x))
+        .map(String::from)
+}
+
+// give the scope of a labeled generate block to the module instantiations in it,
+// after those of the outer blocks
+fn add_gen_scope(
+    block: &GenerateBlock,
+    label: &str,
+    genvar: Option<&str>,
+    s: &mut DefsState
+) {
+    for x in block {
+        if let RefNode::ModuleInstantiation(x) = x {
+            let scope = GenScope { label: String::from(label), genvar: genvar.map(String::from) };
+            s.gen_scopes.entry(x as *const ModuleInstantiation as usize).or_default().push(scope);
+        }
+    }
+}
+
+// generate-if or generate-case; the module instantiations in each branch are given
+// its condition, after those of the outer branches
+fn process_generate_cond(
+    syntax_tree: &SyntaxTree,
+    node: RefNode,
+    s: &mut DefsState
+) {
+    let text = |x: RefNode| get_expr_str(syntax_tree, x);
+    let mut branches: Vec<(String, &GenerateBlock)> = vec![];
+    match node {
+        RefNode::IfGenerateConstruct(x) => {
+            let cond = format!("({})", text(RefNode::from(&x.nodes.1.nodes.1)));
+            if let Some((_, block)) = &x.nodes.3 {
+                branches.push((format!("!{}", cond), block));
+            }
+            branches.push((cond, &x.nodes.2));
+        }
+        RefNode::CaseGenerateConstruct(x) => {
+            // the default branch is taken when none of the others is
+            let expr = format!("({})", text(RefNode::from(&x.nodes.1.nodes.1)));
+            let mut others = vec![];
+            for item in &x.nodes.2 {
+                if let CaseGenerateItem::Nondefault(item) = item {
+                    let cond = item.nodes.0.contents().into_iter()
+                        .map(|x| format!("{}==({})", expr, text(RefNode::from(x))))
+                        .collect::<Vec<_>>()
+                        .join("||");
+                    branches.push((format!("({})", cond), &item.nodes.2));
+                    others.push(cond);
+                }
+            }
+            for item in &x.nodes.2 {
+                if let CaseGenerateItem::Default(item) = item {
+                    if !others.is_empty() {
+                        branches.push((format!("!({})", others.join("||")), &item.nodes.2));
+                    }
+                }
+            }
+        }
+        _ => ()
+    }
+    for (cond, block) in branches {
+        for x in block {
+            if let RefNode::ModuleInstantiation(x) = x {
+                s.gen_conds.entry(x as *const ModuleInstantiation as usize).or_default().push(cond.clone());
+            }
+        }
+        if let Some(label) = generate_block_label(syntax_tree, block) {
+            add_gen_scope(block, &label, None, s);
+        }
+    }
+}
+
+// alias statement
+fn process_net_alias(
+    syntax_tree: &SyntaxTree,
+    node: &NetAlias,
+    s: &mut DefsState
+) {
+    let Some((_, line)) = s.sources.resolve(syntax_tree, &node.nodes.0.nodes.0) else { return; };
+    let text = |x: RefNode| get_expr_str(syntax_tree, x);
+    let mut nets = vec![text(RefNode::from(&node.nodes.1))];
+    nets.extend(node.nodes.3.contents().into_iter().map(|x| text(RefNode::from(x))));
+    let Some(def) = s.defs.last_mut() else { return; };
+    def.aliases.push(AliasDef { nets, line });
+}
+
+// continuous assignment, which may assign several nets
+fn process_assign(
+    syntax_tree: &SyntaxTree,
+    node: &ContinuousAssign,
+    s: &mut DefsState
+) {
+    let mut assigns = vec![];
+    for x in node {
+        let (lhs, rhs) = match x {
+            RefNode::NetAssignment(x) => (RefNode::from(&x.nodes.0), RefNode::from(&x.nodes.2)),
+            RefNode::VariableAssignment(x) => (RefNode::from(&x.nodes.0), RefNode::from(&x.nodes.2)),
+            _ => continue
+        };
+        let Some(id) = get_identifier(lhs.clone()) else { continue; };
+        let Some((_, line)) = s.sources.resolve(syntax_tree, &id) else { continue; };
+        assigns.push(AssignDef { lhs: get_expr_str(syntax_tree, lhs), rhs: get_expr_str(syntax_tree, rhs), line });
+    }
+    let Some(def) = s.defs.last_mut() else { return; };
+    def.assigns.extend(assigns);
+}
+
+// type definition, which is registered with its package name as well if any
+fn process_typedef(
+    syntax_tree: &SyntaxTree,
+    node: &TypeDeclaration,
+    package: Option<&str>,
+    s: &mut DefsState
+) -> Option<TypedefDef> {
+    let TypeDeclaration::DataType(x) = node else { return None; };
+    let id = get_identifier(RefNode::from(&x.nodes.2))?;
+    let name = syntax_tree.get_str(&id)?;
+    let text = get_token_str(syntax_tree, RefNode::from(&x.nodes.1));
+    if let Some(package) = package {
+        s.typedefs.insert(format!("{}::{}", package, name), text.clone());
+    }
+    s.typedefs.insert(String::from(name), text.clone());
+    let (_, line) = s.sources.resolve(syntax_tree, &id)?;
+    Some(TypedefDef { name: String::from(name), type_text: get_expr_str(syntax_tree, RefNode::from(&x.nodes.1)), line })
+}
+
+// package declaration; the type definitions and the parameters in it are
+// registered with the package name, the latter with the values evaluated if possible
+fn process_package(
+    syntax_tree: &SyntaxTree,
+    node: &PackageDeclaration,
+    s: &mut DefsState
+) {
+    let Some(id) = get_identifier(RefNode::from(&node.nodes.3)) else { return; };
+    let Some(package) = syntax_tree.get_str(&id) else { return; };
+    let Some((file, line)) = s.sources.resolve(syntax_tree, &id) else { return; };
+    let mut def = PackageDef {
+        name: String::from(package),
+        file: s.names.path(&file),
+        line,
+        params: vec![],
+        typedefs: vec![],
+        functions: vec![]
+    };
+    for (_, item) in &node.nodes.6 {
+        let PackageItem::PackageOrGenerateItemDeclaration(x) = item else { continue; };
+        let decl = match x.as_ref() {
+            PackageOrGenerateItemDeclaration::ParameterDeclaration(x) => {
+                s.package_params.insert(&x.0 as *const ParameterDeclaration as usize);
+                RefNode::from(&x.0)
+            }
+            PackageOrGenerateItemDeclaration::LocalParameterDeclaration(x) => RefNode::from(&x.0),
+            PackageOrGenerateItemDeclaration::DataDeclaration(x) => {
+                if let DataDeclaration::TypeDeclaration(x) = x.as_ref() {
+                    def.typedefs.extend(process_typedef(syntax_tree, x, Some(package), s));
+                }
+                continue;
+            }
+            PackageOrGenerateItemDeclaration::FunctionDeclaration(x) => {
+                let (ret, id) = match &x.nodes.2 {
+                    FunctionBodyDeclaration::WithoutPort(x) => (RefNode::from(&x.nodes.0), &x.nodes.2),
+                    FunctionBodyDeclaration::WithPort(x) => (RefNode::from(&x.nodes.0), &x.nodes.2)
+                };
+                let Some(id) = get_identifier(RefNode::from(id)) else { continue; };
+                let Some(name) = syntax_tree.get_str(&id) else { continue; };
+                let Some((_, line)) = s.sources.resolve(syntax_tree, &id) else { continue; };
+                def.functions.push(FunctionDef { name: String::from(name), return_type: get_expr_str(syntax_tree, ret), line });
+                continue;
+            }
+            _ => continue
+        };
+        for x in decl {
+            let RefNode::ParamAssignment(x) = x else { continue; };
+            let Some(id) = get_identifier(RefNode::from(&x.nodes.0)) else { continue; };
+            let Some(name) = syntax_tree.get_str(&id) else { continue; };
+            let Some((_, text)) = x.nodes.2.as_ref() else { continue; };
+            let text = get_expr_str(syntax_tree, RefNode::from(text));
+            let value = eval::eval(&text, eval::Scope { params: &def.params, typedefs: s.typedefs }).map_or(text, |x| x.to_string());
+            s.typedefs.insert(format!("{}::{}", package, name), value.clone());
+            s.typedefs.insert(String::from(name), value.clone());
+            def.params.push(ParamDef { name: String::from(name), value: Some(value), description: None, is_local: true });
+        }
+    }
+    s.packages.push(def);
+}
+
+// port definition (direction and width)
+fn process_port_def(
+    syntax_tree: &SyntaxTree,
+    node: RefNode,
+    s: &mut DefsState
+) {
+    // the ports in a declaration share its description
+    s.port_doc = s.doc.text();
+    'check_direction1: {
+        let Some(id) = unwrap_node!(node.clone(), PortDirection) else { break 'check_direction1; };
+        let Some(id) = get_keyword(id) else { break 'check_direction1; };      
+        let Some(id) = syntax_tree.get_str(&id) else { break 'check_direction1; }; 
+        s.is_input = id == "input";
+        s.port_width = 1;
+        s.port_range = None;
+        s.port_type = None;
+        s.port_range_expr = None;
+        s.port_type_expr = None;
+        s.port_unresolved = None;
+    }
+    'check_direction2: {
+        let Some(_) = unwrap_node!(node.clone(), InputDeclaration) else { break 'check_direction2; };
+        s.is_input = true;
+        s.port_width = 1;
+        s.port_range = None;
+        s.port_type = None;
+        s.port_range_expr = None;
+        s.port_type_expr = None;
+        s.port_unresolved = None;
+    }
+    'check_direction3: {
+        let Some(_) = unwrap_node!(node.clone(), OutputDeclaration) else { break 'check_direction3; };
+        s.is_input = false;
+        s.port_width = 1;
+        s.port_range = None;
+        s.port_type = None;
+        s.port_range_expr = None;
+        s.port_type_expr = None;
+        s.port_unresolved = None;
+    }
+    'check_range: {
+        let Some(RefNode::ConstantRange(x)) = unwrap_node!(node.clone(), ConstantRange) else { break 'check_range; };
+        let (msb, _, lsb) = &x.nodes;
+        let msb = get_token_str(syntax_tree, RefNode::from(msb));
+        let lsb = get_token_str(syntax_tree, RefNode::from(lsb));
+        let params = s.defs.last().map(|x| x.params.as_slice()).unwrap_or_default();
+        s.port_range = eval::eval_range(&msb, &lsb, eval::Scope { params, typedefs: s.typedefs });
+        s.port_width = eval::range_width(s.port_range);
+        s.port_range_expr = Some((msb, lsb));
+        s.port_unresolved = s.port_range.is_none().then_some("range");
+    }
+    'check_type: {
+        let Some(x) = unwrap_node!(node.clone(), DataType) else { break 'check_type; };
+        let text = get_token_str(syntax_tree, x);
+        let params = s.defs.last().map(|x| x.params.as_slice()).unwrap_or_default();
+        let Some(width) = eval::eval_type(&text, eval::Scope { params, typedefs: s.typedefs }) else {
+            s.port_unresolved = s.port_unresolved.or(Some("data type"));
+            break 'check_type;
+        };
+        s.port_width = width;
+        s.port_unresolved = None;
+        s.port_type = eval::type_name(&text);
+        s.port_type_expr = Some(text.clone());
+        if !eval::is_integer_type(&text) {
+            s.port_range = None;
+        }
+    }
+    for x in node {
+        if let RefNode::PortIdentifier(x) = x {
+            process_port_ident(syntax_tree, RefNode::from(x), s);
+        }
+    }
+}
+
+// port identifier
+fn process_port_ident(
+    syntax_tree: &SyntaxTree,
+    node: RefNode,
+    s: &mut DefsState
+) {
+    let Some(id) = get_identifier(node) else { return; };
+    let Some((_, line)) = s.sources.resolve(syntax_tree, &id) else { return; };
+    let Some(id) = syntax_tree.get_str(&id) else { return; };
+    let dir = if s.is_input { "input" } else { "output" };
+    let Some(def) = s.defs.last_mut() else { return; };
+    if let Some(kind) = s.port_unresolved {
+        def.issues.push((line, format!("cannot evaluate the {} of port {}", kind, id)));
+    } else {
+        let scope = eval::Scope { params: &def.params, typedefs: s.typedefs };
+        if let Some(elem) = s.port_type_expr.as_ref().map_or(Some(1), |x| eval::elem_width(x, scope)) {
+            def.net_widths.insert(String::from(id), (s.port_width, elem));
+        }
+    }
+    def.ports.push(PortDef {
+        name: s.names.name(id),
+        dir: String::from(dir),
+        line,
+        width: s.port_width,
+        range: s.port_range,
+        type_name: s.port_type.clone(),
+        description: s.port_doc.clone(),
+        range_expr: s.port_range_expr.clone(),
+        type_expr: s.port_type_expr.clone()
+    });
+}
+
+fn analyze_defs(
+    syntax_tree: &SyntaxTree,
+    sources: &mut SourceMap,
+    overrides: &HashMap<String, String>,
+    typedefs: &mut HashMap<String, String>,
+    names: &Interner
+) -> (Vec<ModuleDef>, Vec<PackageDef>) {
+    let mut s = DefsState {
+        sources,
+        names,
+        overrides,
+        typedefs,
+        defs: vec![],
+        packages: vec![],
+        is_input: true,
+        port_width: 1,
+        port_range: None,
+        port_range_expr: None,
+        port_type_expr: None,
+        port_type: None,
+        port_unresolved: None,
+        port_doc: None,
+        param_ports: false,
+        decl_width: None,
+        gen_conds: HashMap::new(),
+        gen_scopes: HashMap::new(),
+        package_params: HashSet::new(),
+        doc: DocComment::default()
+    };
+    // &SyntaxTree is iterable
+    for node in syntax_tree {
+        // The type of each node is RefNode
+        match node {
+            RefNode::ModuleDeclarationNonansi(x) => {
+                // unwrap_node! gets the nearest ModuleIdentifier from x
+                process_module_def(syntax_tree, RefNode::from(x), DefKind::Module, &mut s);
+            }
+            RefNode::ModuleDeclarationAnsi(x) => {
+                process_module_def(syntax_tree, RefNode::from(x), DefKind::Module, &mut s);
+            }
+            RefNode::InterfaceDeclarationNonansi(x) => {
+                process_module_def(syntax_tree, RefNode::from(x), DefKind::Interface, &mut s);
+            }
+            RefNode::InterfaceDeclarationAnsi(x) => {
+                process_module_def(syntax_tree, RefNode::from(x), DefKind::Interface, &mut s);
+            }
+            RefNode::ProgramDeclarationNonansi(x) => {
+                process_module_def(syntax_tree, RefNode::from(x), DefKind::Program, &mut s);
+            }
+            RefNode::ProgramDeclarationAnsi(x) => {
+                process_module_def(syntax_tree, RefNode::from(x), DefKind::Program, &mut s);
+            }
+            RefNode::ParameterPortList(x) => {
+                process_param_def(syntax_tree, RefNode::from(x), None, &mut s);
+                s.param_ports = true;
+            }
+            RefNode::ParameterDeclaration(x) if s.package_params.contains(&(x as *const ParameterDeclaration as usize)) => (),
+            RefNode::ParameterDeclaration(x) => {
+                let description = s.doc.text();
+                process_param_def(syntax_tree, RefNode::from(x), description, &mut s);
+            }
+            RefNode::LoopGenerateConstruct(x) => {
+                process_generate_loop(syntax_tree, x, &mut s);
+            }
+            RefNode::IfGenerateConstruct(_) | RefNode::CaseGenerateConstruct(_) => {
+                process_generate_cond(syntax_tree, node, &mut s);
+            }
+            RefNode::PackageDeclaration(x) => {
+                process_package(syntax_tree, x, &mut s);
+            }
+            RefNode::TypeDeclaration(x) => {
+                let _ = process_typedef(syntax_tree, x, None, &mut s);
+            }
+            RefNode::ModuleInstantiation(x) => {
+                process_module_inst(syntax_tree, RefNode::from(x), &mut s);
+                process_conns(syntax_tree, RefNode::from(x), None, &mut s);
+            }
+            RefNode::InterfaceInstantiation(x) => {
+                process_module_inst(syntax_tree, RefNode::from(x), &mut s);
+                process_conns(syntax_tree, RefNode::from(x), None, &mut s);
+            }
+            RefNode::ProgramInstantiation(x) => {
+                process_module_inst(syntax_tree, RefNode::from(x), &mut s);
+                process_conns(syntax_tree, RefNode::from(x), None, &mut s);
+            }
+            RefNode::ContinuousAssign(x) => {
+                set_has_logic(&mut s);
+                process_assign(syntax_tree, x, &mut s);
+                process_conns(syntax_tree, RefNode::from(x), Some("assign"), &mut s);
+            }
+            RefNode::AlwaysConstruct(x) => {
+                set_has_logic(&mut s);
+                let kind = get_node_str(syntax_tree, RefNode::from(&x.nodes.0)).unwrap_or("always").trim();
+                process_conns(syntax_tree, RefNode::from(x), Some(kind), &mut s);
+            }
+            RefNode::InitialConstruct(x) => {
+                set_has_logic(&mut s);
+                process_conns(syntax_tree, RefNode::from(x), Some("initial"), &mut s);
+            }
+            RefNode::FinalConstruct(x) => {
+                set_has_logic(&mut s);
+                process_conns(syntax_tree, RefNode::from(x), Some("final"), &mut s);
+            }
+            RefNode::NetAlias(x) => {
+                set_has_logic(&mut s);
+                process_net_alias(syntax_tree, x, &mut s);
+            }
+            RefNode::GateInstantiation(_) => {
+                set_has_logic(&mut s);
+            }
+            RefNode::NetDeclaration(NetDeclaration::NetType(x)) => {
+                process_decl_type(syntax_tree, &x.nodes.3, &mut s);
+            }
+            RefNode::DataDeclaration(DataDeclaration::Variable(x)) => {
+                process_decl_type(syntax_tree, &x.nodes.3, &mut s);
+            }
+            // the width of a user-defined net type is not evaluated
+            RefNode::NetDeclaration(_) => {
+                s.decl_width = None;
+            }
+            RefNode::NetDeclAssignment(x) => {
+                if x.nodes.2.is_some() {
+                    set_has_logic(&mut s);
+                }
+                process_decl_width(syntax_tree, RefNode::from(&x.nodes.0), !x.nodes.1.is_empty(), &mut s);
+                if let Some(def) = s.defs.last_mut() {
+                    let assignment = x.nodes.2.as_ref().map(|(_, x)| RefNode::from(x));
+                    conn::process_net_decl(syntax_tree, RefNode::from(&x.nodes.0), assignment, s.sources, def);
+                }
+            }
+            RefNode::VariableDeclAssignment(VariableDeclAssignment::Variable(x)) => {
+                process_decl_width(syntax_tree, RefNode::from(&x.nodes.0), !x.nodes.1.is_empty(), &mut s);
+                if let Some(def) = s.defs.last_mut() {
+                    conn::process_net_decl(syntax_tree, RefNode::from(&x.nodes.0), None, s.sources, def);
+                }
+            }
+            RefNode::AnsiPortDeclaration(x) => {
+                process_port_def(syntax_tree, RefNode::from(x), &mut s);
+            }
+            RefNode::PortDeclaration(x) => {
+                process_port_def(syntax_tree, RefNode::from(x), &mut s);
+            }
+            // whitespace and comments are also visited as tokens
+            RefNode::Locate(x) => {
+                let text = syntax_tree.get_str(x).unwrap_or_default();
+                if text.starts_with("//") || text.starts_with("/*") {
+                    s.doc.comment(text);
+                } else if text.trim().is_empty() {
+                    s.doc.whitespace(text);
+                } else {
+                    s.doc.token();
+                }
+            }
+            _ => (),
+        }
+    }
+    (s.defs, s.packages)
+}
+
+// options of analyze_file; the defaults are those of the command line tool
+#[derive(Default)]
+pub struct Options {
+    pub includes: Vec<PathBuf>,
+    pub ignore_include: bool,
+    pub allow_incomplete: bool,
+    // treatment of non-ASCII characters as --sanitize ("replace" if empty)
+    pub sanitize: String,
+    // parameter values applied to the parameter of every module (as -G)
+    pub overrides: HashMap<String, String>
+}
+
+// analyze a file as a compilation unit of its own, starting from the given defines;
+// the identifiers and the file paths of the result are interned by the given interner,
+// which can be shared by the files analyzed together
+pub fn analyze_file(
+    path: &Path,
+    defines: &Defines,
+    options: &Options,
+    names: &Interner
+) -> Result<FileResult, Error> {
+    let start = Instant::now();
+    let (text, non_ascii) = read_source(path, &options.sanitize).map_err(|x| Error::File {
+        source: std::io::Error::other(x),
+        path: path.to_path_buf()
+    })?;
+    let (syntax_tree, _) = parse_sv_str(&text, VFS_UNIT_PATH, defines, &options.includes, options.ignore_include, options.allow_incomplete)?;
+    let mut sources = SourceMap::new(Path::new(VFS_UNIT_PATH));
+    sources.add(path, 0, text);
+    let (defs, packages) = analyze_defs(&syntax_tree, &mut sources, &options.overrides, &mut HashMap::new(), names);
+    Ok(FileResult {
+        path: path.to_path_buf(),
+        aliases: vec![],
+        non_ascii,
+        library: String::new(),
+        unresolved_includes: vec![],
+        parsed_until: None,
+        missing_defs: vec![],
+        defs,
+        packages,
+        used_defines: None,
+        full_tree: None,
+        macro_defs: None,
+        macro_origins: None,
+        macro_delta: None,
+        elapsed: start.elapsed()
+    })
+}
+
+// definitions in a source text, with the parameter values applied as -G
+#[cfg(test)]
+fn analyze_text(
+    text: &str,
+    overrides: &HashMap<String, String>
+) -> Vec<ModuleDef> {
+    let (syntax_tree, _) = parse_sv_str(text, VFS_UNIT_PATH, &HashMap::new(), &[] as &[PathBuf], false, false).unwrap();
+    let mut sources = SourceMap::new(Path::new(VFS_UNIT_PATH));
+    sources.add(Path::new("test.sv"), 0, String::from(text));
+    analyze_defs(&syntax_tree, &mut sources, overrides, &mut HashMap::new(), &Interner::new()).0
+}
+
+// show the members of the archives as "<archive>/<member>" instead of the extracted files
+fn label_archive_members(
+    results: &mut [FileResult],
+    unit_scopes: &mut [unit::UnitScope],
+    names: &Interner
+) {
+    let label = |x: &Path| archive::label(x).map(|x| names.path(&x));
+    for result in results.iter_mut() {
+        if let Some(x) = archive::label(&result.path) {
+            result.path = x;
+        }
+        for def in &mut result.defs {
+            def.file = label(&def.file).unwrap_or_else(|| def.file.clone());
+            for inst in &mut def.insts {
+                inst.file = label(&inst.file).unwrap_or_else(|| inst.file.clone());
+            }
+        }
+        for package in &mut result.packages {
+            package.file = label(&package.file).unwrap_or_else(|| package.file.clone());
+        }
+    }
+    for scope in unit_scopes.iter_mut() {
+        for path in scope.files.iter_mut().chain(scope.decls.iter_mut().map(|x| &mut x.file)) {
+            if let Some(x) = archive::label(path) {
+                *path = x;
+            }
+        }
+    }
+}
+
+// remove the definitions of the library files which are not instantiated from the
+// other files, directly or via other library definitions, or which are defined in
+// the other files as well; the library files left empty are removed
+fn prune_lib_defs(
+    results: &mut Vec<FileResult>,
+    files: &[PathBuf],
+    lib_files: &[PathBuf]
+) {
+    let canonical = |x: &Path| canonicalize(x).unwrap_or_else(|_| x.to_path_buf());
+    // a file given both directly and as a library file is not a library file
+    let files: HashSet<PathBuf> = files.iter().map(|x| canonical(x)).collect();
+    let lib_files: HashSet<PathBuf> = lib_files.iter().map(|x| canonical(x)).filter(|x| !files.contains(x)).collect();
+    let is_lib: Vec<bool> = results.iter().map(|x| lib_files.contains(&canonical(&x.path))).collect();
+    let mut defined: HashSet<Symbol> = HashSet::new();
+    let mut pending: Vec<Symbol> = vec![];
+    let mut lib_defs: HashMap<Symbol, Vec<&ModuleDef>> = HashMap::new();
+    for (result, &is_lib) in results.iter().zip(&is_lib) {
+        for def in &result.defs {
+            if is_lib {
+                lib_defs.entry(def.symbol).or_default().push(def);
+            } else {
+                defined.insert(def.symbol);
+                pending.extend(def.insts.iter().map(|x| x.mod_symbol));
+            }
+        }
+    }
+    let mut used: HashSet<Symbol> = HashSet::new();
+    while let Some(symbol) = pending.pop() {
+        if defined.contains(&symbol) || used.contains(&symbol) {
+            continue;
+        }
+        for def in lib_defs.get(&symbol).into_iter().flatten() {
+            pending.extend(def.insts.iter().map(|x| x.mod_symbol));
+        }
+        used.insert(symbol);
+    }
+    for (result, &is_lib) in results.iter_mut().zip(&is_lib) {
+        if is_lib {
+            result.defs.retain(|x| used.contains(&x.symbol));
+        }
+    }
+    let mut i = 0;
+    results.retain(|x| {
+        i += 1;
+        !is_lib[i - 1] || !x.defs.is_empty() || !x.packages.is_empty()
+    });
+}
+
+// merge the definitions identical to an earlier one of the same name, e.g. a module
+// copied into several files, recording where they are also defined; the
+// definitions with the same body hash are compared byte by byte if possible
+fn merge_duplicate_defs(
+    results: &mut [FileResult],
+    provider: &dyn source::SourceProvider
+) {
+    let mut firsts: HashMap<(Symbol, DefKind, u64), (usize, usize)> = HashMap::new();
+    let mut merged: Vec<((usize, usize), (usize, usize))> = vec![];
+    for (i, result) in results.iter().enumerate() {
+        for (j, def) in result.defs.iter().enumerate() {
+            let key = (def.symbol, def.kind, def.body_hash);
+            match firsts.get(&key) {
+                Some(&first) if same_def_text(provider, &results[first.0].defs[first.1], def) => merged.push((first, (i, j))),
+                Some(_) => (),
+                None => {
+                    firsts.insert(key, (i, j));
+                }
+            }
+        }
+    }
+    for &(first, (i, j)) in &merged {
+        let location = (results[i].defs[j].file.clone(), results[i].defs[j].line);
+        results[first.0].defs[first.1].also_defined_in.push(location);
+    }
+    for (i, result) in results.iter_mut().enumerate() {
+        let mut j = 0;
+        result.defs.retain(|_| {
+            j += 1;
+            !merged.iter().any(|x| x.1 == (i, j - 1))
+        });
+    }
+}
+
+// whether the source texts of two definitions are the same; a definition whose
+// text cannot be read is reported and taken as different
+fn same_def_text(
+    provider: &dyn source::SourceProvider,
+    x: &ModuleDef,
+    y: &ModuleDef
+) -> bool {
+    let text = |def: &ModuleDef| {
+        let text = def.span.and_then(|(begin, end)| provider.read(&def.file).ok()?.get(begin..end).map(|x| x.to_vec()));
+        if text.is_none() {
+            warn!("failed to read the definition of {}: {:?}", def.name, def.file);
+        }
+        text
+    };
+    match (text(x), text(y)) {
+        (Some(x), Some(y)) => x == y,
+        _ => false
+    }
+}
+
+// tag each instantiation with the kind and the location of the instantiated
+// definition (the first one if defined more than once), or "cell" for a cell of the
+// Liberty files which is not defined
+fn resolve_inst_kinds(
+    results: &mut [FileResult],
+    cells: &HashSet<String>
+) {
+    let mut kinds: HashMap<Symbol, (DefKind, Arc<Path>, usize)> = HashMap::new();
+    for def in results.iter().flat_map(|x| &x.defs) {
+        kinds.entry(def.symbol).or_insert_with(|| (def.kind, def.file.clone(), def.line));
+    }
+    for inst in results.iter_mut().flat_map(|x| &mut x.defs).flat_map(|x| &mut x.insts) {
+        if let Some((kind, file, line)) = kinds.get(&inst.mod_symbol) {
+            inst.kind = InstKind::Def(*kind);
+            inst.resolved = Some((file.clone(), *line));
+        } else if cells.contains(&*inst.mod_name) {
+            inst.kind = InstKind::Cell;
+        }
+    }
+}
+
+// find where each module is instantiated
+fn find_used_by(
+    results: &[FileResult]
+) -> HashMap<Symbol, Vec<UsedBy<'_>>> {
+    let mut used_by: HashMap<Symbol, Vec<UsedBy>> = HashMap::new();
+    for parent in results.iter().flat_map(|x| &x.defs) {
+        for inst in &parent.insts {
+            used_by.entry(inst.mod_symbol).or_default().push(UsedBy { parent, inst });
+        }
+    }
+    used_by
+}
+
+// ==== rewritten definition analyzer ends here ====
+
+// level of a node in the subtrees shown by the filter, or outside or hidden by it
+#[derive(Clone, Copy)]
+enum TreeLevel {
+    Outside,
+    Inside(usize),
+    Hidden
+}
+
+// selection of the nodes shown in the full syntax tree
+struct TreeFilter<'a> {
+    kinds: &'a [String],
+    max_depth: Option<usize>,
+    include_whitespace: bool,
+    levels: Vec<TreeLevel>
+}
+
+impl<'a> TreeFilter<'a> {
+    fn new(opt: &'a Opt) -> Self {
+        // without the filter, the whole tree is shown from its root
+        let root = if opt.tree_filter.is_empty() { TreeLevel::Inside(0) } else { TreeLevel::Outside };
+        TreeFilter {
+            kinds: &opt.tree_filter,
+            max_depth: opt.max_depth,
+            include_whitespace: opt.include_whitespace,
+            levels: vec![root]
+        }
+    }
+
+    fn level(&self) -> Option<usize> {
+        match self.levels.last() {
+            Some(TreeLevel::Inside(x)) => Some(*x),
+            _ => None
+        }
+    }
+
+    // whether the current node is shown
+    fn is_shown(&self) -> bool {
+        self.level().is_some_and(|x| self.max_depth.is_none_or(|y| x <= y))
+    }
+
+    // enter a node and return its level (1 for a top-level or matched node) if it is
+    // in the subtrees of interest, regardless of the maximum depth
+    fn enter(&mut self, node: &RefNode) -> Option<usize> {
+        let level = match (self.levels.last(), node) {
+            (Some(TreeLevel::Hidden), _) => TreeLevel::Hidden,
+            (_, RefNode::WhiteSpace(_)) if !self.include_whitespace => TreeLevel::Hidden,
+            (Some(TreeLevel::Inside(x)), _) => TreeLevel::Inside(x + 1),
+            (_, RefNode::Locate(_)) if self.kinds.iter().any(|x| x == "Token") => TreeLevel::Inside(1),
+            (_, RefNode::Locate(_)) => TreeLevel::Outside,
+            (_, x) if self.kinds.contains(&x.to_string()) => TreeLevel::Inside(1),
+            _ => TreeLevel::Outside
+        };
+        self.levels.push(level);
+        self.level()
+    }
+
+    // leave a node and return whether it was shown
+    fn leave(&mut self) -> bool {
+        let shown = self.is_shown();
+        self.levels.pop();
+        shown
+    }
+}
+
+// node of the full syntax tree in JSON; `text` is given only for tokens, and
+// the position of a node is the one of its first token and the end is the one
+// of its last token
+#[derive(Default, Serialize)]
+struct TreeNode {
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_column: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_offset: Option<usize>,
+    children: Vec<TreeNode>
+}
+
+fn format_full_tree_json(
+    syntax_tree: &SyntaxTree,
+    mut filter: TreeFilter,
+    sources: &mut SourceMap
+) -> String {
+    let mut stack = vec![TreeNode { kind: String::from("SyntaxTree"), ..TreeNode::default() }];
+    for node in syntax_tree.into_iter().event() {
+        match node {
+            NodeEvent::Enter(RefNode::Locate(locate)) => {
+                let level = filter.enter(&RefNode::Locate(locate));
+                if level.is_none() {
+                    continue;
+                }
+                // the nodes below the maximum depth still extend the span of the shown ones
+                let span = sources.span(syntax_tree, locate);
+                let line = span.map_or(locate.line as usize, |x| x.line);
+                for x in stack.iter_mut() {
+                    if x.line.is_none() {
+                        x.line = Some(line);
+                        x.column = span.map(|x| x.column);
+                        x.offset = span.map(|x| x.offset);
+                    }
+                    x.end_line = span.map(|x| x.end_line);
+                    x.end_column = span.map(|x| x.end_column);
+                    x.end_offset = span.map(|x| x.end_offset);
+                }
+                if filter.is_shown() {
+                    stack.push(TreeNode {
+                        kind: String::from("Token"),
+                        text: syntax_tree.get_str(locate).map(String::from),
+                        line: Some(line),
+                        column: span.map(|x| x.column),
+                        offset: span.map(|x| x.offset),
+                        end_line: span.map(|x| x.end_line),
+                        end_column: span.map(|x| x.end_column),
+                        end_offset: span.map(|x| x.end_offset),
+                        children: vec![]
+                    });
+                }
+            }
+            NodeEvent::Enter(x) => {
+                filter.enter(&x);
+                if filter.is_shown() {
+                    stack.push(TreeNode { kind: x.to_string(), ..TreeNode::default() });
+                }
+            }
+            NodeEvent::Leave(_) => {
+                if !filter.leave() {
+                    continue;
+                }
+                let Some(x) = stack.pop() else { continue; };
+                let Some(parent) = stack.last_mut() else { continue; };
+                parent.children.push(x);
+            }
+        }
+    }
+    serde_json::to_string(&stack[0]).unwrap_or_default()
+}
+
+fn format_full_tree(
+    syntax_tree: &SyntaxTree,
+    mut filter: TreeFilter,
+    sources: &mut SourceMap
+) -> String {
+    let mut ret = String::new();
+    // whitespace nodes are not shown themselves, but their tokens are
+    let mut whitespace = 0;
+    for node in syntax_tree.into_iter().event() {
+        match node {
+            NodeEvent::Enter(RefNode::Locate(locate)) => {
+                let Some(level) = filter.enter(&RefNode::Locate(locate)) else { continue; };
+                if !filter.is_shown() {
+                    continue;
+                }
+                let depth = level + 2 - whitespace;
+                ret.push_str(&format!("{}- Token: {}\n",
+                                      "  ".repeat(depth),
+                                      escape_str(syntax_tree.get_str(locate).unwrap())));
+                // the position in the original file, which differs from the one
+                // in the preprocessed text for a macro or an included file
+                let span = sources.span(syntax_tree, locate);
+                ret.push_str(&format!("{}  Line: {}\n",
+                                      "  ".repeat(depth),
+                                      span.map_or(locate.line as usize, |x| x.line)));
+                if let Some(span) = span {
+                    ret.push_str(&format!("{}  Column: {}\n", "  ".repeat(depth), span.column));
+                    ret.push_str(&format!("{}  Offset: {}\n", "  ".repeat(depth), span.offset));
+                    ret.push_str(&format!("{}  EndLine: {}\n", "  ".repeat(depth), span.end_line));
+                    ret.push_str(&format!("{}  EndColumn: {}\n", "  ".repeat(depth), span.end_column));
+                    ret.push_str(&format!("{}  EndOffset: {}\n", "  ".repeat(depth), span.end_offset));
+                }
+            }
+            NodeEvent::Enter(RefNode::WhiteSpace(x)) => {
+                if filter.enter(&RefNode::WhiteSpace(x)).is_some() {
+                    whitespace += 1;
+                }
+            }
+            NodeEvent::Leave(RefNode::WhiteSpace(_)) => {
+                if filter.level().is_some() {
+                    whitespace -= 1;
+                }
+                filter.leave();
+            }
+            NodeEvent::Enter(x) => {
+                let Some(level) = filter.enter(&x) else { continue; };
+                if filter.is_shown() {
+                    ret.push_str(&format!("{}- {}:\n",
+                                          "  ".repeat(level + 2 - whitespace),
+                                          x));
+                }
+            }
+            NodeEvent::Leave(_) => {
+                filter.leave();
+            }
+        }
+    }
+    ret
+}
+
+fn get_identifier(
+    node: RefNode
+) -> Option<Locate> {
+    // unwrap_node! can take multiple types
+    match unwrap_node!(node, SimpleIdentifier, EscapedIdentifier) {
+        Some(RefNode::SimpleIdentifier(x)) => Some(x.nodes.0),
+        Some(RefNode::EscapedIdentifier(x)) => Some(x.nodes.0),
+        _ => None,
+    }
+}
+
+// original string of any node
+fn get_node_str<'a>(
+    syntax_tree: &'a SyntaxTree,
+    node: RefNode
+) -> Option<&'a str> {
+    syntax_tree.get_str(RefNodes(vec![node]))
+}
+
+// byte range of a node in the original file, from its first token to its last one
+fn get_token_span(
+    syntax_tree: &SyntaxTree,
+    node: RefNode,
+    sources: &mut SourceMap
+) -> Option<(usize, usize)> {
+    let mut first = None;
+    let mut last = None;
+    let mut skip = false;
+    for x in node.into_iter().event() {
+        match x {
+            NodeEvent::Enter(RefNode::WhiteSpace(_)) => skip = true,
+            NodeEvent::Leave(RefNode::WhiteSpace(_)) => skip = false,
+            NodeEvent::Enter(RefNode::Locate(x)) if !skip => {
+                first = first.or(Some(x));
+                last = Some(x);
+            }
+            _ => ()
+        }
+    }
+    let begin = sources.span(syntax_tree, first?)?.offset;
+    let end = sources.span(syntax_tree, last?)?.end_offset;
+    if begin <= end { Some((begin, end)) } else { None }
+}
+
+// tokens of a node, without whitespace and comments
+fn get_tokens<'a>(
+    syntax_tree: &'a SyntaxTree,
+    node: RefNode
+) -> Vec<&'a str> {
+    let mut tokens = vec![];
+    let mut skip = false;
+    for x in node.into_iter().event() {
+        match x {
+            NodeEvent::Enter(RefNode::WhiteSpace(_)) => skip = true,
+            NodeEvent::Leave(RefNode::WhiteSpace(_)) => skip = false,
+            NodeEvent::Enter(RefNode::Locate(x)) if !skip => {
+                if let Some(x) = syntax_tree.get_str(x) {
+                    tokens.push(x);
+                }
+            }
+            _ => ()
+        }
+    }
+    tokens
+}
+
+// tokens of a node separated by a space
+fn get_token_str(
+    syntax_tree: &SyntaxTree,
+    node: RefNode
+) -> String {
+    get_tokens(syntax_tree, node).join(" ")
+}
+
+// text of an expression or a data type in the output, the same by the parser and the
+// scanner: the tokens with a space only between two words (e.g. "{w[1],w[0]}",
+// "a[1:0]^b", and "int unsigned[7:0]"), where the parts of a number are one word
+// (e.g. "4'b0" and "10ns")
+fn join_tokens<'a>(
+    tokens: impl IntoIterator<Item = &'a str>
+) -> String {
+    let is_word = |c: Option<char>| c.is_some_and(|x| x.is_ascii_alphanumeric() || x == '_' || x == '$');
+    let mut ret = String::new();
+    let mut number = false;
+    for x in tokens {
+        if is_word(ret.chars().last()) && is_word(x.chars().next()) && !number {
+            ret.push(' ');
+        }
+        ret.push_str(x);
+        number = x.starts_with(|c: char| c.is_ascii_digit() || c == '\'');
+    }
+    ret
+}
+
+// text of an expression or a data type node in the output
+fn get_expr_str(
+    syntax_tree: &SyntaxTree,
+    node: RefNode
+) -> String {
+    join_tokens(get_tokens(syntax_tree, node))
+}
+
+fn get_keyword(
+    node: RefNode
+) -> Option<Locate> {
+    match unwrap_node!(node, Keyword) {
+        Some(RefNode::Keyword(x)) => Some(x.nodes.0),
+        _ => None,
+    }
+}
+
+// escape_str adapted from this code:
+// https://github.com/chyh1990/yaml-rust/blob/6cd3ce4abe6894443645c48bdc375808ec911493/src/emitter.rs#L43-L104
+fn escape_str(v: &str) -> String {
+    let mut wr = String::new();
+    
+    wr.push('"');
+
+    let mut start = 0;
+
+    for (i, byte) in v.bytes().enumerate() {
+        let escaped = match byte {
+            b'"' => "\\\"",
+            b'\\' => "\\\\",
+            b'\x00' => "\\u0000",
+            b'\x01' => "\\u0001",
+            b'\x02' => "\\u0002",
+            b'\x03' => "\\u0003",
+            b'\x04' => "\\u0004",
+            b'\x05' => "\\u0005",
+            b'\x06' => "\\u0006",
+            b'\x07' => "\\u0007",
+            b'\x08' => "\\b",
+            b'\t' => "\\t",
+            b'\n' => "\\n",
+            b'\x0b' => "\\u000b",
+            b'\x0c' => "\\f",
+            b'\r' => "\\r",
+            b'\x0e' => "\\u000e",
+            b'\x0f' => "\\u000f",
+            b'\x10' => "\\u0010",
+            b'\x11' => "\\u0011",
+            b'\x12' => "\\u0012",
+            b'\x13' => "\\u0013",
+            b'\x14' => "\\u0014",
+            b'\x15' => "\\u0015",
+            b'\x16' => "\\u0016",
+            b'\x17' => "\\u0017",
+            b'\x18' => "\\u0018",
+            b'\x19' => "\\u0019",
+            b'\x1a' => "\\u001a",
+            b'\x1b' => "\\u001b",
+            b'\x1c' => "\\u001c",
+            b'\x1d' => "\\u001d",
+            b'\x1e' => "\\u001e",
+            b'\x1f' => "\\u001f",
+            b'\x7f' => "\\u007f",
+            _ => continue,
+        };
+
+        if start < i {
+            wr.push_str(&v[start..i]);
+        }
+
+        wr.push_str(escaped);
+
+        start = i + 1;
+    }
+
+    if start != v.len() {
+        wr.push_str(&v[start..]);
+    }
+
+    wr.push('"');
+    
+    wr
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{remove_file, write};
+    use super::*;
+
+    fn analyze_files(
+        paths: &[&Path]
+    ) -> Vec<FileResult> {
+        let names = Interner::new();
+        paths.iter().map(|x| analyze_file(x, &Defines::new(), &Options::default(), &names).unwrap()).collect()
+    }
+
+    #[test]
+    fn identical_duplicate_defs_are_merged() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b, c) = (dir.path().join("a.sv"), dir.path().join("b.sv"), dir.path().join("c.sv"));
+        write(&a, "module m; wire w; endmodule\n").unwrap();
+        write(&b, "// a copy\nmodule m; wire w; endmodule\n").unwrap();
+        write(&c, "module m; wire v; endmodule\n").unwrap();
+        let mut results = analyze_files(&[&a, &b, &c]);
+        merge_duplicate_defs(&mut results, &source::FsProvider);
+        assert_eq!(results[0].defs[0].also_defined_in, [(Arc::from(b.as_path()), 2)]);
+        assert!(results[1].defs.is_empty());
+        // a definition of the same name with a different text is kept
+        assert_eq!(results[2].defs.len(), 1);
+        assert!(results[2].defs[0].also_defined_in.is_empty());
+    }
+
+    #[test]
+    fn unreadable_duplicate_defs_are_not_merged() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.sv"), dir.path().join("b.sv"));
+        write(&a, "module m; wire w; endmodule\n").unwrap();
+        write(&b, "module m; wire w; endmodule\n").unwrap();
+        let mut results = analyze_files(&[&a, &b]);
+        remove_file(&b).unwrap();
+        merge_duplicate_defs(&mut results, &source::FsProvider);
+        assert!(results[0].defs[0].also_defined_in.is_empty());
+        assert_eq!(results[1].defs.len(), 1);
+    }
+}
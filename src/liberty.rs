@@ -0,0 +1,63 @@
+// Cell names of Liberty (.lib) files, with which the instances of the cells in a
+// gate-level netlist are told apart from the references to undefined modules.
+
+use std::collections::HashSet;
+use std::fs::read_to_string;
+use std::path::Path;
+
+// the text without comments and line continuations
+fn strip_comments(
+    text: &str
+) -> String {
+    let b = text.as_bytes();
+    let mut ret = String::new();
+    let mut i = 0;
+    let mut begin = 0;
+    while i < b.len() {
+        if b[i..].starts_with(b"/*") {
+            ret.push_str(&text[begin..i]);
+            i = b[i + 2..].windows(2).position(|x| x == b"*/").map_or(b.len(), |x| i + x + 4);
+            ret.push(' ');
+            begin = i;
+        } else if b[i..].starts_with(b"//") || b[i..].starts_with(b"\\\n") {
+            ret.push_str(&text[begin..i]);
+            i = if b[i] == b'\\' { i + 2 } else { b[i..].iter().position(|&c| c == b'\n').map_or(b.len(), |x| i + x) };
+            begin = i;
+        } else if b[i] == b'"' {
+            i += 1;
+            while i < b.len() && b[i] != b'"' {
+                i += if b[i] == b'\\' { 2 } else { 1 };
+            }
+            i += 1;
+        } else {
+            i += 1;
+        }
+    }
+    ret.push_str(&text[begin.min(b.len())..]);
+    ret
+}
+
+// names of the cell groups, i.e. `cell (NAME) { ... }` where the name may be quoted
+pub fn read_cells(
+    path: &Path
+) -> std::io::Result<HashSet<String>> {
+    let text = strip_comments(&read_to_string(path)?);
+    let b = text.as_bytes();
+    let is_word = |c: u8| c.is_ascii_alphanumeric() || c == b'_';
+    let mut ret = HashSet::new();
+    let mut pos = 0;
+    while let Some(i) = text[pos..].find("cell").map(|x| pos + x) {
+        pos = i + 4;
+        if i > 0 && is_word(b[i - 1]) || b.get(pos).is_some_and(|&c| is_word(c)) {
+            continue;
+        }
+        let rest = text[pos..].trim_start();
+        let Some(rest) = rest.strip_prefix('(') else { continue; };
+        let Some(close) = rest.find(')') else { continue; };
+        let name = rest[..close].trim().trim_matches('"').trim();
+        if !name.is_empty() {
+            ret.insert(String::from(name));
+        }
+    }
+    Ok(ret)
+}
@@ -0,0 +1,126 @@
+// Diagnostics on the standard error, apart from the results on the standard output.
+// The messages are events of `tracing`, printed as text or as JSON lines. The
+// messages of a thread can be buffered and written at once, so that the messages
+// of a compilation unit are not interleaved with those of the others, and can be
+// captured instead of written (e.g. for the response of --stdin-batch).
+
+use std::cell::RefCell;
+use std::error::Error as StdError;
+use std::io::{self, Write};
+use structopt::StructOpt;
+use tracing::Level;
+
+#[derive(StructOpt)]
+pub struct LogOpt {
+    /// Minimum level of the messages on the standard error
+    #[structopt(long = "log-level", possible_values = &["error", "warn", "info", "debug", "trace"], default_value = "info")]
+    pub log_level: String,
+
+    /// Format of the messages on the standard error
+    #[structopt(long = "log-format", possible_values = &["text", "json"], default_value = "text")]
+    pub log_format: String
+}
+
+thread_local! {
+    static BUFFER: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+    static CAPTURE: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+}
+
+// write the messages to the capture of the thread if any, or to the standard error
+fn write_stderr(
+    buf: &[u8]
+) -> io::Result<()> {
+    let captured = CAPTURE.with(|x| x.borrow_mut().as_mut().map(|x| x.extend_from_slice(buf)).is_some());
+    if captured {
+        Ok(())
+    } else {
+        io::stderr().lock().write_all(buf)
+    }
+}
+
+// writer of the messages to the buffer of the thread if any, or to the standard error
+struct DiagWriter;
+
+impl Write for DiagWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let buffered = BUFFER.with(|x| x.borrow_mut().as_mut().map(|x| x.extend_from_slice(buf)).is_some());
+        if !buffered {
+            write_stderr(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+// start buffering the messages of the thread
+pub fn begin_buffer() {
+    BUFFER.with(|x| *x.borrow_mut() = Some(vec![]));
+}
+
+// stop buffering the messages of the thread, and take the messages buffered
+pub fn take_buffer() -> Vec<u8> {
+    BUFFER.with(|x| x.borrow_mut().take()).unwrap_or_default()
+}
+
+// write the messages buffered at once
+pub fn write_buffered(
+    messages: &[u8]
+) {
+    if !messages.is_empty() {
+        let _ = write_stderr(messages);
+    }
+}
+
+// start capturing the messages of the thread, which are not written
+pub fn begin_capture() {
+    CAPTURE.with(|x| *x.borrow_mut() = Some(vec![]));
+}
+
+// stop capturing the messages of the thread, and take the messages captured, each
+// with the lines of its causes
+pub fn take_capture() -> Vec<String> {
+    let text = CAPTURE.with(|x| x.borrow_mut().take()).unwrap_or_default();
+    let mut ret: Vec<String> = vec![];
+    for line in String::from_utf8_lossy(&text).lines() {
+        match ret.last_mut() {
+            Some(x) if line.starts_with(' ') => {
+                x.push('\n');
+                x.push_str(line);
+            }
+            _ => ret.push(String::from(line))
+        }
+    }
+    ret
+}
+
+// the first initialization is kept, e.g. for the requests of --stdin-batch
+pub fn init(
+    opt: &LogOpt
+) {
+    let level: Level = opt.log_level.parse().unwrap_or(Level::INFO);
+    let builder = tracing_subscriber::fmt()
+        .with_writer(|| DiagWriter)
+        .with_max_level(level)
+        .with_ansi(false);
+    if opt.log_format == "json" {
+        let _ = builder.json().try_init();
+    } else {
+        let _ = builder.without_time().with_target(false).try_init();
+    }
+}
+
+// the causes of an error, each on its own line
+pub fn causes(
+    err: &dyn StdError
+) -> String {
+    let mut ret = String::new();
+    let mut err = err.source();
+    while let Some(x) = err {
+        ret.push_str(&format!("\n  Caused by {}", x));
+        err = x.source();
+    }
+    ret
+}
@@ -0,0 +1,109 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use crate::{escape_str, FileResult, Opt};
+
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub modules: Vec<ManifestEntry>
+}
+
+// fingerprints of a module
+#[derive(Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub mod_name: String,
+    pub file_name: String,
+    pub iface_hash: String,
+    pub body_hash: String
+}
+
+pub fn make_manifest(
+    results: &[FileResult]
+) -> Manifest {
+    let mut modules = vec![];
+    for result in results {
+        for def in &result.defs {
+            modules.push(ManifestEntry {
+                mod_name: String::from(&*def.name),
+                file_name: result.path.to_string_lossy().into_owned(),
+                iface_hash: format!("{:016x}", def.iface_hash()),
+                body_hash: format!("{:016x}", def.body_hash)
+            });
+        }
+    }
+    Manifest { modules }
+}
+
+pub fn read_manifest(
+    path: &Path
+) -> Result<Manifest, Box<dyn std::error::Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+pub fn write_manifest(
+    path: &Path,
+    manifest: &Manifest
+) -> Result<(), Box<dyn std::error::Error>> {
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(writer, manifest)?;
+    Ok(())
+}
+
+fn print_change(
+    mod_name: &str,
+    change: &str
+) {
+    outln!("  - mod_name: {}", escape_str(mod_name));
+    outln!("    change: {}", escape_str(change));
+}
+
+// list the modules added, removed, or changed from the baseline
+pub fn print_changes(
+    baseline: &Manifest,
+    current: &Manifest
+) {
+    for x in &current.modules {
+        match baseline.modules.iter().find(|y| y.mod_name == x.mod_name) {
+            None => print_change(&x.mod_name, "added"),
+            Some(y) if y.iface_hash != x.iface_hash => print_change(&x.mod_name, "interface"),
+            Some(y) if y.body_hash != x.body_hash => print_change(&x.mod_name, "body"),
+            _ => ()
+        }
+    }
+    for y in &baseline.modules {
+        if !current.modules.iter().any(|x| x.mod_name == y.mod_name) {
+            print_change(&y.mod_name, "removed");
+        }
+    }
+}
+
+// list the changes from the baseline of --baseline and write the manifest of
+// --emit-manifest; false if either fails
+pub fn compare_and_write(
+    results: &[FileResult],
+    opt: &Opt
+) -> bool {
+    let mut ok = true;
+    if let Some(path) = &opt.baseline {
+        match read_manifest(path) {
+            Ok(baseline) => {
+                outln!("changed_modules:");
+                print_changes(&baseline, &make_manifest(results));
+            }
+            Err(x) => {
+                error!("failed to read baseline: {:?} ({})", path, x);
+                ok = false;
+            }
+        }
+    }
+    if let Some(path) = &opt.emit_manifest {
+        if let Err(x) = write_manifest(path, &make_manifest(results)) {
+            error!("failed to write manifest: {:?} ({})", path, x);
+            ok = false;
+        }
+    }
+    ok
+}
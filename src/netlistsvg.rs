@@ -0,0 +1,85 @@
+// Netlist in the JSON format of Yosys which netlistsvg renders as a schematic: the
+// ports of each module, and its instances as cells connected by the nets. A net is
+// as wide as the port of the module with the same name, and one bit otherwise;
+// the bit numbers start from 2 since 0 and 1 stand for the constants.
+
+use std::collections::HashMap;
+use serde_json::{json, Map, Value};
+use crate::{DefKind, FileResult, ModuleDef};
+
+// bits of a net, which are numbered when the net first appears
+fn net_bits<'a>(
+    bits: &mut HashMap<&'a str, Vec<usize>>,
+    name: &'a str,
+    width: usize
+) -> Vec<usize> {
+    let next = bits.values().map(|x| x.len()).sum::<usize>() + 2;
+    bits.entry(name).or_insert_with(|| (next..next + width).collect()).clone()
+}
+
+fn module_json(
+    def: &ModuleDef,
+    is_top: bool
+) -> Value {
+    let mut bits: HashMap<&str, Vec<usize>> = HashMap::new();
+
+    let mut ports = Map::new();
+    for port in &def.ports {
+        let bits = net_bits(&mut bits, &port.name, port.width.max(1) as usize);
+        ports.insert(String::from(&*port.name), json!({ "direction": port.dir, "bits": bits }));
+    }
+
+    let mut cells = Map::new();
+    for inst in &def.insts {
+        let mut directions = Map::new();
+        let mut connections = Map::new();
+        for conn in def.conns.iter().filter(|x| x.kind == "pin" && x.inst_name.as_deref() == Some(&*inst.inst_name) && !x.net.is_empty()) {
+            // an ordered connection to an undefined module is named by its position
+            let name = match (&conn.port_name, conn.port_index) {
+                (Some(name), _) => name.clone(),
+                (None, Some(i)) => i.to_string(),
+                (None, None) => continue
+            };
+            let dir = if conn.driver == Some(true) { "output" } else { "input" };
+            directions.entry(name.clone()).or_insert_with(|| json!(dir));
+            let bits = net_bits(&mut bits, &conn.net, 1);
+            let entry = connections.entry(name).or_insert_with(|| json!([]));
+            if let Value::Array(x) = entry {
+                x.extend(bits.into_iter().map(Value::from));
+            }
+        }
+        cells.insert(String::from(&*inst.inst_name), json!({
+            "type": &*inst.mod_name,
+            "port_directions": directions,
+            "connections": connections
+        }));
+    }
+
+    let mut netnames = Map::new();
+    for (name, bits) in &bits {
+        netnames.insert(String::from(*name), json!({ "bits": bits }));
+    }
+    let mut ret = json!({ "ports": ports, "cells": cells, "netnames": netnames });
+    if is_top {
+        ret["attributes"] = json!({ "top": 1 });
+    }
+    ret
+}
+
+// modules of the netlist; the top module is marked so that netlistsvg renders it
+pub fn print_netlist(
+    results: &[FileResult],
+    top: Option<&str>,
+    meta: Option<&Value>
+) {
+    let mut modules = Map::new();
+    for def in results.iter().flat_map(|x| &x.defs).filter(|x| x.kind == DefKind::Module) {
+        modules.entry(String::from(&*def.name)).or_insert_with(|| module_json(def, top == Some(&*def.name)));
+    }
+    // the provenance header (--meta) comes first, which netlistsvg ignores
+    let netlist = match meta {
+        Some(meta) => json!({ "meta": meta, "modules": modules }),
+        None => json!({ "modules": modules })
+    };
+    outln!("{}", serde_json::to_string_pretty(&netlist).unwrap_or_default());
+}
@@ -0,0 +1,273 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{create_dir_all, read, write};
+use std::path::PathBuf;
+use serde::Serialize;
+use structopt::StructOpt;
+use tracing::{error, warn};
+use crate::scan::KEYWORDS;
+use crate::logging::{self, LogOpt};
+use crate::{escape_str, find_defs, read_defines, strip, Interner, ModuleDef};
+
+#[derive(StructOpt)]
+#[structopt(name = "obfuscate", about = "Rename the identifiers consistently across the files")]
+pub struct ObfuscateOpt {
+    /// A list of source files
+    #[structopt(parse(from_os_str), required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Output directory
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    pub output: PathBuf,
+
+    /// Module whose name, parameters, and ports are kept
+    #[structopt(long = "top", multiple = true, number_of_values = 1)]
+    pub tops: Vec<String>,
+
+    /// Mapping file from the original names to the new ones (map.json in the output directory by default)
+    #[structopt(long = "map", parse(from_os_str))]
+    pub map: Option<PathBuf>,
+
+    /// Define
+    #[structopt(short = "d", long = "define", multiple = true, number_of_values = 1)]
+    pub defines: Vec<String>,
+
+    #[structopt(flatten)]
+    pub log: LogOpt
+}
+
+// directives whose argument is a macro name, which is kept
+const MACRO_DIRECTIVES: &[&str] = &["define", "ifdef", "ifndef", "elsif", "undef"];
+
+// directives which are kept together with the rest of the line
+const LINE_DIRECTIVES: &[&str] = &[
+    "begin_keywords", "default_nettype", "include", "line", "pragma", "timescale",
+    "unconnected_drive",
+];
+
+#[derive(Serialize)]
+struct MapEntry {
+    original: String,
+    obfuscated: String
+}
+
+// consistent renaming of the identifiers
+struct Renamer {
+    kept: HashSet<String>,
+    modules: HashSet<String>,
+    insts: HashSet<String>,
+    used: HashSet<String>,
+    names: HashMap<String, String>,
+    map: Vec<MapEntry>
+}
+
+impl Renamer {
+    fn rename(&mut self, name: &str) -> String {
+        if self.kept.contains(name) || KEYWORDS.contains(&name) {
+            return String::from(name);
+        }
+        if let Some(x) = self.names.get(name) {
+            return x.clone();
+        }
+        let prefix = if self.modules.contains(name) { "m" } else if self.insts.contains(name) { "u" } else { "s" };
+        // skip the names which are already in use
+        let new_name = (self.names.len() + 1..)
+            .map(|i| format!("{}{}", prefix, i))
+            .find(|x| !self.used.contains(x) && !self.kept.contains(x))
+            .unwrap_or_default();
+        self.used.insert(new_name.clone());
+        self.names.insert(String::from(name), new_name.clone());
+        self.map.push(MapEntry { original: String::from(name), obfuscated: new_name.clone() });
+        new_name
+    }
+}
+
+fn is_ident_char(
+    c: u8
+) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b'$'
+}
+
+// rewrite a text without comments; the named connections of the external modules
+// (which are not defined in the files) are kept
+fn obfuscate_text(
+    text: &str,
+    externals: &HashSet<String>,
+    r: &mut Renamer
+) -> String {
+    let b = text.as_bytes();
+    let mut ret = String::new();
+    let mut i = 0;
+    let mut in_external = false;
+    let mut after_dot = false;
+    while i < b.len() {
+        let begin = i;
+        let c = b[i];
+        if c == b'"' {
+            i += 1;
+            while i < b.len() && b[i] != b'"' && b[i] != b'\n' {
+                i += if b[i] == b'\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(b.len());
+        } else if b[i..].starts_with(b"(*") && b.get(i + 2) != Some(&b')') {
+            i = b[i + 2..].windows(2).position(|x| x == b"*)").map_or(b.len(), |x| i + x + 4);
+        } else if c == b'`' {
+            i += 1;
+            while i < b.len() && is_ident_char(b[i]) {
+                i += 1;
+            }
+            let name = &text[begin + 1..i];
+            if MACRO_DIRECTIVES.contains(&name) {
+                while i < b.len() && (b[i] == b' ' || b[i] == b'\t') {
+                    i += 1;
+                }
+                while i < b.len() && is_ident_char(b[i]) {
+                    i += 1;
+                }
+            } else if LINE_DIRECTIVES.contains(&name) {
+                i = b[i..].iter().position(|&c| c == b'\n').map_or(b.len(), |x| i + x);
+            }
+        } else if c == b'$' || c.is_ascii_digit() || (c == b'\'' && b.get(i + 1).is_some_and(|x| x.is_ascii_alphanumeric())) {
+            i += 1;
+            while i < b.len() && (is_ident_char(b[i]) || b[i] == b'\'' || b[i] == b'?' || (c != b'$' && b[i] == b'.')) {
+                i += 1;
+            }
+        } else if c == b'\\' || c.is_ascii_alphabetic() || c == b'_' {
+            i += 1;
+            while i < b.len() && (if c == b'\\' { !b[i].is_ascii_whitespace() } else { is_ident_char(b[i]) }) {
+                i += 1;
+            }
+            let name = &text[begin..i];
+            if externals.contains(name) {
+                in_external = true;
+            }
+            if (in_external && after_dot) || (c != b'\\' && externals.contains(name)) {
+                ret.push_str(name);
+            } else {
+                ret.push_str(&r.rename(name));
+            }
+            after_dot = false;
+            continue;
+        } else {
+            i += 1;
+            if c == b';' {
+                in_external = false;
+            }
+        }
+        if !c.is_ascii_whitespace() {
+            after_dot = c == b'.';
+        }
+        ret.push_str(&text[begin..i]);
+    }
+    ret
+}
+
+pub fn run(
+    opt: &ObfuscateOpt
+) -> i32 {
+    logging::init(&opt.log);
+    let mut exit_code = 0;
+    let mut defines = read_defines(&opt.defines);
+    let mut typedefs = HashMap::new();
+    let names = Interner::new();
+    let mut texts = vec![];
+    let mut defs: Vec<ModuleDef> = vec![];
+    for path in &opt.files {
+        let Ok(org) = read(path) else {
+            error!("failed to read: {:?}", path);
+            exit_code = 1;
+            continue;
+        };
+        match find_defs(path, &mut defines, &mut typedefs, &[], true, &names) {
+            Some(x) => defs.extend(x),
+            None => warn!("failed to find the modules: {:?}", path)
+        }
+        let text = strip::strip(&String::from_utf8_lossy(&org), &[String::from("comments")], true);
+        texts.push((path, text));
+    }
+
+    // the names of the top modules and their interfaces, as well as those of the
+    // modules not defined in the files, are kept
+    let mut kept = HashSet::new();
+    for top in &opt.tops {
+        let Some(def) = defs.iter().find(|x| *x.name == **top) else {
+            error!("top module not found: {}", top);
+            exit_code = 1;
+            continue;
+        };
+        kept.insert(String::from(&*def.name));
+        kept.extend(def.params.iter().map(|x| x.name.clone()));
+        kept.extend(def.ports.iter().map(|x| String::from(&*x.name)));
+    }
+    let modules: HashSet<String> = defs.iter().map(|x| String::from(&*x.name)).collect();
+    let externals: HashSet<String> = defs.iter().flat_map(|x| &x.insts)
+        .filter(|x| !modules.contains(&*x.mod_name))
+        .map(|x| String::from(&*x.mod_name))
+        .collect();
+    let mut r = Renamer {
+        kept,
+        insts: defs.iter().flat_map(|x| &x.insts).map(|x| String::from(&*x.inst_name)).collect(),
+        modules,
+        used: HashSet::new(),
+        names: HashMap::new(),
+        map: vec![]
+    };
+    // the generated names must not be the same as the original ones
+    for (_, text) in &texts {
+        r.used.extend(text.split(|c: char| !c.is_ascii_alphanumeric() && c != '_').map(String::from));
+    }
+
+    // the files are written by their names, which must differ
+    let mut file_names = HashSet::new();
+    let clashes: Vec<_> = texts.iter().filter(|(path, _)| !path.file_name().is_some_and(|x| file_names.insert(x))).collect();
+    for (path, _) in &clashes {
+        error!("file name already in use: {:?}", path);
+    }
+    if !clashes.is_empty() {
+        return 1;
+    }
+    if let Err(x) = create_dir_all(&opt.output) {
+        error!("failed to create directory: {:?} ({})", opt.output, x);
+        return 1;
+    }
+    println!("obfuscated:");
+    for (path, text) in &texts {
+        let out_path = opt.output.join(path.file_name().unwrap());
+        if let Err(x) = write(&out_path, obfuscate_text(text, &externals, &mut r)) {
+            error!("failed to write: {:?} ({})", out_path, x);
+            exit_code = 1;
+            continue;
+        }
+        println!("  - file_name: {}", escape_str(path.to_str().unwrap()));
+        println!("    output: {}", escape_str(out_path.to_str().unwrap()));
+    }
+    let map_path = opt.map.clone().unwrap_or_else(|| opt.output.join("map.json"));
+    let map = serde_json::to_string_pretty(&r.map).unwrap_or_default();
+    if let Err(x) = write(&map_path, map) {
+        error!("failed to write: {:?} ({})", map_path, x);
+        exit_code = 1;
+    }
+    println!("map: {}", escape_str(map_path.to_str().unwrap()));
+    exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::write;
+    use structopt::StructOpt;
+    use super::*;
+
+    #[test]
+    fn same_file_names_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        create_dir_all(dir.path().join("a")).unwrap();
+        create_dir_all(dir.path().join("b")).unwrap();
+        let a = dir.path().join("a/top.sv");
+        let b = dir.path().join("b/top.sv");
+        write(&a, "module top; sub u_sub (); endmodule\n").unwrap();
+        write(&b, "module sub; endmodule\n").unwrap();
+        let output = dir.path().join("out");
+        let opt = ObfuscateOpt::from_iter(["obfuscate", "-o", output.to_str().unwrap(), a.to_str().unwrap(), b.to_str().unwrap()]);
+        assert_eq!(run(&opt), 1);
+        assert!(!output.exists());
+    }
+}
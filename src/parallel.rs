@@ -0,0 +1,76 @@
+// Parsing of the compilation units ahead on the threads of --jobs. The units are
+// read and preprocessed in order, each from the defines left by the units before it
+// in the same library (or from the defines of the library with --separate), and the
+// preprocessed texts, whose parsing takes most of the time, are parsed in parallel.
+// The results are analyzed in the order of the units as if they were parsed one by
+// one; a unit is parsed again if the defines before it turn out to be different,
+// e.g. after a unit which failed to be parsed and so left the defines unchanged.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+use sv_parser::{parse_sv_pp, Defines, Error, SyntaxTree};
+use crate::{archive, prepare_unit, preprocess_unit, source, unit_includes, Library, Opt, PreparedUnit};
+
+// the parser needs a stack as large as that of the main thread or more
+const STACK_SIZE: usize = 64 << 20;
+
+pub struct Prefetched {
+    pub prepared: PreparedUnit,
+    // defines before the unit, from which it is parsed
+    pub defines: Defines,
+    pub parsed: Result<(SyntaxTree, Defines), Error>,
+    pub unresolved_includes: Vec<PathBuf>,
+    // time spent on the unit
+    pub elapsed: Duration
+}
+
+// parse the units from the first one, as many as the threads, keyed by their
+// indices; a unit without any file read is left to be parsed in order
+pub fn prefetch(
+    units: &[(Vec<&PathBuf>, usize)],
+    first: usize,
+    libraries: &[Library],
+    archives: &[archive::Archive],
+    provider: &dyn source::SourceProvider,
+    opt: &Opt,
+    vfs: bool
+) -> HashMap<usize, Prefetched> {
+    let mut lib_defines: HashMap<usize, Defines> = HashMap::new();
+    let mut preprocessed = vec![];
+    for (i, (unit, lib)) in units.iter().enumerate().skip(first).take(opt.jobs) {
+        let start = Instant::now();
+        let library = &libraries[*lib];
+        let includes = unit_includes(unit, &library.includes, archives);
+        let Some(prepared) = prepare_unit(unit, provider, &includes, opt, vfs) else { continue; };
+        if prepared.sources.segments.is_empty() {
+            continue;
+        }
+        let defines = lib_defines.entry(*lib).or_insert_with(|| library.defines.clone());
+        let (text, unresolved_includes) = preprocess_unit(&prepared, defines, &includes, opt);
+        let before = match &text {
+            Ok((_, after)) if !opt.separate => std::mem::replace(defines, after.clone()),
+            _ => defines.clone()
+        };
+        preprocessed.push((i, prepared, before, text, unresolved_includes, start.elapsed()));
+    }
+
+    // a unit whose thread cannot be started is also left to be parsed in order
+    let allow_incomplete = opt.allow_incomplete;
+    thread::scope(|scope| {
+        let handles: Vec<_> = preprocessed.into_iter()
+            .filter_map(|(i, prepared, defines, text, unresolved_includes, elapsed)| {
+                let parse = move || {
+                    let start = Instant::now();
+                    let parsed = text.and_then(|(text, defines)| parse_sv_pp(text, defines, allow_incomplete));
+                    Prefetched { prepared, defines, parsed, unresolved_includes, elapsed: elapsed + start.elapsed() }
+                };
+                thread::Builder::new().stack_size(STACK_SIZE).spawn_scoped(scope, parse).ok().map(|x| (i, x))
+            })
+            .collect();
+        handles.into_iter()
+            .map(|(i, x)| (i, x.join().unwrap_or_else(|x| std::panic::resume_unwind(x))))
+            .collect()
+    })
+}
@@ -0,0 +1,52 @@
+// Pinout of the top module: the ports with their roles guessed from the names.
+
+use crate::{conn, escape_str, ModuleDef};
+
+// parts of a port name which mark a control signal of a clock or a reset rather
+// than the clock or the reset itself, e.g. clk_en or rst_sel
+const CONTROL_PARTS: &[&str] = &["en", "enable", "ce", "sel", "gate", "div", "cnt", "count", "sync", "req", "ack"];
+
+// role of a port by its name: "clock" (clk, sys_clk, aclk, clk100, clock),
+// "reset" (rst, rst_n, aresetn, por_reset), or "data" otherwise
+pub fn port_role(
+    name: &str
+) -> &'static str {
+    let name = name.to_ascii_lowercase();
+    let parts: Vec<&str> = name.split('_').filter(|x| !x.is_empty()).collect();
+    if parts.iter().any(|x| CONTROL_PARTS.contains(x)) {
+        return "data";
+    }
+    let is_clock = |x: &str| {
+        x.ends_with("clk") || x.ends_with("clock") ||
+            x.strip_prefix("clk").is_some_and(|x| x.chars().all(|c| c.is_ascii_digit()))
+    };
+    let is_reset = |x: &str| {
+        let x = x.strip_suffix('n').filter(|x| x.ends_with("rst") || x.ends_with("reset")).unwrap_or(x);
+        x.ends_with("rst") || x.ends_with("reset") ||
+            x.strip_prefix("rst").is_some_and(|x| x.chars().all(|c| c.is_ascii_digit()))
+    };
+    if parts.iter().any(|x| is_clock(x)) {
+        "clock"
+    } else if parts.iter().any(|x| is_reset(x)) {
+        "reset"
+    } else {
+        "data"
+    }
+}
+
+pub fn print_pinout(
+    def: &ModuleDef,
+    clocks: bool
+) {
+    for port in &def.ports {
+        outln!("  - port_name: {}", escape_str(&port.name));
+        outln!("    port_dir: {}", escape_str(&port.dir));
+        outln!("    port_width: {}", port.width);
+        outln!("    role: {}", escape_str(port_role(&port.name)));
+        if clocks {
+            outln!("    clock: {}", conn::clock_value(def, &port.name));
+        }
+        outln!("    file_name: {}", escape_str(def.file.to_str().unwrap()));
+        outln!("    line: {}", port.line);
+    }
+}
@@ -0,0 +1,390 @@
+// Project scripts of FPGA tools: the source files in compile order, the include
+// paths, the defines, and the top module.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::write;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{error, warn};
+use crate::{archive, find_used_by, read_sanitized, scan, DefKind, FileResult, Opt, Symbol};
+
+// files in compile order: the files with packages come first in the given order,
+// followed by the others with the definitions of the instantiated modules before
+// the files instantiating them
+pub fn compile_order(
+    results: &[FileResult]
+) -> Vec<&Path> {
+    let files: HashMap<Symbol, usize> = results.iter().enumerate()
+        .flat_map(|(i, x)| x.defs.iter().map(move |y| (y.symbol, i)))
+        .collect();
+    let has_package = |result: &FileResult| {
+        let Some(text) = read_sanitized(&result.path) else { return false; };
+        scan::declarations(&text, &mut HashMap::new()).is_some_and(|x| x.iter().any(|y| y.0 == "package"))
+    };
+    let mut ret = vec![];
+    let mut visited = HashSet::new();
+    for (i, result) in results.iter().enumerate() {
+        if has_package(result) && visited.insert(i) {
+            ret.push(result.path.as_path());
+        }
+    }
+    // depth-first search of the files of the children; a cycle is broken at the file visited first
+    fn visit<'a>(
+        i: usize,
+        results: &'a [FileResult],
+        files: &HashMap<Symbol, usize>,
+        visited: &mut HashSet<usize>,
+        ret: &mut Vec<&'a Path>
+    ) {
+        if !visited.insert(i) {
+            return;
+        }
+        for inst in results[i].defs.iter().flat_map(|x| &x.insts) {
+            if let Some(&j) = files.get(&inst.mod_symbol) {
+                visit(j, results, files, visited, ret);
+            }
+        }
+        ret.push(results[i].path.as_path());
+    }
+    for i in 0..results.len() {
+        visit(i, results, &files, &mut visited, &mut ret);
+    }
+    ret
+}
+
+// the given top module, or the modules which are not instantiated by others
+pub fn find_tops<'a>(
+    results: &'a [FileResult],
+    top: Option<&'a str>
+) -> Vec<&'a str> {
+    if let Some(top) = top {
+        return vec![top];
+    }
+    let used_by = find_used_by(results);
+    results.iter().flat_map(|x| &x.defs)
+        .filter(|x| x.kind == DefKind::Module && !used_by.contains_key(&x.symbol))
+        .map(|x| &*x.name)
+        .collect()
+}
+
+// a Tcl word, braced if it has special characters
+fn tcl_word(
+    text: &str
+) -> String {
+    if !text.is_empty() && text.chars().all(|c| c.is_ascii_alphanumeric() || "_-+=./:,@%".contains(c)) {
+        String::from(text)
+    } else {
+        format!("{{{}}}", text)
+    }
+}
+
+// the top module for a tool which takes a single one; the first is selected of several
+fn select_top<'a>(
+    tops: &[&'a str]
+) -> Option<&'a str> {
+    if tops.len() > 1 {
+        warn!("multiple top modules: {} (selected: {})", tops.join(", "), tops[0]);
+    }
+    tops.first().copied()
+}
+
+// Tcl fragment for Vivado adding the files to the current fileset
+pub fn write_vivado_tcl(
+    path: &Path,
+    results: &[FileResult],
+    defines: &[String],
+    includes: &[PathBuf],
+    top: Option<&str>
+) -> io::Result<()> {
+    let mut text = String::from("# generated by svinst_port\n");
+    for file in compile_order(results) {
+        text.push_str(&format!("add_files -norecurse {}\n", tcl_word(&file.to_string_lossy())));
+    }
+    if !includes.is_empty() {
+        let includes: Vec<String> = includes.iter().map(|x| tcl_word(&x.to_string_lossy())).collect();
+        text.push_str(&format!("set_property include_dirs [list {}] [current_fileset]\n", includes.join(" ")));
+    }
+    if !defines.is_empty() {
+        let defines: Vec<String> = defines.iter().map(|x| tcl_word(x)).collect();
+        text.push_str(&format!("set_property verilog_define [list {}] [current_fileset]\n", defines.join(" ")));
+    }
+    if let Some(top) = select_top(&find_tops(results, top)) {
+        text.push_str(&format!("set_property top {} [current_fileset]\n", tcl_word(top)));
+    }
+    write(path, text)
+}
+
+// assignments of a Quartus settings file for the files, search paths, macros, and top level entity
+pub fn write_qsf(
+    path: &Path,
+    results: &[FileResult],
+    defines: &[String],
+    includes: &[PathBuf],
+    top: Option<&str>
+) -> io::Result<()> {
+    let mut text = String::from("# generated by svinst_port\n");
+    if let Some(top) = select_top(&find_tops(results, top)) {
+        text.push_str(&format!("set_global_assignment -name TOP_LEVEL_ENTITY {}\n", tcl_word(top)));
+    }
+    for file in compile_order(results) {
+        let kind = match file.extension().and_then(|x| x.to_str()) {
+            Some("v") | Some("vh") => "VERILOG_FILE",
+            _ => "SYSTEMVERILOG_FILE"
+        };
+        text.push_str(&format!("set_global_assignment -name {} {}\n", kind, tcl_word(&file.to_string_lossy())));
+    }
+    for include in includes {
+        text.push_str(&format!("set_global_assignment -name SEARCH_PATH {}\n", tcl_word(&include.to_string_lossy())));
+    }
+    for define in defines {
+        text.push_str(&format!("set_global_assignment -name VERILOG_MACRO {}\n", tcl_word(define)));
+    }
+    write(path, text)
+}
+
+// a Starlark string
+fn starlark_str(
+    text: &str
+) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// a Bazel target name, in which the characters not allowed are replaced
+fn target_name(
+    name: &str
+) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() || "_-.".contains(c) { c } else { '_' }).collect()
+}
+
+// sv_library targets of the modules, loaded from a .bzl file, each named after its
+// module and depending on the targets of the modules instantiated in it (the first
+// definition if defined more than once); the modules of a file share the target of
+// the first of them, which the others are aliases of, so that the file is compiled
+// once, and a file without modules has a target named after the file (or after its
+// path if the name is taken); the defines are those of -d and of the library of the
+// file, and a member of an archive is given in srcs by its path in the archive, which
+// is given in `archive`
+pub fn write_bazel(
+    path: &Path,
+    results: &[FileResult],
+    defines: &[String],
+    lib_defines: &[String],
+    rules: &str
+) -> io::Result<()> {
+    let mut files: HashMap<Symbol, usize> = HashMap::new();
+    for (i, def) in results.iter().enumerate().flat_map(|(i, x)| x.defs.iter().map(move |def| (i, def))) {
+        files.entry(def.symbol).or_insert(i);
+    }
+    // the definitions of a file which are the first of their names
+    let firsts = &files;
+    let defs = |i: usize| results[i].defs.iter().filter(move |x| firsts[&x.symbol] == i);
+
+    // the targets of the files with modules are named first, so that a module is not
+    // renamed after a file
+    let mut taken: HashSet<String> = HashSet::new();
+    let mut names: Vec<Option<String>> = (0..results.len())
+        .map(|i| defs(i).next().map(|x| target_name(&x.name)).filter(|x| taken.insert(x.clone())))
+        .collect();
+    let stem = |x: &FileResult| x.path.file_stem().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default();
+    for (i, result) in results.iter().enumerate() {
+        if names[i].is_none() {
+            let name = Some(target_name(&stem(result))).filter(|x| taken.insert(x.clone()))
+                .unwrap_or_else(|| target_name(&result.path.with_extension("").to_string_lossy()));
+            taken.insert(name.clone());
+            names[i] = Some(name);
+        }
+    }
+    let names: Vec<String> = names.into_iter().flatten().collect();
+    let mut aliases: Vec<Vec<String>> = vec![vec![]; results.len()];
+    let mut targets: HashMap<Symbol, String> = HashMap::new();
+    for i in 0..results.len() {
+        for (j, def) in defs(i).enumerate() {
+            let name = target_name(&def.name);
+            if j == 0 || !taken.insert(name.clone()) {
+                targets.insert(def.symbol, names[i].clone());
+            } else {
+                targets.insert(def.symbol, name.clone());
+                aliases[i].push(name);
+            }
+        }
+    }
+
+    let mut text = String::from("# generated by svinst_port\n");
+    text.push_str(&format!("\nload({}, \"sv_library\")\n", starlark_str(rules)));
+    for (i, result) in results.iter().enumerate() {
+        let mut deps: Vec<&str> = vec![];
+        for inst in result.defs.iter().flat_map(|x| &x.insts) {
+            match (files.get(&inst.mod_symbol), targets.get(&inst.mod_symbol)) {
+                (Some(&j), Some(target)) if j != i && !deps.contains(&target.as_str()) => deps.push(target),
+                _ => ()
+            }
+        }
+        let mut target_defines: Vec<&str> = defines.iter().map(|x| x.as_str()).collect();
+        target_defines.extend(lib_defines.iter()
+            .filter_map(|x| x.split_once(':'))
+            .filter(|x| x.0 == result.library)
+            .map(|x| x.1));
+        let (archive, src) = match archive::member(&result.path) {
+            Some((archive, member)) => (Some(archive), member),
+            None => (None, result.path.clone())
+        };
+        let list = |x: Vec<String>| x.iter().map(|y| starlark_str(y)).collect::<Vec<String>>().join(", ");
+        text.push_str("\nsv_library(\n");
+        text.push_str(&format!("    name = {},\n", starlark_str(&names[i])));
+        text.push_str(&format!("    srcs = [{}],\n", starlark_str(&src.to_string_lossy())));
+        if let Some(archive) = archive {
+            text.push_str(&format!("    archive = {},\n", starlark_str(&archive.to_string_lossy())));
+        }
+        if !target_defines.is_empty() {
+            text.push_str(&format!("    defines = [{}],\n", list(target_defines.iter().map(|x| String::from(*x)).collect())));
+        }
+        if !deps.is_empty() {
+            text.push_str(&format!("    deps = [{}],\n", list(deps.iter().map(|x| format!(":{}", x)).collect())));
+        }
+        text.push_str(")\n");
+        for alias in &aliases[i] {
+            text.push_str("\nalias(\n");
+            text.push_str(&format!("    name = {},\n", starlark_str(alias)));
+            text.push_str(&format!("    actual = {},\n", starlark_str(&format!(":{}", names[i]))));
+            text.push_str(")\n");
+        }
+    }
+    write(path, text)
+}
+
+// a quoted CMake argument
+fn cmake_str(
+    text: &str
+) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('$', "\\$"))
+}
+
+// CMake variables of the files in compile order, the include paths, the defines, and the top modules
+pub fn write_cmake(
+    path: &Path,
+    results: &[FileResult],
+    defines: &[String],
+    includes: &[PathBuf],
+    top: Option<&str>
+) -> io::Result<()> {
+    let mut text = String::from("# generated by svinst_port\n");
+    let mut set = |name: &str, values: Vec<String>| {
+        text.push_str(&format!("set({}", name));
+        for value in values {
+            text.push_str(&format!("\n    {}", cmake_str(&value)));
+        }
+        text.push_str(")\n");
+    };
+    set("SVINST_SOURCES", compile_order(results).iter().map(|x| x.to_string_lossy().into_owned()).collect());
+    set("SVINST_INCLUDE_DIRS", includes.iter().map(|x| x.to_string_lossy().into_owned()).collect());
+    set("SVINST_DEFINES", defines.to_vec());
+    set("SVINST_TOP", find_tops(results, top).iter().map(|x| String::from(*x)).collect());
+    write(path, text)
+}
+
+// write the project scripts and the build targets desired; false if any is not
+// written
+pub fn write_projects(
+    results: &[FileResult],
+    opt: &Opt
+) -> bool {
+    let mut ok = true;
+    if let Some(path) = &opt.emit_vivado_tcl {
+        if let Err(x) = write_vivado_tcl(path, results, &opt.defines, &opt.includes, opt.top.as_deref()) {
+            error!("failed to write Tcl script: {:?} ({})", path, x);
+            ok = false;
+        }
+    }
+    if let Some(path) = &opt.emit_qsf {
+        if let Err(x) = write_qsf(path, results, &opt.defines, &opt.includes, opt.top.as_deref()) {
+            error!("failed to write QSF: {:?} ({})", path, x);
+            ok = false;
+        }
+    }
+    if let Some(path) = &opt.emit_bazel {
+        if let Err(x) = write_bazel(path, results, &opt.defines, &opt.lib_defines, &opt.bazel_rules) {
+            error!("failed to write Bazel targets: {:?} ({})", path, x);
+            ok = false;
+        }
+    }
+    if let Some(path) = &opt.emit_cmake {
+        if let Err(x) = write_cmake(path, results, &opt.defines, &opt.includes, opt.top.as_deref()) {
+            error!("failed to write CMake file: {:?} ({})", path, x);
+            ok = false;
+        }
+    }
+    ok
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs::{read_to_string, File};
+    use flate2::write::GzEncoder;
+    use structopt::StructOpt;
+    use crate::{run_opt, Opt};
+    use super::*;
+
+    #[test]
+    fn bazel_targets_are_per_module() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |x: &str| dir.path().join(x).to_string_lossy().into_owned();
+        write(path("top.sv"), "module top; sub1 u1(); sub2 u2(); ip u3(); endmodule\n").unwrap();
+        write(path("subs.sv"), "module sub1; endmodule\nmodule sub2; leaf u(); endmodule\n").unwrap();
+        std::fs::create_dir(path("lib")).unwrap();
+        write(path("lib/leaf.sv"), "module leaf; endmodule\n").unwrap();
+        let text = "module ip; endmodule\n";
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(path("ip.tgz")).unwrap(), flate2::Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(text.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "rtl/ip.sv", text.as_bytes()).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let lib = format!("cells={}", path("lib"));
+        let argv = [
+            "svinst_port", &path("top.sv"), &path("subs.sv"), &path("ip.tgz"), "--lib", &lib,
+            "-d", "A=1", "--lib-define", "cells:B", "--emit-bazel", &path("BUILD"), "--bazel-rules", "//sv:defs.bzl"
+        ];
+        assert_eq!(run_opt(Opt::from_iter(argv), HashMap::new()), 0);
+        let expected = format!("\
+# generated by svinst_port
+
+load(\"//sv:defs.bzl\", \"sv_library\")
+
+sv_library(
+    name = \"top\",
+    srcs = [\"{top}\"],
+    defines = [\"A=1\"],
+    deps = [\":sub1\", \":sub2\", \":ip\"],
+)
+
+sv_library(
+    name = \"sub1\",
+    srcs = [\"{subs}\"],
+    defines = [\"A=1\"],
+    deps = [\":leaf\"],
+)
+
+alias(
+    name = \"sub2\",
+    actual = \":sub1\",
+)
+
+sv_library(
+    name = \"ip\",
+    srcs = [\"rtl/ip.sv\"],
+    archive = \"{ip}\",
+    defines = [\"A=1\"],
+)
+
+sv_library(
+    name = \"leaf\",
+    srcs = [\"{leaf}\"],
+    defines = [\"A=1\", \"B\"],
+)
+", top = path("top.sv"), subs = path("subs.sv"), ip = path("ip.tgz"), leaf = path("lib/leaf.sv"));
+        assert_eq!(read_to_string(path("BUILD")).unwrap(), expected);
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::fs::{read, write};
+use std::io::Write;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use sv_parser::{parse_sv, Define};
+use tempfile::NamedTempFile;
+use tracing::{error, info};
+use crate::logging::{self, LogOpt};
+use crate::read_defines;
+
+#[derive(StructOpt)]
+#[structopt(name = "reduce", about = "Reduce a file which fails to parse into a minimal reproducer")]
+pub struct ReduceOpt {
+    /// A source file which fails to parse
+    #[structopt(parse(from_os_str))]
+    pub file: PathBuf,
+
+    /// Output file (standard output by default)
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+
+    /// Define
+    #[structopt(short = "d", long = "define", multiple = true, number_of_values = 1)]
+    pub defines: Vec<String>,
+
+    /// Include path
+    #[structopt(short = "i", long = "include", multiple = true, number_of_values = 1)]
+    pub includes: Vec<PathBuf>,
+
+    #[structopt(flatten)]
+    pub log: LogOpt
+}
+
+// parser run on candidate texts
+struct Tester<'a> {
+    defines: HashMap<String, Option<Define>>,
+    includes: &'a [PathBuf],
+    count: usize
+}
+
+impl Tester<'_> {
+    // the failure of a text, if any; a syntax error is identified by the line
+    // where it is found, so that a reduction does not turn it into another error
+    fn failure(&mut self, lines: &[&str]) -> Option<String> {
+        self.count += 1;
+        let text: String = lines.iter().flat_map(|x| x.chars().chain(std::iter::once('\n')))
+            .map(|c| if c.is_ascii() { c } else { '?' })
+            .collect();
+        let mut tmpfile = NamedTempFile::new().ok()?;
+        tmpfile.write_all(text.as_bytes()).ok()?;
+        match parse_sv(tmpfile.path(), &self.defines, self.includes, false, false) {
+            Ok(_) => None,
+            Err(sv_parser_error::Error::Parse(Some((path, pos)))) => {
+                let text = if path == tmpfile.path() { text } else { String::from_utf8_lossy(&read(&path).ok()?).into_owned() };
+                let begin = text[..pos.min(text.len())].rfind('\n').map_or(0, |x| x + 1);
+                let end = text[begin..].find('\n').map_or(text.len(), |x| begin + x);
+                Some(format!("syntax error at: {}", text[begin..end].trim()))
+            }
+            Err(x) => Some(x.to_string())
+        }
+    }
+}
+
+// delta debugging: chunks of lines are removed as long as the same failure occurs,
+// halving the size of the chunks until single lines
+fn reduce<'a>(
+    lines: Vec<&'a str>,
+    failure: &str,
+    tester: &mut Tester
+) -> Vec<&'a str> {
+    let mut lines = lines;
+    let mut size = lines.len() / 2;
+    while size > 0 {
+        let mut i = 0;
+        let mut removed = false;
+        while i < lines.len() {
+            let end = (i + size).min(lines.len());
+            let candidate: Vec<&str> = lines[..i].iter().chain(&lines[end..]).copied().collect();
+            if tester.failure(&candidate).as_deref() == Some(failure) {
+                lines = candidate;
+                removed = true;
+            } else {
+                i = end;
+            }
+        }
+        // chunks of the same size are tried again after a removal
+        if !removed {
+            size /= 2;
+        }
+    }
+    lines
+}
+
+pub fn run(
+    opt: &ReduceOpt
+) -> i32 {
+    logging::init(&opt.log);
+    let Ok(org) = read(&opt.file) else {
+        error!("failed to read: {:?}", opt.file);
+        return 1;
+    };
+    let text = String::from_utf8_lossy(&org);
+    let lines: Vec<&str> = text.lines().collect();
+    // the candidates are written elsewhere, so the includes relative to the file are
+    // searched in its directory
+    let mut includes = opt.includes.clone();
+    includes.extend(opt.file.parent().map(|x| if x.as_os_str().is_empty() { PathBuf::from(".") } else { x.to_path_buf() }));
+    let mut tester = Tester { defines: read_defines(&opt.defines), includes: &includes, count: 0 };
+    let Some(failure) = tester.failure(&lines) else {
+        error!("the file parses successfully: {:?}", opt.file);
+        return 1;
+    };
+    let org_len = lines.len();
+    let reduced = reduce(lines, &failure, &mut tester);
+    info!("reduced: {} lines -> {} lines ({} parses, {})", org_len, reduced.len(), tester.count, failure);
+    let mut out = reduced.join("\n");
+    out.push('\n');
+    match &opt.output {
+        Some(path) => {
+            if let Err(x) = write(path, out) {
+                error!("failed to write: {:?} ({})", path, x);
+                return 1;
+            }
+        }
+        None => print!("{}", out)
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, read_to_string};
+    use structopt::StructOpt;
+    use super::*;
+
+    #[test]
+    fn reduced_file_reproduces_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        create_dir_all(dir.path().join("inc")).unwrap();
+        write(dir.path().join("inc/defs.svh"), "`define W 4\n").unwrap();
+        let path = dir.path().join("top.sv");
+        write(&path, "`include \"inc/defs.svh\"\nmodule a;\n  wire [`W-1:0] x;\nendmodule\nmodule b;\n  assign y = `W +;\nendmodule\nmodule c;\nendmodule\n").unwrap();
+        let output = dir.path().join("reduced.sv");
+        let opt = ReduceOpt::from_iter(["reduce", "-o", output.to_str().unwrap(), path.to_str().unwrap()]);
+        assert_eq!(run(&opt), 0);
+        let reduced = read_to_string(&output).unwrap();
+        // the include relative to the file is resolved, so the failure is the syntax error
+        let includes = [dir.path().to_path_buf()];
+        let mut tester = Tester { defines: HashMap::new(), includes: &includes, count: 0 };
+        let failure = tester.failure(&read_to_string(&path).unwrap().lines().collect::<Vec<_>>());
+        assert_eq!(failure.as_deref(), Some("syntax error at: assign y = `W +;"));
+        assert_eq!(tester.failure(&reduced.lines().collect::<Vec<_>>()), failure);
+        assert_eq!(reduced, "`include \"inc/defs.svh\"\n  assign y = `W +;\n");
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::fs::{read, write};
+use std::io::Write;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use sv_parser::{parse_sv, unwrap_node, RefNode, SyntaxTree};
+use sv_parser_syntaxtree::NamedPortConnection;
+use tempfile::NamedTempFile;
+use tracing::{error, warn};
+use crate::scan::KEYWORDS;
+use crate::logging::{self, LogOpt};
+use crate::{escape_str, get_identifier, read_defines, read_sanitized, SourceMap};
+
+#[derive(StructOpt)]
+#[structopt(name = "rename", about = "Rename a module or a port consistently across the files")]
+pub struct RenameOpt {
+    /// A list of source files
+    #[structopt(parse(from_os_str), required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Current name
+    #[structopt(long = "from")]
+    pub from: String,
+
+    /// New name
+    #[structopt(long = "to")]
+    pub to: String,
+
+    /// Module whose port is renamed
+    #[structopt(long = "in")]
+    pub module: Option<String>,
+
+    /// Print a unified diff instead of writing the files
+    #[structopt(long = "diff")]
+    pub diff: bool,
+
+    /// Define
+    #[structopt(short = "d", long = "define", multiple = true, number_of_values = 1)]
+    pub defines: Vec<String>,
+
+    /// Include path
+    #[structopt(short = "i", long = "include", multiple = true, number_of_values = 1)]
+    pub includes: Vec<PathBuf>,
+
+    #[structopt(flatten)]
+    pub log: LogOpt
+}
+
+fn is_identifier(
+    name: &str
+) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') &&
+        chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$') && !KEYWORDS.contains(&name)
+}
+
+// positions of the names of a module (or an interface or a program) in its
+// declarations, instantiations, bind directives, and extern prototypes
+fn find_module_names(
+    syntax_tree: &SyntaxTree,
+    sources: &SourceMap,
+    name: &str,
+    names: &mut Vec<(PathBuf, usize, String)>,
+    new_name: &str
+) {
+    for node in syntax_tree {
+        match node {
+            RefNode::ModuleIdentifier(_) | RefNode::InterfaceIdentifier(_) | RefNode::ProgramIdentifier(_) => (),
+            _ => continue
+        }
+        let Some(id) = get_identifier(node) else { continue; };
+        if syntax_tree.get_str(&id) != Some(name) {
+            continue;
+        }
+        let Some((path, pos)) = syntax_tree.get_origin(&id) else { continue; };
+        let (path, pos) = sources.origin(path, pos);
+        names.push((path, pos, String::from(new_name)));
+    }
+}
+
+// positions of the names of a port in the declaration of its module, where a name
+// after a dot is a port of another module or a member, and in the named
+// connections of the instances of the module; an implicit connection ".a" is
+// made explicit as ".b(a)"
+fn find_port_names(
+    syntax_tree: &SyntaxTree,
+    sources: &mut SourceMap,
+    module: &str,
+    name: &str,
+    names: &mut Vec<(PathBuf, usize, String)>,
+    new_name: &str
+) {
+    for node in syntax_tree {
+        let is_decl = matches!(node,
+            RefNode::ModuleDeclarationNonansi(_) | RefNode::ModuleDeclarationAnsi(_) |
+            RefNode::InterfaceDeclarationNonansi(_) | RefNode::InterfaceDeclarationAnsi(_) |
+            RefNode::ProgramDeclarationNonansi(_) | RefNode::ProgramDeclarationAnsi(_));
+        let is_inst = matches!(node, RefNode::ModuleInstantiation(_));
+        if !is_decl && !is_inst {
+            continue;
+        }
+        let id = unwrap_node!(node.clone(), ModuleIdentifier, InterfaceIdentifier, ProgramIdentifier).and_then(get_identifier);
+        if id.and_then(|x| syntax_tree.get_str(&x)) != Some(module) {
+            continue;
+        }
+        for x in node {
+            let (id, replacement, is_reference) = match x {
+                RefNode::SimpleIdentifier(_) | RefNode::EscapedIdentifier(_) if is_decl => (get_identifier(x), String::from(new_name), true),
+                // a child of the module connected implicitly to the port
+                RefNode::NamedPortConnection(NamedPortConnection::Identifier(y)) if is_decl && y.nodes.3.is_none() => {
+                    (get_identifier(RefNode::from(&y.nodes.2)), format!("{}({})", name, new_name), false)
+                }
+                RefNode::NamedPortConnection(NamedPortConnection::Identifier(y)) if is_inst => {
+                    let replacement = if y.nodes.3.is_none() { format!("{}({})", new_name, name) } else { String::from(new_name) };
+                    (get_identifier(RefNode::from(&y.nodes.2)), replacement, false)
+                }
+                // the search of the new name (for a clash) does not warn
+                RefNode::NamedPortConnection(NamedPortConnection::Asterisk(y)) if name != new_name => {
+                    if let Some((path, line)) = sources.resolve(syntax_tree, &y.nodes.1.nodes.0) {
+                        warn!("wildcard connection may depend on the port name: {:?}:{}", path, line);
+                    }
+                    continue;
+                }
+                _ => continue
+            };
+            let Some(id) = id.filter(|x| syntax_tree.get_str(x) == Some(name)) else { continue; };
+            let Some((path, pos)) = syntax_tree.get_origin(&id) else { continue; };
+            let (path, pos) = sources.origin(path, pos);
+            // a port name in the module is not after a dot
+            if is_reference && sources.text(&path).get(..pos).is_some_and(|x| x.trim_end().ends_with('.')) {
+                continue;
+            }
+            names.push((path, pos, replacement));
+        }
+    }
+}
+
+// print the changed lines of a file with three lines of context
+fn print_diff(
+    path: &str,
+    old: &str,
+    new: &str
+) {
+    // a renaming keeps the number of lines
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let changed: Vec<usize> = (0..old.len()).filter(|&i| old.get(i) != new.get(i)).collect();
+    if changed.is_empty() {
+        return;
+    }
+    println!("--- a/{}", path);
+    println!("+++ b/{}", path);
+    let mut i = 0;
+    while i < changed.len() {
+        let begin = changed[i].saturating_sub(3);
+        let mut last = changed[i];
+        let mut j = i;
+        while j + 1 < changed.len() && changed[j + 1] <= last + 6 {
+            j += 1;
+            last = changed[j];
+        }
+        let end = (last + 4).min(old.len());
+        println!("@@ -{},{} +{},{} @@", begin + 1, end - begin, begin + 1, end - begin);
+        // consecutive changed lines are printed as a block of removals and additions
+        let mut k = begin;
+        while k < end {
+            let run = (k..end).take_while(|x| changed[i..=j].contains(x)).count();
+            if run == 0 {
+                println!(" {}", old[k]);
+                k += 1;
+                continue;
+            }
+            for x in &old[k..k + run] {
+                println!("-{}", x);
+            }
+            for x in &new[k..k + run] {
+                println!("+{}", x);
+            }
+            k += run;
+        }
+        i = j + 1;
+    }
+}
+
+pub fn run(
+    opt: &RenameOpt
+) -> i32 {
+    logging::init(&opt.log);
+    let mut exit_code = 0;
+    if !is_identifier(&opt.to) {
+        error!("invalid name: {}", opt.to);
+        return 1;
+    }
+    let mut defines = read_defines(&opt.defines);
+    let mut names = vec![];
+    let mut clashes = vec![];
+    for path in &opt.files {
+        let Some(text) = read_sanitized(path) else {
+            error!("failed to read: {:?}", path);
+            exit_code = 1;
+            continue;
+        };
+        let Ok(mut tmpfile) = NamedTempFile::new() else {
+            error!("failed to create a temporary file: {:?}", path);
+            exit_code = 1;
+            continue;
+        };
+        let _ = tmpfile.write_all(text.as_bytes());
+        let mut sources = SourceMap::new(tmpfile.path());
+        sources.add(path, 0, text);
+        match parse_sv(tmpfile.path(), &defines, &opt.includes, false, false) {
+            Ok((syntax_tree, new_defines)) => {
+                defines = new_defines;
+                match &opt.module {
+                    Some(module) => {
+                        find_port_names(&syntax_tree, &mut sources, module, &opt.from, &mut names, &opt.to);
+                        find_port_names(&syntax_tree, &mut sources, module, &opt.to, &mut clashes, &opt.to);
+                    }
+                    None => {
+                        find_module_names(&syntax_tree, &sources, &opt.from, &mut names, &opt.to);
+                        find_module_names(&syntax_tree, &sources, &opt.to, &mut clashes, &opt.to);
+                    }
+                }
+            }
+            Err(sv_parser_error::Error::Parse(Some((origin_path, origin_pos)))) => {
+                let (origin_path, origin_pos) = sources.origin(&origin_path, origin_pos);
+                let (line, column) = sources.line_col(&origin_path, origin_pos);
+                error!("parse failed: {:?}:{}:{}", origin_path, line, column);
+                exit_code = 1;
+            }
+            Err(x) => {
+                error!("parse failed: {:?} ({})", path, x);
+                exit_code = 1;
+            }
+        }
+    }
+    // no file is written unless all of them are renamed
+    if exit_code != 0 {
+        return exit_code;
+    }
+    if !clashes.is_empty() {
+        error!("name already in use: {}", opt.to);
+        return 1;
+    }
+
+    // the positions are grouped by file; a name in a macro may be found more than once
+    let mut edits: HashMap<&PathBuf, Vec<(usize, &String)>> = HashMap::new();
+    for (path, pos, replacement) in &names {
+        match opt.files.iter().find(|x| *x == path) {
+            Some(x) => edits.entry(x).or_default().push((*pos, replacement)),
+            None => warn!("name in a file not given is not renamed: {:?}", path)
+        }
+    }
+    if !opt.diff {
+        println!("renamed:");
+    }
+    for path in &opt.files {
+        let Some(mut positions) = edits.remove(path) else { continue; };
+        let Ok(org) = read(path) else { continue; };
+        positions.sort_by_key(|x| x.0);
+        positions.dedup_by_key(|x| x.0);
+        // the text at each position is checked, as a name in a macro may come from its argument
+        let mut out = org.clone();
+        let mut count = 0;
+        for &(pos, replacement) in positions.iter().rev() {
+            if out.get(pos..pos + opt.from.len()) == Some(opt.from.as_bytes()) {
+                out.splice(pos..pos + opt.from.len(), replacement.bytes());
+                count += 1;
+            }
+        }
+        if opt.diff {
+            print_diff(path.to_str().unwrap(), &String::from_utf8_lossy(&org), &String::from_utf8_lossy(&out));
+            continue;
+        }
+        if let Err(x) = write(path, &out) {
+            error!("failed to write: {:?} ({})", path, x);
+            exit_code = 1;
+            continue;
+        }
+        println!("  - file_name: {}", escape_str(path.to_str().unwrap()));
+        println!("    changes: {}", count);
+    }
+    exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{read_to_string, write};
+    use structopt::StructOpt;
+    use super::*;
+
+    #[test]
+    fn port_and_connections_are_renamed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("top.sv");
+        write(&path, "module leaf (input a, output y);
+  sub u_sub (.a(a), .b(y));
+  assign y = a & u_sub.a;
+endmodule
+module top (input a, output y);
+  leaf u_named (.a(a), .y(y));
+  leaf u_implicit (.a, .y);
+endmodule
+").unwrap();
+        let opt = RenameOpt::from_iter(["rename", "--in", "leaf", "--from", "a", "--to", "d", path.to_str().unwrap()]);
+        assert_eq!(run(&opt), 0);
+        // the names after a dot belong to the other modules
+        assert_eq!(read_to_string(&path).unwrap(), "module leaf (input d, output y);
+  sub u_sub (.a(d), .b(y));
+  assign y = d & u_sub.a;
+endmodule
+module top (input a, output y);
+  leaf u_named (.d(a), .y(y));
+  leaf u_implicit (.d(a), .y);
+endmodule
+");
+    }
+
+    #[test]
+    fn failing_file_leaves_all_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let good = dir.path().join("good.sv");
+        let bad = dir.path().join("bad.sv");
+        write(&good, "module a; endmodule\nmodule top; a u_a (); endmodule\n").unwrap();
+        write(&bad, "module b; a u_a (); endmodule\nmodule c; wire x endmodule\n").unwrap();
+        let opt = RenameOpt::from_iter(["rename", "--from", "a", "--to", "z"].iter().copied()
+            .chain([good.to_str().unwrap(), bad.to_str().unwrap()]));
+        assert_eq!(run(&opt), 1);
+        assert_eq!(read_to_string(&good).unwrap(), "module a; endmodule\nmodule top; a u_a (); endmodule\n");
+        assert_eq!(read_to_string(&bad).unwrap(), "module b; a u_a (); endmodule\nmodule c; wire x endmodule\n");
+    }
+}
@@ -0,0 +1,489 @@
+// The YAML report of the files: the definitions of each file, its packages, and
+// what is desired of the file itself, or the page of them with --limit and --offset.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::read;
+use std::time::UNIX_EPOCH;
+use sha2::{Digest, Sha256};
+use sv_parser::Define;
+use crate::{archive, bus, conn, escape_str, eval, DefKind, FileResult, GenScope, InstDef, InstKind, MacroChange, MacroOrigin, ModuleDef, Opt, PackageDef, Symbol, UsedBy};
+
+// the files with the definitions and what is desired of them
+pub fn print_files(
+    results: &[FileResult],
+    used_by: &HashMap<Symbol, Vec<UsedBy>>,
+    bus_patterns: &[bus::BusPattern],
+    blackboxes: &HashSet<String>,
+    opt: &Opt
+) {
+    // the page of the modules shown with --limit and --offset; the files without any
+    // module on the page are not shown
+    let paged = opt.limit.is_some() || opt.offset > 0;
+    let page_end = opt.limit.map_or(usize::MAX, |x| opt.offset.saturating_add(x));
+    let mut index = 0;
+    // an empty list rather than null if no file is shown
+    let shown = if paged {
+        page_end > opt.offset && results.iter().map(|x| x.defs.len()).sum::<usize>() > opt.offset
+    } else {
+        !results.is_empty()
+    };
+    outln!("{}", if shown { "files:" } else { "files: []" });
+    for result in results {
+        let defs: Vec<&ModuleDef> = result.defs.iter().enumerate()
+            .filter(|x| (opt.offset..page_end).contains(&(index + x.0)))
+            .map(|x| x.1)
+            .collect();
+        index += result.defs.len();
+        if paged && defs.is_empty() {
+            continue;
+        }
+        outln!("  - file_name: {}", escape_str(result.path.to_str().unwrap()));
+        if !opt.libs.is_empty() {
+            outln!("    library: {}", escape_str(&result.library));
+        }
+        if !result.aliases.is_empty() {
+            outln!("    aliases:");
+            for alias in &result.aliases {
+                outln!("      - {}", escape_str(alias.to_str().unwrap()));
+            }
+        }
+        if result.non_ascii > 0 {
+            outln!("    non_ascii_bytes: {}", result.non_ascii);
+        }
+        if opt.file_meta {
+            print_file_meta(result);
+        }
+        if let Some(names) = &result.used_defines {
+            if names.is_empty() {
+                outln!("    used_defines: []");
+            } else {
+                outln!("    used_defines:");
+            }
+            for name in names {
+                outln!("      - {}", escape_str(name));
+            }
+        }
+        if !result.unresolved_includes.is_empty() {
+            outln!("    unresolved_includes:");
+            for name in &result.unresolved_includes {
+                outln!("      - {}", escape_str(name.to_str().unwrap()));
+            }
+        }
+        if let Some(line) = result.parsed_until {
+            outln!("    parsed_until: {}", line);
+            if result.missing_defs.is_empty() {
+                outln!("    missing_defs: []");
+            } else {
+                outln!("    missing_defs:");
+            }
+            for name in &result.missing_defs {
+                outln!("      - {}", escape_str(name));
+            }
+        }
+        let show_defs = !opt.full_tree || opt.defs;
+        if show_defs && opt.schema == "port-v2" {
+            for (section, kind) in [("modules", DefKind::Module), ("interfaces", DefKind::Interface), ("programs", DefKind::Program)] {
+                let defs: Vec<&ModuleDef> = defs.iter().copied().filter(|x| x.kind == kind).collect();
+                if defs.is_empty() {
+                    outln!("    {}: []", section);
+                } else {
+                    outln!("    {}:", section);
+                    print_defs(&defs, used_by, bus_patterns, blackboxes, opt);
+                }
+            }
+            print_packages(&result.packages, opt.package_decls);
+        } else if show_defs {
+            outln!("    defs:");
+            print_defs(&defs, used_by, bus_patterns, blackboxes, opt);
+            if opt.package_decls {
+                print_packages(&result.packages, true);
+            }
+        }
+        if let Some(full_tree) = &result.full_tree {
+            if opt.tree_format == "json" {
+                // JSON is also valid as a YAML value
+                outln!("    syntax_tree: {}", full_tree);
+            } else {
+                outln!("    syntax_tree:");
+                out!("{}", full_tree);
+            }
+        }
+        // show macro definitions if desired
+        if let Some(macro_defs) = &result.macro_defs {
+            outln!("    macro_defs:");
+            show_macro_defs(macro_defs);
+        }
+        if let Some(origins) = &result.macro_origins {
+            show_macro_origins(origins);
+        }
+        if let Some(delta) = &result.macro_delta {
+            show_macro_delta(delta);
+        }
+    }
+    if paged {
+        outln!("page:");
+        outln!("  offset: {}", opt.offset);
+        match opt.limit {
+            Some(limit) => outln!("  limit: {}", limit),
+            None => outln!("  limit: null")
+        }
+        outln!("  total: {}", index);
+    }
+}
+
+
+// encoding of a text guessed from its byte order mark and its bytes
+fn guess_encoding(
+    text: &[u8]
+) -> &'static str {
+    if text.starts_with(&[0xef, 0xbb, 0xbf]) {
+        "utf-8-bom"
+    } else if text.starts_with(&[0xff, 0xfe]) {
+        "utf-16le"
+    } else if text.starts_with(&[0xfe, 0xff]) {
+        "utf-16be"
+    } else if text.is_ascii() {
+        "ascii"
+    } else if std::str::from_utf8(text).is_ok() {
+        "utf-8"
+    } else {
+        "unknown"
+    }
+}
+
+fn print_file_meta(
+    result: &FileResult
+) {
+    outln!("    file_meta:");
+    if let Ok(text) = read(archive::resolve(&result.path)) {
+        outln!("      size: {}", text.len());
+        outln!("      sha256: \"{:x}\"", Sha256::digest(&text));
+        outln!("      encoding: {}", escape_str(guess_encoding(&text)));
+    }
+    let mtime = archive::resolve(&result.path).metadata().and_then(|x| x.modified()).ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok());
+    if let Some(mtime) = mtime {
+        outln!("      mtime: {}", mtime.as_secs());
+    }
+    outln!("      parse_ms: {}", result.elapsed.as_millis());
+    outln!("      modules: {}", result.defs.len());
+}
+
+fn show_macro_defs(
+    defines: &HashMap<String, Option<Define>>
+) {
+    for define in defines.values().flatten() {
+        outln!("      - '{:?}'", define);
+    }
+}
+
+// where each macro is defined, in the order of the names
+fn show_macro_origins(
+    origins: &HashMap<String, MacroOrigin>
+) {
+    let mut names: Vec<&String> = origins.keys().collect();
+    names.sort();
+    if names.is_empty() {
+        outln!("    macro_origins: []");
+        return;
+    }
+    outln!("    macro_origins:");
+    for name in names {
+        let origin = &origins[name];
+        outln!("      - macro_name: {}", escape_str(name));
+        match &origin.file {
+            Some(file) => outln!("        file_name: {}", escape_str(file.to_str().unwrap())),
+            None => outln!("        file_name: \"command line\"")
+        }
+        if let Some(line) = origin.line {
+            outln!("        line: {}", line);
+        }
+        if origin.redefined {
+            outln!("        redefined: true");
+        }
+    }
+}
+
+fn show_macro_delta(
+    delta: &[MacroChange]
+) {
+    if delta.is_empty() {
+        outln!("    macro_delta: []");
+        return;
+    }
+    outln!("    macro_delta:");
+    for change in delta {
+        outln!("      - macro_name: {}", escape_str(&change.name));
+        outln!("        change: {}", escape_str(change.change));
+        match &change.text {
+            Some(text) => outln!("        text: {}", escape_str(text)),
+            None => outln!("        text: null")
+        }
+    }
+}
+
+pub fn print_defs(
+    defs: &[&ModuleDef],
+    used_by: &HashMap<Symbol, Vec<UsedBy>>,
+    bus_patterns: &[bus::BusPattern],
+    blackboxes: &HashSet<String>,
+    opt: &Opt
+) {
+    for def in defs {
+        outln!("      - mod_name: {}", escape_str(&def.name));
+        // the sections of port-v2 tell the interfaces and the programs by themselves
+        if def.kind != DefKind::Module && opt.schema != "port-v2" {
+            outln!("        kind: {}", escape_str(def.kind.as_str()));
+        }
+        if let Some(description) = &def.description {
+            outln!("        description: {}", escape_str(description));
+        }
+        outln!("        iface_hash: \"{:016x}\"", def.iface_hash());
+        if def.is_stub() {
+            outln!("        is_stub: true");
+        }
+        if blackboxes.contains(&*def.name) {
+            outln!("        blackbox: true");
+        }
+        if !def.also_defined_in.is_empty() {
+            outln!("        also_defined_in:");
+            for (file, line) in &def.also_defined_in {
+                outln!("          - file_name: {}", escape_str(file.to_str().unwrap()));
+                outln!("            line: {}", line);
+            }
+        }
+        if opt.src_spans || opt.emit_module_text {
+            if let Some((begin, end)) = def.span {
+                outln!("        src_begin: {}", begin);
+                outln!("        src_end: {}", end);
+                if opt.emit_module_text {
+                    let text = read(archive::resolve(&def.file)).ok().and_then(|x| x.get(begin..end).map(|x| String::from_utf8_lossy(x).into_owned()));
+                    if let Some(text) = text {
+                        outln!("        src_text: {}", escape_str(&text));
+                    }
+                }
+            }
+        }
+        // the parameters are listed only when any of them is documented
+        if def.params.iter().any(|x| x.description.is_some()) {
+            outln!("        params:");
+            for param in &def.params {
+                outln!("          - param_name: {}", escape_str(&param.name));
+                if let Some(value) = &param.value {
+                    outln!("            param_value: {}", escape_str(value));
+                }
+                if let Some(description) = &param.description {
+                    outln!("            description: {}", escape_str(description));
+                }
+            }
+        }
+        if def.ports.is_empty() {
+            outln!("        ports: []");
+        } else {
+            outln!("        ports:");
+        }
+        for port in &def.ports {
+            outln!("          - port_name: {}", escape_str(&port.name));
+            outln!("            port_dir: {}", escape_str(&port.dir));
+            if let Some(type_name) = &port.type_name {
+                outln!("            port_type: {}", escape_str(type_name));
+            }
+            outln!("            port_width: {}", port.width);
+            if let Some((msb, lsb)) = port.range {
+                outln!("            port_msb: {}", msb);
+                outln!("            port_lsb: {}", lsb);
+            }
+            if let Some(description) = &port.description {
+                outln!("            description: {}", escape_str(description));
+            }
+            if opt.port_clocks {
+                outln!("            clock: {}", conn::clock_value(def, &port.name));
+            }
+        }
+        if opt.buses {
+            bus::print_buses(def, bus_patterns);
+        }
+        if def.insts.is_empty() {
+            outln!("        insts: []");
+        } else {
+            outln!("        insts:");
+        }
+        for inst in &def.insts {
+            outln!("          - mod_name: {}", escape_str(&inst.mod_name));
+            outln!("            inst_name: {}", escape_str(&inst.inst_name));
+            outln!("            kind: {}", escape_str(inst.kind.as_str()));
+            match &inst.resolved {
+                Some((file, line)) => {
+                    outln!("            resolved_file: {}", escape_str(file.to_str().unwrap()));
+                    outln!("            resolved_line: {}", line);
+                }
+                None => {
+                    outln!("            resolved_file: null");
+                    outln!("            resolved_line: null");
+                }
+            }
+            if blackboxes.contains(&*inst.mod_name) {
+                outln!("            blackbox: true");
+            }
+            if !inst.scope.is_empty() {
+                outln!("            generate_scope: {}", escape_str(&GenScope::format(&inst.scope)));
+            }
+            if let Some(cond) = inst.cond.as_ref().filter(|_| opt.eval_generates) {
+                outln!("            generate_cond: {}", escape_str(cond));
+                let scope = eval::Scope { params: &def.params, typedefs: &HashMap::new() };
+                if let Some(value) = eval::eval(cond, scope) {
+                    outln!("            active: {}", value != 0);
+                }
+            }
+        }
+        if opt.connectivity {
+            conn::print_nets(def);
+        }
+        if !def.generates.is_empty() {
+            outln!("        generates:");
+        }
+        for x in &def.generates {
+            outln!("          - genvar: {}", escape_str(&x.genvar));
+            if let Some(label) = &x.label {
+                outln!("            label: {}", escape_str(label));
+            }
+            outln!("            line: {}", x.line);
+            outln!("            init: {}", escape_str(&x.init));
+            outln!("            cond: {}", escape_str(&x.cond));
+            outln!("            step: {}", escape_str(&x.step));
+            let scope = eval::Scope { params: &def.params, typedefs: &HashMap::new() };
+            if let Some(count) = eval::loop_count(&x.genvar, &x.init, &x.cond, &x.step, scope) {
+                outln!("            iterations: {}", count);
+            }
+        }
+        if !def.aliases.is_empty() {
+            outln!("        net_aliases:");
+        }
+        for x in &def.aliases {
+            outln!("          - nets:");
+            for net in &x.nets {
+                outln!("              - {}", escape_str(net));
+            }
+            outln!("            line: {}", x.line);
+        }
+        if opt.assigns {
+            if def.assigns.is_empty() {
+                outln!("        assigns: []");
+            } else {
+                outln!("        assigns:");
+            }
+            for x in &def.assigns {
+                outln!("          - lhs: {}", escape_str(&x.lhs));
+                outln!("            rhs: {}", escape_str(&x.rhs));
+                outln!("            line: {}", x.line);
+            }
+        }
+        let uses = used_by.get(&def.symbol).map(|x| x.as_slice()).unwrap_or_default();
+        if uses.is_empty() {
+            outln!("        used_by: []");
+        } else {
+            outln!("        used_by:");
+        }
+        for x in uses {
+            outln!("          - mod_name: {}", escape_str(&x.parent.name));
+            outln!("            inst_name: {}", escape_str(&x.inst.inst_name));
+            outln!("            file_name: {}", escape_str(x.inst.file.to_str().unwrap()));
+            outln!("            line: {}", x.inst.line);
+        }
+    }
+}
+
+fn print_packages(
+    packages: &[PackageDef],
+    decls: bool
+) {
+    if packages.is_empty() {
+        outln!("    packages: []");
+    } else {
+        outln!("    packages:");
+    }
+    for package in packages {
+        outln!("      - pkg_name: {}", escape_str(&package.name));
+        outln!("        line: {}", package.line);
+        if !decls {
+            continue;
+        }
+        if package.params.is_empty() {
+            outln!("        params: []");
+        } else {
+            outln!("        params:");
+        }
+        for param in &package.params {
+            outln!("          - param_name: {}", escape_str(&param.name));
+            if let Some(value) = &param.value {
+                outln!("            param_value: {}", escape_str(value));
+            }
+        }
+        if package.typedefs.is_empty() {
+            outln!("        typedefs: []");
+        } else {
+            outln!("        typedefs:");
+        }
+        for typedef in &package.typedefs {
+            outln!("          - type_name: {}", escape_str(&typedef.name));
+            outln!("            type: {}", escape_str(&typedef.type_text));
+            outln!("            line: {}", typedef.line);
+        }
+        if package.functions.is_empty() {
+            outln!("        functions: []");
+        } else {
+            outln!("        functions:");
+        }
+        for function in &package.functions {
+            outln!("          - func_name: {}", escape_str(&function.name));
+            outln!("            return_type: {}", escape_str(&function.return_type));
+            outln!("            line: {}", function.line);
+        }
+    }
+}
+
+// instantiations of the names which are neither defined, cells, nor blackboxes
+pub fn print_unknown_refs(
+    results: &[FileResult],
+    blackboxes: &HashSet<String>
+) {
+    let refs: Vec<(&ModuleDef, &InstDef)> = results.iter()
+        .flat_map(|x| &x.defs)
+        .flat_map(|x| x.insts.iter().map(move |inst| (x, inst)))
+        .filter(|x| x.1.kind == InstKind::Unknown && !blackboxes.contains(&*x.1.mod_name))
+        .collect();
+    if refs.is_empty() {
+        outln!("unknown_refs: []");
+        return;
+    }
+    outln!("unknown_refs:");
+    for (parent, inst) in refs {
+        outln!("  - mod_name: {}", escape_str(&inst.mod_name));
+        outln!("    inst_name: {}", escape_str(&inst.inst_name));
+        outln!("    parent_name: {}", escape_str(&parent.name));
+        outln!("    file_name: {}", escape_str(inst.file.to_str().unwrap()));
+        outln!("    line: {}", inst.line);
+    }
+}
+
+// number of instantiations of each module name across the input files and of the
+// distinct modules instantiating it, from the most instantiated one, as inst_counts
+// without --top
+pub fn print_inst_summary(
+    used_by: &HashMap<Symbol, Vec<UsedBy>>
+) {
+    let mut counts: Vec<(&str, usize, usize)> = used_by.values()
+        .map(|x| (&*x[0].inst.mod_name, x.len(), x.iter().map(|x| x.parent.symbol).collect::<HashSet<_>>().len()))
+        .collect();
+    counts.sort_by(|x, y| y.1.cmp(&x.1).then(x.0.cmp(y.0)));
+    if counts.is_empty() {
+        outln!("inst_counts: []");
+        return;
+    }
+    outln!("inst_counts:");
+    for (name, count, parents) in counts {
+        outln!("  - mod_name: {}", escape_str(name));
+        outln!("    count: {}", count);
+        outln!("    parents: {}", parents);
+    }
+}
@@ -0,0 +1,1244 @@
+use std::collections::HashMap;
+use std::path::Path;
+use sv_parser::{Define, DefineText};
+use crate::doc::DocComment;
+use crate::{eval, fnv1a, join_tokens, AliasDef, DefKind, FunctionDef, GenScope, GenerateLoop, InstDef, InstKind, Interner, ModuleDef, PackageDef, ParamDef, PortDef, TypedefDef};
+
+// A lightweight scanner which extracts module definitions without building
+// the full syntax tree. It handles comments, conditional compilation, and
+// macro definitions, but does not follow `include nor expand macros; it gives
+// up (returns None) whenever the source is beyond its capability.
+
+// keywords of IEEE 1800-2017, which cannot be module or instance names
+pub const KEYWORDS: &[&str] = &[
+    "accept_on", "alias", "always", "always_comb", "always_ff", "always_latch", "and",
+    "assert", "assign", "assume", "automatic", "before", "begin", "bind", "bins",
+    "binsof", "bit", "break", "buf", "bufif0", "bufif1", "byte", "case", "casex",
+    "casez", "cell", "chandle", "checker", "class", "clocking", "cmos", "config",
+    "const", "constraint", "context", "continue", "cover", "covergroup", "coverpoint",
+    "cross", "deassign", "default", "defparam", "design", "disable", "dist", "do",
+    "edge", "else", "end", "endcase", "endchecker", "endclass", "endclocking",
+    "endconfig", "endfunction", "endgenerate", "endgroup", "endinterface", "endmodule",
+    "endpackage", "endprimitive", "endprogram", "endproperty", "endspecify",
+    "endsequence", "endtable", "endtask", "enum", "event", "eventually", "expect",
+    "export", "extends", "extern", "final", "first_match", "for", "force", "foreach",
+    "forever", "fork", "forkjoin", "function", "generate", "genvar", "global", "highz0",
+    "highz1", "if", "iff", "ifnone", "ignore_bins", "illegal_bins", "implements",
+    "implies", "import", "incdir", "include", "initial", "inout", "input", "inside",
+    "instance", "int", "integer", "interconnect", "interface", "intersect", "join",
+    "join_any", "join_none", "large", "let", "liblist", "library", "local",
+    "localparam", "logic", "longint", "macromodule", "matches", "medium", "modport",
+    "module", "nand", "negedge", "nettype", "new", "nexttime", "nmos", "nor",
+    "noshowcancelled", "not", "notif0", "notif1", "null", "or", "output", "package",
+    "packed", "parameter", "pmos", "posedge", "primitive", "priority", "program",
+    "property", "protected", "pull0", "pull1", "pulldown", "pullup",
+    "pulsestyle_ondetect", "pulsestyle_onevent", "pure", "rand", "randc", "randcase",
+    "randsequence", "rcmos", "real", "realtime", "ref", "reg", "reject_on", "release",
+    "repeat", "restrict", "return", "rnmos", "rpmos", "rtran", "rtranif0", "rtranif1",
+    "s_always", "s_eventually", "s_nexttime", "s_until", "s_until_with", "scalared",
+    "sequence", "shortint", "shortreal", "showcancelled", "signed", "small", "soft",
+    "solve", "specify", "specparam", "static", "string", "strong", "strong0", "strong1",
+    "struct", "super", "supply0", "supply1", "sync_accept_on", "sync_reject_on",
+    "table", "tagged", "task", "this", "throughout", "time", "timeprecision",
+    "timeunit", "tran", "tranif0", "tranif1", "tri", "tri0", "tri1", "triand", "trior",
+    "trireg", "type", "typedef", "union", "unique", "unique0", "unsigned", "until",
+    "until_with", "untyped", "use", "uwire", "var", "vectored", "virtual", "void",
+    "wait", "wait_order", "wand", "weak", "weak0", "weak1", "while", "wildcard", "wire",
+    "with", "within", "wor", "xnor", "xor",
+];
+
+// directives which are ignored together with the rest of the line
+const LINE_DIRECTIVES: &[&str] = &[
+    "begin_keywords", "celldefine", "default_nettype", "end_keywords", "endcelldefine",
+    "include", "line", "nounconnected_drive", "pragma", "resetall", "timescale",
+    "unconnected_drive", "undefineall",
+];
+
+// constructs which cannot contain module instantiations
+// keywords of the continuous assignments, procedural blocks, gates, and aliases, which make
+// a module more than a stub
+const LOGIC_KEYWORDS: &[&str] = &[
+    "assign", "always", "always_comb", "always_ff", "always_latch", "initial", "final",
+    "and", "nand", "or", "nor", "xor", "xnor", "buf", "not", "bufif0", "bufif1",
+    "notif0", "notif1", "nmos", "pmos", "rnmos", "rpmos", "cmos", "rcmos", "tran",
+    "rtran", "tranif0", "tranif1", "rtranif0", "rtranif1", "pullup", "pulldown", "alias",
+];
+
+const SKIPPED_BLOCKS: &[(&str, &str)] = &[
+    ("function", "endfunction"), ("task", "endtask"), ("class", "endclass"),
+    ("covergroup", "endgroup"), ("property", "endproperty"), ("sequence", "endsequence"),
+    ("specify", "endspecify"), ("clocking", "endclocking"), ("checker", "endchecker"),
+];
+
+#[derive(PartialEq)]
+enum Kind {
+    Ident,
+    Number,
+    Str,
+    Symbol,
+    Macro
+}
+
+struct Token {
+    kind: Kind,
+    text: String,
+    line: usize,
+    offset: usize,
+    doc: Option<String>
+}
+
+impl Token {
+    fn is(&self, text: &str) -> bool {
+        self.kind != Kind::Str && self.text == text
+    }
+
+    // identifier which is not a keyword
+    fn is_name(&self) -> bool {
+        self.kind == Kind::Ident && !KEYWORDS.contains(&self.text.as_str())
+    }
+}
+
+fn is_ident_char(
+    c: u8
+) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b'$'
+}
+
+// the rest of a line, joining the lines ending with a backslash
+fn read_line(
+    b: &[u8],
+    i: &mut usize,
+    line: &mut usize
+) -> String {
+    let mut ret = String::new();
+    while *i < b.len() && b[*i] != b'\n' {
+        if b[*i] == b'\\' && b.get(*i + 1) == Some(&b'\n') {
+            ret.push('\n');
+            *line += 1;
+            *i += 2;
+        } else {
+            ret.push(b[*i] as char);
+            *i += 1;
+        }
+    }
+    ret
+}
+
+// macro definition from the text after `define
+fn read_define(
+    text: &str
+) -> Option<Define> {
+    let text = text.trim_start();
+    let end = text.bytes().position(|c| !is_ident_char(c)).unwrap_or(text.len());
+    let name = &text[..end];
+    if name.is_empty() {
+        return None;
+    }
+    let mut rest = &text[end..];
+    let mut args = vec![];
+    if let Some(x) = rest.strip_prefix('(') {
+        let close = x.find(')')?;
+        for arg in x[..close].split(',') {
+            let mut arg = arg.splitn(2, '=');
+            let arg_name = String::from(arg.next().unwrap_or("").trim());
+            args.push((arg_name, arg.next().map(|x| String::from(x.trim()))));
+        }
+        rest = &x[close + 1..];
+    }
+    let body = rest.trim();
+    let body = if body.is_empty() { None } else { Some(DefineText::new(String::from(body), None)) };
+    Some(Define::new(String::from(name), args, body))
+}
+
+// macros predefined by the tools, which do not depend on the defines
+const PREDEFINED_MACROS: &[&str] = &["__FILE__", "__LINE__"];
+
+// add a macro expanded in the active code and the macros referred in its body
+fn add_used_macro(
+    name: &str,
+    defines: &HashMap<String, Option<Define>>,
+    used: &mut Vec<String>
+) {
+    if PREDEFINED_MACROS.contains(&name) || used.iter().any(|x| x == name) {
+        return;
+    }
+    used.push(String::from(name));
+    let Some(Some(define)) = defines.get(name) else { return; };
+    let Some(body) = &define.text else { return; };
+    let b = body.text.as_bytes();
+    let mut i = 0;
+    while i < b.len() {
+        i += 1;
+        if b[i - 1] != b'`' {
+            continue;
+        }
+        let begin = i;
+        while i < b.len() && is_ident_char(b[i]) {
+            i += 1;
+        }
+        if i > begin {
+            add_used_macro(&body.text[begin..i], defines, used);
+        }
+    }
+}
+
+// tokenize the source and apply the conditional compilation directives
+fn tokenize(
+    text: &str,
+    defines: &mut HashMap<String, Option<Define>>
+) -> Option<Vec<Token>> {
+    tokenize_with_uses(text, defines, &mut vec![], &mut vec![])
+}
+
+// tokenize the source, and collect the macros expanded or tested and the line
+// directives (name, argument, line) in the active code
+fn tokenize_with_uses(
+    text: &str,
+    defines: &mut HashMap<String, Option<Define>>,
+    used: &mut Vec<String>,
+    directives: &mut Vec<(String, String, usize)>
+) -> Option<Vec<Token>> {
+    let b = text.as_bytes();
+    let mut tokens = vec![];
+    // conditional blocks: (whether the current branch is active, whether any branch is taken)
+    let mut conds: Vec<(bool, bool)> = vec![];
+    let mut i = 0;
+    let mut line = 1;
+    let mut doc = DocComment::default();
+    while i < b.len() {
+        let c = b[i];
+        let active = conds.iter().all(|x| x.0);
+        let begin = i;
+        if c == b'\n' {
+            line += 1;
+            i += 1;
+            doc.whitespace("\n");
+        } else if c.is_ascii_whitespace() {
+            i += 1;
+        } else if c == b'/' && b.get(i + 1) == Some(&b'/') {
+            while i < b.len() && b[i] != b'\n' {
+                i += 1;
+            }
+            doc.comment(&text[begin..i]);
+        } else if c == b'/' && b.get(i + 1) == Some(&b'*') {
+            i += 2;
+            while i < b.len() && !(b[i] == b'*' && b.get(i + 1) == Some(&b'/')) {
+                if b[i] == b'\n' {
+                    line += 1;
+                }
+                i += 1;
+            }
+            i += 2;
+            doc.comment(&text[begin..i.min(b.len())]);
+        } else if c == b'"' {
+            i += 1;
+            while i < b.len() && b[i] != b'"' && b[i] != b'\n' {
+                i += if b[i] == b'\\' { 2 } else { 1 };
+            }
+            i += 1;
+            if active {
+                tokens.push(Token { kind: Kind::Str, text: String::from(&text[begin..i.min(b.len())]), line, offset: begin, doc: doc.token() });
+            }
+        } else if c == b'`' {
+            i += 1;
+            while i < b.len() && is_ident_char(b[i]) {
+                i += 1;
+            }
+            let name = &text[begin + 1..i];
+            match name {
+                "ifdef" | "ifndef" | "elsif" | "undef" => {
+                    while i < b.len() && (b[i] == b' ' || b[i] == b'\t') {
+                        i += 1;
+                    }
+                    let arg_begin = i;
+                    while i < b.len() && is_ident_char(b[i]) {
+                        i += 1;
+                    }
+                    let arg = &text[arg_begin..i];
+                    if arg.is_empty() {
+                        return None;
+                    }
+                    // a condition is tested if the enclosing code is active, and for
+                    // `elsif, if no branch before it is taken
+                    let tested = match name {
+                        "ifdef" | "ifndef" => active,
+                        "elsif" => conds.split_last().is_some_and(|(x, outer)| !x.1 && outer.iter().all(|x| x.0)),
+                        _ => false
+                    };
+                    if tested && !used.iter().any(|x| x == arg) {
+                        used.push(String::from(arg));
+                    }
+                    match name {
+                        "ifdef" | "ifndef" => {
+                            let cond = defines.contains_key(arg) == (name == "ifdef");
+                            conds.push((cond, cond));
+                        }
+                        "elsif" => {
+                            let top = conds.last_mut()?;
+                            let cond = !top.1 && defines.contains_key(arg);
+                            *top = (cond, top.1 || cond);
+                        }
+                        _ => {
+                            if active {
+                                defines.remove(arg);
+                            }
+                        }
+                    }
+                }
+                "else" => {
+                    let top = conds.last_mut()?;
+                    *top = (!top.1, true);
+                }
+                "endif" => {
+                    conds.pop()?;
+                }
+                "define" => {
+                    let rest = read_line(b, &mut i, &mut line);
+                    if active {
+                        let define = read_define(&rest)?;
+                        defines.insert(define.identifier.clone(), Some(define));
+                    }
+                }
+                x if LINE_DIRECTIVES.contains(&x) => {
+                    let directive_line = line;
+                    let rest = read_line(b, &mut i, &mut line);
+                    if active {
+                        directives.push((String::from(x), String::from(rest.trim()), directive_line));
+                    }
+                }
+                _ => {
+                    if active {
+                        add_used_macro(name, defines, used);
+                        tokens.push(Token { kind: Kind::Macro, text: format!("`{}", name), line, offset: begin, doc: doc.token() });
+                    }
+                }
+            }
+        } else if c == b'\\' {
+            while i < b.len() && !b[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            if active {
+                tokens.push(Token { kind: Kind::Ident, text: String::from(&text[begin..i]), line, offset: begin, doc: doc.token() });
+            }
+        } else if c.is_ascii_alphabetic() || c == b'_' || c == b'$' {
+            while i < b.len() && is_ident_char(b[i]) {
+                i += 1;
+            }
+            if active {
+                tokens.push(Token { kind: Kind::Ident, text: String::from(&text[begin..i]), line, offset: begin, doc: doc.token() });
+            }
+        } else if c.is_ascii_digit() || (c == b'\'' && b.get(i + 1).is_some_and(|x| x.is_ascii_alphanumeric())) {
+            i += 1;
+            while i < b.len() && (is_ident_char(b[i]) || b[i] == b'\'' || b[i] == b'?' || b[i] == b'.') {
+                i += 1;
+            }
+            if active {
+                tokens.push(Token { kind: Kind::Number, text: String::from(&text[begin..i]), line, offset: begin, doc: doc.token() });
+            }
+        } else {
+            i += 1;
+            if active {
+                tokens.push(Token { kind: Kind::Symbol, text: String::from(c as char), line, offset: begin, doc: doc.token() });
+            }
+        }
+    }
+    if !conds.is_empty() {
+        return None;
+    }
+    Some(tokens)
+}
+
+// text of tokens as an expression or a data type in the output, e.g. "int unsigned[7:0]"
+fn join(
+    tokens: &[Token]
+) -> String {
+    join_tokens(tokens.iter().map(|x| x.text.as_str()))
+}
+
+// split tokens at the commas which are not enclosed in brackets
+fn split_items(
+    tokens: &[Token]
+) -> Vec<&[Token]> {
+    split_tokens(tokens, ",")
+}
+
+// split tokens at the separators which are not enclosed in brackets
+fn split_tokens<'a>(
+    tokens: &'a [Token],
+    sep: &str
+) -> Vec<&'a [Token]> {
+    let mut items = vec![];
+    let mut depth = 0;
+    let mut begin = 0;
+    for (i, x) in tokens.iter().enumerate() {
+        if x.is("(") || x.is("[") || x.is("{") {
+            depth += 1;
+        } else if x.is(")") || x.is("]") || x.is("}") {
+            depth -= 1;
+        } else if x.is(sep) && depth == 0 {
+            items.push(&tokens[begin..i]);
+            begin = i + 1;
+        }
+    }
+    items.push(&tokens[begin..]);
+    items
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let ret = self.tokens.get(self.pos);
+        self.pos += 1;
+        ret
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.peek().is_some_and(|x| x.is(text))
+    }
+
+    // tokens enclosed in the brackets starting at the current position
+    fn group(&mut self) -> Option<&'a [Token]> {
+        let begin = self.pos;
+        let mut depth = 0;
+        loop {
+            let x = self.next()?;
+            if x.is("(") || x.is("[") || x.is("{") {
+                depth += 1;
+            } else if x.is(")") || x.is("]") || x.is("}") {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&self.tokens[begin + 1..self.pos - 1]);
+                }
+            }
+        }
+    }
+
+    // tokens until the next semicolon, which is consumed
+    fn statement(&mut self) -> Option<&'a [Token]> {
+        let begin = self.pos;
+        loop {
+            match self.peek()? {
+                x if x.is("(") || x.is("[") || x.is("{") => {
+                    self.group()?;
+                }
+                x if x.is(";") => {
+                    self.pos += 1;
+                    return Some(&self.tokens[begin..self.pos - 1]);
+                }
+                _ => self.pos += 1
+            }
+        }
+    }
+
+    fn skip_until(&mut self, end: &str) -> Option<()> {
+        while !self.next()?.is(end) {}
+        Some(())
+    }
+}
+
+// texts of the msb and the lsb of a packed range
+fn range_texts(
+    tokens: &[Token]
+) -> Option<(String, String)> {
+    let mut depth = 0;
+    let mut conds = 0;
+    for (i, x) in tokens.iter().enumerate() {
+        if x.is("(") || x.is("[") || x.is("{") {
+            depth += 1;
+        } else if x.is(")") || x.is("]") || x.is("}") {
+            depth -= 1;
+        } else if depth == 0 && x.is("?") {
+            conds += 1;
+        } else if depth == 0 && x.is(":") {
+            // skip the scope operators (e.g. "pkg::W") and the colons of conditional operators
+            let is_scope = |j: Option<usize>| j.and_then(|j| tokens.get(j)).is_some_and(|y| y.is(":") && y.offset.abs_diff(x.offset) == 1);
+            if is_scope(i.checked_sub(1)) || is_scope(Some(i + 1)) {
+                continue;
+            }
+            if conds == 0 {
+                return Some((join(&tokens[..i]), join(&tokens[i + 1..])));
+            }
+            conds -= 1;
+        }
+    }
+    None
+}
+
+// net types which may precede the data type of a port
+const NET_TYPES: &[&str] = &[
+    "var", "wire", "tri", "wand", "wor", "triand", "trior", "tri0", "tri1",
+    "uwire", "supply0", "supply1", "interconnect",
+];
+
+// direction, width, range, and type shared by the consecutive port declarations
+struct PortState {
+    is_input: bool,
+    width: i32,
+    range: Option<(i32, i32)>,
+    type_name: Option<String>,
+    range_expr: Option<(String, String)>,
+    type_expr: Option<String>,
+    // "range" or "data type" if the width cannot be evaluated
+    unresolved: Option<&'static str>
+}
+
+// a port declaration in an ANSI port list or in the module body
+fn process_port_item(
+    tokens: &[Token],
+    description: Option<&String>,
+    scope: eval::Scope,
+    s: &mut PortState,
+    ports: &mut Vec<PortDef>,
+    issues: &mut Vec<(usize, String)>,
+    names: &Interner
+) {
+    let mut name = None;
+    let mut range = None;
+    let mut type_begin = 0;
+    let mut p = Parser { tokens, pos: 0 };
+    while let Some(x) = p.peek() {
+        if x.is("=") {
+            break;
+        } else if x.is("input") || x.is("output") || x.is("inout") || x.is("ref") {
+            s.is_input = x.is("input");
+            s.width = 1;
+            s.range = None;
+            s.type_name = None;
+            s.range_expr = None;
+            s.type_expr = None;
+            s.unresolved = None;
+            p.pos += 1;
+            type_begin = p.pos;
+        } else if x.is("[") {
+            let Some(inner) = p.group() else { return; };
+            // the first packed dimension before the name
+            if name.is_none() && range.is_none() {
+                range = Some(range_texts(inner));
+            }
+        } else if x.is("(") || x.is("{") {
+            if p.group().is_none() { return; }
+        } else {
+            if x.is_name() {
+                name = Some((p.pos, &x.text));
+            }
+            p.pos += 1;
+        }
+    }
+    if let Some(texts) = range {
+        s.range = texts.as_ref().and_then(|(msb, lsb)| eval::eval_range(msb, lsb, scope));
+        s.width = eval::range_width(s.range);
+        s.range_expr = texts;
+        s.unresolved = s.range.is_none().then_some("range");
+    }
+    // the data type between the direction and the name, if not implicit
+    if let Some((pos, _)) = name {
+        let types = &tokens[type_begin.min(pos)..pos];
+        let begin = types.iter().position(|x| !NET_TYPES.iter().any(|y| x.is(y))).unwrap_or(types.len());
+        let types = &types[begin..];
+        let implicit = types.first().is_none_or(|x| x.is("[") || x.is("signed") || x.is("unsigned"));
+        if !implicit {
+            if let Some(width) = eval::eval_type(&join(types), scope) {
+                s.width = width;
+                s.type_name = eval::type_name(&join(types));
+                s.type_expr = Some(join(types));
+                if !eval::is_integer_type(&join(types)) {
+                    s.range = None;
+                }
+                s.unresolved = None;
+            } else {
+                s.unresolved = s.unresolved.or(Some("data type"));
+            }
+        }
+    }
+    if let Some((pos, name)) = name {
+        if let Some(kind) = s.unresolved {
+            issues.push((tokens[pos].line, format!("cannot evaluate the {} of port {}", kind, name)));
+        }
+        ports.push(PortDef {
+            name: names.name(name),
+            dir: String::from(if s.is_input { "input" } else { "output" }),
+            line: tokens[pos].line,
+            width: s.width,
+            range: s.range,
+            type_name: s.type_name.clone(),
+            description: description.cloned(),
+            range_expr: s.range_expr.clone(),
+            type_expr: s.type_expr.clone()
+        });
+    }
+}
+
+// type definition after the typedef keyword; the type is registered with its
+// package name as well if it is in a package
+fn process_typedef(
+    tokens: &[Token],
+    package: Option<&str>,
+    typedefs: &mut HashMap<String, String>
+) -> Option<TypedefDef> {
+    let mut depth = 0;
+    let mut name = None;
+    for (i, x) in tokens.iter().enumerate() {
+        if x.is("(") || x.is("[") || x.is("{") {
+            depth += 1;
+        } else if x.is(")") || x.is("]") || x.is("}") {
+            depth -= 1;
+        } else if depth == 0 && x.is_name() {
+            name = Some(i);
+        }
+    }
+    let i = name?;
+    // a forward declaration such as "typedef struct name;"
+    if i == 0 || (i == 1 && (tokens[0].is("enum") || tokens[0].is("struct") || tokens[0].is("union") || tokens[0].is("class"))) {
+        return None;
+    }
+    let text = join(&tokens[..i]);
+    if let Some(package) = package {
+        typedefs.insert(format!("{}::{}", package, tokens[i].text), text.clone());
+    }
+    typedefs.insert(tokens[i].text.clone(), text.clone());
+    Some(TypedefDef { name: tokens[i].text.clone(), type_text: text, line: tokens[i].line })
+}
+
+// function header after the function keyword up to the semicolon, with the return
+// type before the name
+fn process_function_header(
+    tokens: &[Token]
+) -> Option<FunctionDef> {
+    let tokens = match tokens.first() {
+        Some(x) if x.is("automatic") || x.is("static") => &tokens[1..],
+        _ => tokens
+    };
+    let end = tokens.iter().position(|x| x.is("(")).unwrap_or(tokens.len());
+    let name = tokens[..end].last().filter(|x| x.is_name())?;
+    Some(FunctionDef { name: name.text.clone(), return_type: join(&tokens[..end - 1]), line: name.line })
+}
+
+// parameter declarations in a parameter port list or in the module body; the items
+// are local from the start if `local`, and after localparam until parameter
+fn process_param_items(
+    tokens: &[Token],
+    description: Option<&String>,
+    overrides: &HashMap<String, String>,
+    local: bool,
+    params: &mut Vec<ParamDef>
+) {
+    let mut description = description;
+    let mut local = local;
+    for item in split_items(tokens) {
+        // the parameters in a declaration share its description
+        if item.first().is_some_and(|x| x.is("parameter") || x.is("localparam")) {
+            description = item[0].doc.as_ref();
+            local = item[0].is("localparam");
+        }
+        if item.iter().any(|x| x.is("type")) {
+            continue;
+        }
+        let eq = item.iter().position(|x| x.is("=")).unwrap_or(item.len());
+        let Some(name) = item[..eq].iter().rev().find(|x| x.is_name()) else { continue; };
+        if params.iter().any(|x| x.name == name.text) {
+            continue;
+        }
+        let value = match overrides.get(&name.text).filter(|_| !local) {
+            Some(x) => Some(x.clone()),
+            None if eq < item.len() => Some(join(&item[eq + 1..])),
+            None => None
+        };
+        params.push(ParamDef { name: name.text.clone(), value, description: description.cloned(), is_local: local });
+    }
+}
+
+// instantiation starting at the current position, if any
+fn try_instance(
+    p: &mut Parser,
+    path: &Path,
+    issues: &mut Vec<(usize, String)>,
+    names: &Interner
+) -> Option<InstDef> {
+    let begin = p.pos;
+    let mod_name = p.next()?;
+    let mut params = None;
+    if p.peek_is("#") {
+        p.pos += 1;
+        if !p.peek_is("(") {
+            p.pos = begin + 1;
+            return None;
+        }
+        params = Some(format!("#({})", join(p.group()?)));
+    }
+    let inst_name = p.next()?;
+    if !inst_name.is_name() {
+        p.pos = begin + 1;
+        return None;
+    }
+    while p.peek_is("[") {
+        p.group()?;
+    }
+    if !p.peek_is("(") {
+        p.pos = begin + 1;
+        return None;
+    }
+    let (conn_style, conn_count) = port_conn_style(p.group()?);
+    let rest = p.statement()?;
+    // the other instances of the instantiation are not reported
+    let count = split_items(rest).len();
+    if count > 1 {
+        issues.push((inst_name.line, format!("only the first of {} instances is reported: {}", count, inst_name.text)));
+    }
+    let (mod_name, mod_symbol) = names.intern(&mod_name.text);
+    Some(InstDef {
+        mod_name,
+        mod_symbol,
+        inst_name: names.name(&inst_name.text),
+        params,
+        kind: InstKind::Unknown,
+        file: names.path(path),
+        line: inst_name.line,
+        conn_style,
+        conn_count,
+        resolved: None,
+        cond: None,
+        scope: vec![]
+    })
+}
+
+// style and number of the port connections of an instance; "()" has no connection
+fn port_conn_style(
+    tokens: &[Token]
+) -> (&'static str, usize) {
+    let items = split_items(tokens);
+    if items.len() == 1 && items[0].is_empty() {
+        ("none", 0)
+    } else if items.iter().any(|x| x.len() == 2 && x[0].is(".") && x[1].is("*")) {
+        ("wildcard", items.len())
+    } else if items.iter().any(|x| x.first().is_some_and(|x| x.is("."))) {
+        ("named", items.len())
+    } else {
+        ("ordered", items.len())
+    }
+}
+
+// keyword which begins a module, interface, or program declaration, and its kind
+fn declaration_kind(
+    tokens: &[Token],
+    pos: usize
+) -> Option<DefKind> {
+    let x = &tokens[pos];
+    let prev = pos.checked_sub(1).map(|i| &tokens[i]);
+    let next = tokens.get(pos + 1);
+    if x.is("module") || x.is("macromodule") {
+        Some(DefKind::Module)
+    } else if x.is("interface") && !prev.is_some_and(|x| x.is("virtual")) && !next.is_some_and(|x| x.is("class")) {
+        Some(DefKind::Interface)
+    } else if x.is("program") {
+        Some(DefKind::Program)
+    } else {
+        None
+    }
+}
+
+// generate-for loop starting at the current position, which is a for-loop whose
+// variable is a genvar; the position is moved to the end of the loop header
+fn try_generate_loop(
+    p: &mut Parser,
+    genvars: &[String]
+) -> Option<Option<GenerateLoop>> {
+    let line = p.next()?.line;
+    let header = split_tokens(p.group()?, ";");
+    let [init, cond, step] = header.as_slice() else { return Some(None); };
+    let (is_genvar, init) = match init.first() {
+        Some(x) if x.is("genvar") => (true, &init[1..]),
+        _ => (false, *init)
+    };
+    let [genvar, eq, ..] = init else { return Some(None); };
+    if !eq.is("=") || !(is_genvar || genvars.contains(&genvar.text)) {
+        return Some(None);
+    }
+    // the label before or after begin; the label before is skipped
+    let label = match p.tokens.get(p.pos..p.pos + 3) {
+        Some([x, colon, name]) if x.is("begin") && colon.is(":") => Some(name.text.clone()),
+        Some([name, colon, x]) if x.is("begin") && colon.is(":") && name.is_name() => {
+            p.pos += 2;
+            Some(name.text.clone())
+        }
+        _ => None
+    };
+    Some(Some(GenerateLoop {
+        genvar: genvar.text.clone(),
+        label,
+        init: join(&init[2..]),
+        cond: join(cond),
+        step: join(step),
+        line
+    }))
+}
+
+// whether a statement has an assignment outside the brackets
+fn has_assignment(
+    tokens: &[Token]
+) -> bool {
+    let mut depth = 0;
+    for x in tokens {
+        if x.is("(") || x.is("[") || x.is("{") {
+            depth += 1;
+        } else if x.is(")") || x.is("]") || x.is("}") {
+            depth -= 1;
+        } else if x.is(";") {
+            break;
+        } else if depth == 0 && x.is("=") {
+            return true;
+        }
+    }
+    false
+}
+
+fn scan_module(
+    p: &mut Parser,
+    path: &Path,
+    kind: DefKind,
+    overrides: &HashMap<String, String>,
+    typedefs: &mut HashMap<String, String>,
+    names: &Interner
+) -> Option<ModuleDef> {
+    let end = format!("end{}", kind.as_str());
+    let begin = p.pos - 1;
+    if p.peek_is("automatic") || p.peek_is("static") {
+        p.pos += 1;
+    }
+    let name = p.next()?;
+    if !name.is_name() {
+        return None;
+    }
+    let mut params = vec![];
+    let mut ports = vec![];
+    let mut insts = vec![];
+    let mut generates = vec![];
+    let mut aliases = vec![];
+    let mut genvars = vec![];
+    let mut issues = vec![];
+    // the parameters in the body are local if the module has a parameter port list
+    let mut param_ports = false;
+    let mut s = PortState { is_input: true, width: 1, range: None, type_name: None, range_expr: None, type_expr: None, unresolved: None };
+    // module header
+    loop {
+        let x = p.peek()?;
+        if x.is("import") {
+            p.statement()?;
+        } else if x.is("#") {
+            p.pos += 1;
+            if !p.peek_is("(") {
+                return None;
+            }
+            process_param_items(p.group()?, None, overrides, false, &mut params);
+            param_ports = true;
+        } else if x.is("(") {
+            let items = p.group()?;
+            if items.iter().any(|x| x.kind == Kind::Macro) {
+                return None;
+            }
+            // a list of port names is declared later in the body
+            let items = split_items(items);
+            if !items.iter().all(|x| x.len() <= 1) {
+                for item in items {
+                    let description = item.first().and_then(|x| x.doc.as_ref());
+                    process_port_item(item, description, eval::Scope { params: &params, typedefs }, &mut s, &mut ports, &mut issues, names);
+                }
+            }
+        } else if x.is(";") {
+            p.pos += 1;
+            break;
+        } else {
+            return None;
+        }
+    }
+    // module body
+    let mut boundary = true;
+    let mut has_logic = false;
+    // labeled generate blocks by the nesting of begin and end, and the scope of the
+    // block of a generate-for loop just found
+    let mut blocks: Vec<Option<GenScope>> = vec![];
+    let mut loop_scope: Option<GenScope> = None;
+    loop {
+        let x = p.peek()?;
+        let mut next_boundary = false;
+        let block_scope = loop_scope.take();
+        // a declaration of a net (but not of a variable) with an assignment is a continuous assignment
+        let is_net = NET_TYPES.iter().any(|k| *k != "var" && x.is(k));
+        if boundary && (LOGIC_KEYWORDS.iter().any(|k| x.is(k)) || (is_net && has_assignment(&p.tokens[p.pos..]))) {
+            has_logic = true;
+        }
+        if x.is(&end) {
+            p.pos += 1;
+            break;
+        } else if declaration_kind(p.tokens, p.pos).is_some() {
+            return None;
+        } else if let Some((_, end)) = SKIPPED_BLOCKS.iter().find(|(b, _)| x.is(b)) {
+            p.skip_until(end)?;
+            next_boundary = true;
+        } else if boundary && x.is("genvar") {
+            p.pos += 1;
+            for item in split_items(p.statement()?) {
+                genvars.extend(item.first().map(|x| x.text.clone()));
+            }
+            next_boundary = true;
+        } else if boundary && x.is("for") && p.tokens.get(p.pos + 1).is_some_and(|x| x.is("(")) {
+            let generate = try_generate_loop(p, &genvars)?;
+            loop_scope = generate.as_ref().and_then(|x| Some(GenScope { label: x.label.clone()?, genvar: Some(x.genvar.clone()) }));
+            generates.extend(generate);
+            next_boundary = true;
+        } else if boundary && x.is("alias") {
+            p.pos += 1;
+            let nets = split_tokens(p.statement()?, "=").into_iter().map(join).collect();
+            aliases.push(AliasDef { nets, line: x.line });
+            next_boundary = true;
+        } else if boundary && x.is("typedef") {
+            p.pos += 1;
+            process_typedef(p.statement()?, None, typedefs);
+            next_boundary = true;
+        } else if boundary && (x.is("import") || x.is("export") || x.is("extern") || x.is("localparam")) {
+            p.statement()?;
+            next_boundary = true;
+        } else if boundary && (x.is("input") || x.is("output") || x.is("inout") || x.is("ref")) {
+            // the ports in a declaration share its description
+            for item in split_items(p.statement()?) {
+                process_port_item(item, x.doc.as_ref(), eval::Scope { params: &params, typedefs }, &mut s, &mut ports, &mut issues, names);
+            }
+            next_boundary = true;
+        } else if boundary && x.is("parameter") {
+            process_param_items(&p.statement()?[1..], x.doc.as_ref(), overrides, param_ports, &mut params);
+            next_boundary = true;
+        } else if x.is("(") || x.is("[") || x.is("{") {
+            p.group()?;
+            next_boundary = true;
+        } else if x.kind == Kind::Macro {
+            p.pos += 1;
+            if p.peek_is("(") {
+                p.group()?;
+            }
+        } else if boundary && x.is_name() {
+            if let Some(mut inst) = try_instance(p, path, &mut issues, names) {
+                inst.scope = blocks.iter().flatten().cloned().collect();
+                insts.push(inst);
+                next_boundary = true;
+            }
+        } else {
+            p.pos += 1;
+            let block = x.is("begin") || x.is("fork") ||
+                (x.kind == Kind::Ident && (x.text.starts_with("end") || x.text.starts_with("join")));
+            // skip the block label, which names the scope of a generate block
+            let label = if block && p.peek_is(":") {
+                p.pos += 2;
+                p.tokens.get(p.pos - 1).map(|x| x.text.clone())
+            } else {
+                None
+            };
+            if x.is("begin") {
+                blocks.push(block_scope.or(label.map(|label| GenScope { label, genvar: None })));
+            } else if x.is("end") {
+                blocks.pop();
+            }
+            next_boundary = block || x.is(";") || x.is(":") || x.is("else") ||
+                x.is("generate") || x.is("default");
+        }
+        boundary = next_boundary;
+    }
+    let body: Vec<&str> = p.tokens[begin..p.pos].iter().map(|x| x.text.as_str()).collect();
+    // the end label is a part of the declaration
+    let last = match (p.tokens.get(p.pos), p.tokens.get(p.pos + 1)) {
+        (Some(x), Some(y)) if x.is(":") && y.is_name() => y,
+        _ => &p.tokens[p.pos - 1]
+    };
+    let (def_name, symbol) = names.intern(&name.text);
+    Some(ModuleDef {
+        name: def_name,
+        symbol,
+        kind,
+        file: names.path(path),
+        line: name.line,
+        description: p.tokens[begin].doc.clone(),
+        span: Some((p.tokens[begin].offset, last.offset + last.text.len())),
+        body_hash: fnv1a(body.join(" ").as_bytes()),
+        params,
+        ports,
+        insts,
+        generates,
+        aliases,
+        assigns: vec![],
+        also_defined_in: vec![],
+        has_logic,
+        nets: vec![],
+        conns: vec![],
+        const_pins: vec![],
+        net_widths: HashMap::new(),
+        pin_exprs: vec![],
+        issues
+    })
+}
+
+// scan the source text of a file; defines and typedefs are updated by the
+// directives and type definitions in it
+pub fn scan(
+    path: &Path,
+    text: &str,
+    defines: &mut HashMap<String, Option<Define>>,
+    overrides: &HashMap<String, String>,
+    typedefs: &mut HashMap<String, String>,
+    names: &Interner
+) -> Option<(Vec<ModuleDef>, Vec<PackageDef>)> {
+    // the keywords are those of IEEE 1800-2017, so the parser handles another set
+    // selected by `begin_keywords
+    let mut directives = vec![];
+    let tokens = tokenize_with_uses(text, defines, &mut vec![], &mut directives)?;
+    if directives.iter().any(|x| x.0 == "begin_keywords") {
+        return None;
+    }
+    let mut p = Parser { tokens: &tokens, pos: 0 };
+    let mut defs = vec![];
+    let mut packages = vec![];
+    let mut package = None;
+    while p.pos < tokens.len() {
+        let x = &tokens[p.pos];
+        let kind = declaration_kind(&tokens, p.pos);
+        p.pos += 1;
+        if let Some(kind) = kind {
+            defs.push(scan_module(&mut p, path, kind, overrides, typedefs, names)?);
+        } else if x.is("package") {
+            if p.peek_is("automatic") || p.peek_is("static") {
+                p.pos += 1;
+            }
+            let name = p.next()?;
+            packages.push(PackageDef {
+                name: name.text.clone(),
+                file: names.path(path),
+                line: name.line,
+                params: vec![],
+                typedefs: vec![],
+                functions: vec![]
+            });
+            package = Some(name.text.as_str());
+        } else if x.is("endpackage") {
+            package = None;
+        } else if x.is("typedef") {
+            let typedef = process_typedef(p.statement()?, package, typedefs);
+            if let Some(def) = packages.last_mut().filter(|_| package.is_some()) {
+                def.typedefs.extend(typedef);
+            }
+        } else if let Some(def) = packages.last_mut().filter(|_| package.is_some()) {
+            // the parameters of a package are registered with the package name like
+            // the type definitions, with the values evaluated if possible
+            if x.is("parameter") || x.is("localparam") {
+                let count = def.params.len();
+                process_param_items(p.statement()?, None, &HashMap::new(), false, &mut def.params);
+                def.params.retain(|x| x.value.is_some());
+                for i in count..def.params.len() {
+                    let text = def.params[i].value.clone().unwrap_or_default();
+                    let value = eval::eval(&text, eval::Scope { params: &def.params[..i], typedefs }).map_or(text, |x| x.to_string());
+                    typedefs.insert(format!("{}::{}", def.name, def.params[i].name), value.clone());
+                    typedefs.insert(def.params[i].name.clone(), value.clone());
+                    def.params[i].value = Some(value);
+                }
+            } else if x.is("function") {
+                def.functions.extend(process_function_header(p.statement()?));
+                p.skip_until("endfunction")?;
+            }
+        }
+    }
+    Some((defs, packages))
+}
+
+// macros which a text depends on: those expanded, including the ones in the
+// bodies of the expanded macros, and those tested by `ifdef, `ifndef, and `elsif
+// in the active code, in the order of the first use; defines are updated by the
+// directives in the text
+pub fn used_defines(
+    text: &str,
+    defines: &mut HashMap<String, Option<Define>>
+) -> Option<Vec<String>> {
+    let mut used = vec![];
+    tokenize_with_uses(text, defines, &mut used, &mut vec![])?;
+    Some(used)
+}
+
+// directives which take the rest of the line, e.g. `timescale and `resetall, in the
+// active code as (name, argument, line); defines are updated by the directives in
+// the text
+pub fn line_directives(
+    text: &str,
+    defines: &mut HashMap<String, Option<Define>>
+) -> Option<Vec<(String, String, usize)>> {
+    let mut directives = vec![];
+    tokenize_with_uses(text, defines, &mut vec![], &mut directives)?;
+    Some(directives)
+}
+
+// module, interface, program, and package declarations in a text as (kind, name,
+// byte offset of the beginning, byte offset of the end including the end label)
+pub fn declarations(
+    text: &str,
+    defines: &mut HashMap<String, Option<Define>>
+) -> Option<Vec<(&'static str, String, usize, usize)>> {
+    let tokens = tokenize(text, defines)?;
+    let mut p = Parser { tokens: &tokens, pos: 0 };
+    let mut ret = vec![];
+    while p.pos < tokens.len() {
+        let begin = &tokens[p.pos];
+        let kind = match declaration_kind(&tokens, p.pos) {
+            None if begin.is("package") => "package",
+            None => {
+                p.pos += 1;
+                continue;
+            }
+            Some(x) => x.as_str()
+        };
+        p.pos += 1;
+        if p.peek_is("automatic") || p.peek_is("static") {
+            p.pos += 1;
+        }
+        let name = p.next()?;
+        p.skip_until(&format!("end{}", kind))?;
+        let mut last = &tokens[p.pos - 1];
+        if p.peek_is(":") && p.tokens.get(p.pos + 1).is_some_and(|x| x.is_name()) {
+            last = &tokens[p.pos + 1];
+            p.pos += 2;
+        }
+        ret.push((kind, name.text.clone(), begin.offset, last.offset + last.text.len()));
+    }
+    Some(ret)
+}
+
+// tokens of a text as (kind, text, byte offset)
+pub fn lex(
+    text: &str
+) -> Option<Vec<(&'static str, String, usize)>> {
+    let tokens = tokenize(text, &mut HashMap::new())?;
+    let kind = |x: &Token| match x.kind {
+        Kind::Ident if KEYWORDS.contains(&x.text.as_str()) => "keyword",
+        Kind::Ident => "identifier",
+        Kind::Number => "number",
+        Kind::Str => "string",
+        Kind::Symbol => "symbol",
+        Kind::Macro => "macro"
+    };
+    Some(tokens.iter().map(|x| (kind(x), x.text.clone(), x.offset)).collect())
+}
+
+// whether the text contains anything other than whitespace and comments
+pub fn has_tokens(
+    text: &str
+) -> bool {
+    tokenize(text, &mut HashMap::new()).is_none_or(|x| !x.is_empty())
+}
+
+// names of the modules, interfaces, and programs declared in the text
+pub fn module_names(
+    text: &str
+) -> Vec<String> {
+    let Some(tokens) = tokenize(text, &mut HashMap::new()) else { return vec![]; };
+    let mut ret = vec![];
+    for i in 0..tokens.len() {
+        if declaration_kind(&tokens, i).is_none() {
+            continue;
+        }
+        let name = tokens[i + 1..].iter().find(|x| !x.is("automatic") && !x.is("static"));
+        if let Some(name) = name.filter(|x| x.is_name()) {
+            ret.push(name.text.clone());
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs::read_to_string;
+    use std::path::{Path, PathBuf};
+    use crate::{analyze_file, Defines, Options};
+    use super::*;
+
+    // modules with their ports (name, direction, and width) and instances
+    type Summary = Vec<(String, String, Vec<(String, String, i32)>, Vec<(String, String)>)>;
+
+    fn summary(defs: &[ModuleDef]) -> Summary {
+        defs.iter()
+            .map(|x| (
+                String::from(&*x.name),
+                String::from(x.kind.as_str()),
+                x.ports.iter().map(|y| (String::from(&*y.name), y.dir.clone(), y.width)).collect(),
+                x.insts.iter().map(|y| (String::from(&*y.mod_name), String::from(&*y.inst_name))).collect()
+            ))
+            .collect()
+    }
+
+    fn scan_text(text: &str, defines: &[&str]) -> Option<Summary> {
+        let mut defines: HashMap<String, Option<Define>> = defines.iter().map(|x| (String::from(*x), None)).collect();
+        let (defs, _) = scan(Path::new("test.sv"), text, &mut defines, &HashMap::new(), &mut HashMap::new(), &Interner::new())?;
+        Some(summary(&defs))
+    }
+
+    fn names(summary: &Summary) -> Vec<&str> {
+        summary.iter().map(|x| x.0.as_str()).collect()
+    }
+
+    #[test]
+    fn agrees_with_parser_on_samples() {
+        for name in ["ranges.sv", "types.sv", "sample.sv"] {
+            let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "sample", name].iter().collect();
+            let text = read_to_string(&path).unwrap();
+            let names = Interner::new();
+            let scanned = scan(&path, &text, &mut HashMap::new(), &HashMap::new(), &mut HashMap::new(), &names).unwrap();
+            let parsed = analyze_file(&path, &Defines::new(), &Options::default(), &names).unwrap();
+            assert!(!scanned.0.is_empty(), "{}", name);
+            assert_eq!(summary(&scanned.0), summary(&parsed.defs), "{}", name);
+        }
+    }
+
+    #[test]
+    fn conditional_compilation() {
+        let text = "
+            `ifdef FAST
+            module fast; endmodule
+            `elsif SLOW
+            module slow; endmodule
+            `else
+            module other; endmodule
+            `endif
+            `ifndef FAST
+            `define DEFINED_HERE
+            `endif
+            `ifdef DEFINED_HERE
+            module defined; endmodule
+            `endif
+        ";
+        assert_eq!(names(&scan_text(text, &["FAST"]).unwrap()), ["fast"]);
+        assert_eq!(names(&scan_text(text, &["SLOW"]).unwrap()), ["slow", "defined"]);
+        assert_eq!(names(&scan_text(text, &[]).unwrap()), ["other", "defined"]);
+    }
+
+    #[test]
+    fn comments_and_strings() {
+        let text = "
+            // module in_line_comment; endmodule
+            /* module in_block_comment; endmodule */
+            module top (input a, output [3:0] y);
+                initial $display(\"module in_string; endmodule\");
+                sub u_sub (.a(a), .y(y)); // sub u_comment ();
+            endmodule
+        ";
+        let summary = scan_text(text, &[]).unwrap();
+        assert_eq!(names(&summary), ["top"]);
+        assert_eq!(summary[0].2, [(String::from("a"), String::from("input"), 1), (String::from("y"), String::from("output"), 4)]);
+        assert_eq!(summary[0].3, [(String::from("sub"), String::from("u_sub"))]);
+    }
+
+    #[test]
+    fn begin_keywords_left_to_parser() {
+        assert!(scan_text("`begin_keywords \"1364-2005\"\nmodule m; endmodule\n`end_keywords\n", &[]).is_none());
+    }
+}
@@ -0,0 +1,272 @@
+use std::fs::read_to_string;
+use std::path::PathBuf;
+use serde_json::Value;
+use structopt::StructOpt;
+use tracing::error;
+use crate::logging::{self, LogOpt};
+use crate::escape_str;
+
+// JSON Schema of the output, in which the output in YAML is regarded as JSON
+pub const SCHEMA: &str = include_str!("../schema/output.schema.json");
+
+#[derive(StructOpt)]
+#[structopt(name = "schema", about = "Print the JSON Schema of the output, or validate output files against it")]
+pub struct SchemaOpt {
+    /// Output file to be validated
+    #[structopt(long = "validate", parse(from_os_str), multiple = true, number_of_values = 1)]
+    pub validate: Vec<PathBuf>,
+
+    #[structopt(flatten)]
+    pub log: LogOpt
+}
+
+fn type_name(
+    value: &Value
+) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(x) if x.is_i64() || x.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object"
+    }
+}
+
+// validate a value with the subset of JSON Schema used in the schema: $ref to the
+// definitions, type, enum, const, minimum, required, properties,
+// additionalProperties (false only), and items
+fn validate(
+    value: &Value,
+    schema: &Value,
+    root: &Value,
+    path: &str,
+    errors: &mut Vec<String>
+) {
+    if let Some(name) = schema["$ref"].as_str().and_then(|x| x.strip_prefix("#/definitions/")) {
+        validate(value, &root["definitions"][name], root, path, errors);
+        return;
+    }
+    let actual = type_name(value);
+    let types: Vec<&str> = match &schema["type"] {
+        Value::String(x) => vec![x.as_str()],
+        Value::Array(x) => x.iter().filter_map(|x| x.as_str()).collect(),
+        _ => vec![]
+    };
+    // an integer is also a number
+    if !types.is_empty() && !types.iter().any(|&x| x == actual || (x == "number" && actual == "integer")) {
+        errors.push(format!("{}: expected {}, found {}", path, types.join(" or "), actual));
+        return;
+    }
+    if let Some(values) = schema["enum"].as_array() {
+        if !values.contains(value) {
+            errors.push(format!("{}: unexpected value {}", path, value));
+        }
+    }
+    if let Some(x) = schema.get("const").filter(|&x| x != value) {
+        errors.push(format!("{}: expected {}, found {}", path, x, value));
+    }
+    if let (Some(min), Some(x)) = (schema["minimum"].as_f64(), value.as_f64()) {
+        if x < min {
+            errors.push(format!("{}: {} is less than {}", path, x, min));
+        }
+    }
+    match value {
+        Value::Object(x) => {
+            for name in schema["required"].as_array().into_iter().flatten().filter_map(|x| x.as_str()) {
+                if !x.contains_key(name) {
+                    errors.push(format!("{}: missing {}", path, name));
+                }
+            }
+            for (name, value) in x {
+                if let Some(schema) = schema["properties"].get(name) {
+                    validate(value, schema, root, &format!("{}.{}", path, name), errors);
+                } else if schema["additionalProperties"] == Value::Bool(false) {
+                    errors.push(format!("{}: unexpected {}", path, name));
+                }
+            }
+        }
+        Value::Array(x) => {
+            if let Some(schema) = schema.get("items") {
+                for (i, value) in x.iter().enumerate() {
+                    validate(value, schema, root, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        _ => ()
+    }
+}
+
+pub fn run(
+    opt: &SchemaOpt
+) -> i32 {
+    logging::init(&opt.log);
+    if opt.validate.is_empty() {
+        print!("{}", SCHEMA);
+        return 0;
+    }
+    let root: Value = serde_json::from_str(SCHEMA).unwrap();
+    let mut exit_code = 0;
+    println!("validated:");
+    for path in &opt.validate {
+        let Ok(text) = read_to_string(path) else {
+            error!("failed to read: {:?}", path);
+            exit_code = 1;
+            continue;
+        };
+        // YAML is a superset of JSON, so that a file in either format is accepted
+        let mut errors = vec![];
+        match serde_yaml::from_str::<Value>(&text) {
+            Ok(value) => validate(&value, &root, &root, "$", &mut errors),
+            Err(x) => errors.push(format!("invalid YAML: {}", x))
+        }
+        println!("  - file_name: {}", escape_str(path.to_str().unwrap()));
+        println!("    schema_version: {}", escape_str(root["version"].as_str().unwrap_or_default()));
+        if errors.is_empty() {
+            println!("    errors: []");
+            continue;
+        }
+        exit_code = 1;
+        println!("    errors:");
+        for x in &errors {
+            println!("      - {}", escape_str(x));
+        }
+    }
+    exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs::{read_to_string, write};
+    use std::path::{Path, PathBuf};
+    use serde_json::Value;
+    use structopt::StructOpt;
+    use crate::{run_opt, Opt, CAPTURE};
+    use super::{validate, SCHEMA};
+
+    const SOURCE: &str = "`define W 8
+`define GONE
+`undef GONE
+`unconnected_drive pull1
+package pkg;
+  parameter P = 4;
+  typedef logic [P-1:0] word_t;
+  function automatic int f(int x); return x; endfunction
+endpackage
+`nounconnected_drive
+typedef int unit_t;
+interface bus_if; logic v; endinterface
+program prog; endprogram
+// a flop
+module leaf #(parameter W = 2) (input clk, input [W-1:0] d, output logic [W-1:0] q);
+  always_ff @(posedge clk) q <= d;
+endmodule
+module top (input clk, input rst_n, input [`W-1:0] a, input [3:0] s_axi_awaddr, output [`W-1:0] y);
+  wire [`W-1:0] n;
+  wire [1:0] m;
+  alias m = n[1:0];
+  assign y = n ^ a;
+  leaf #(.W(`W)) u_leaf (.clk(clk), .d(a), .q(n));
+  for (genvar i = 0; i < 2; i++) begin : gen_cells
+    CKLN1 u_icg (.CK(clk), .E(1'b1), .Q());
+    leaf #(.W(1)) u_bit (.clk(clk), .d(a[i]), .q());
+  end
+  if (`W > 4) begin : gen_wide
+    missing u_missing (.a(a[3:0]));
+  end
+  bus_if u_if ();
+endmodule
+";
+
+    // output of a run on the files in a directory with the options
+    fn run_in(
+        dir: &Path,
+        args: &[&str]
+    ) -> String {
+        let mut argv = vec![String::from("svinst_port")];
+        argv.extend(args.iter().map(|x| x.replace("{}", &dir.to_string_lossy())));
+        argv.push(dir.join("top.sv").to_string_lossy().into_owned());
+        CAPTURE.with(|x| *x.borrow_mut() = Some(String::new()));
+        run_opt(Opt::from_iter(&argv), HashMap::new());
+        CAPTURE.with(|x| x.borrow_mut().take()).unwrap_or_default()
+    }
+
+    #[test]
+    fn output_of_options_is_valid() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("top.sv"), SOURCE).unwrap();
+        write(dir.path().join("cells.lib"), "library(x) {\n  cell(CKLN1) {\n  }\n}\n").unwrap();
+        write(dir.path().join("special.yaml"), "- kind: clock_gate\n  patterns: [\"CKLN*\"]\n  clock_port: CK\n").unwrap();
+        write(dir.path().join("blackbox.txt"), "missing\n").unwrap();
+        let root: Value = serde_json::from_str(SCHEMA).unwrap();
+        let cases: &[&[&str]] = &[
+            &[],
+            &["--full-tree"],
+            &["--full-tree", "--tree-format", "json"],
+            &["--tokens"],
+            &["--separate", "--single-unit"],
+            &["--scan-only"],
+            &["--top", "top", "--flat-paths", "--inst-params"],
+            &["--top", "top", "--inst-counts"],
+            &["--connectivity", "--net-report"],
+            &["--file-meta"],
+            &["--src-spans", "--emit-module-text"],
+            &["--emit-manifest", "{}/manifest.json"],
+            &["--baseline", "{}/manifest.json"],
+            &["--unit-decls"],
+            &["--top", "top", "--config", "cfg:W=4"],
+            &["--top", "top", "--pinout"],
+            &["--buses"],
+            &["--schema", "port-v2"],
+            &["--used-defines"],
+            &["--liberty", "{}/cells.lib"],
+            &["--liberty", "{}/cells.lib", "--special-cells", "{}/special.yaml"],
+            &["--eval-generates"],
+            &["--inst-counts"],
+            &["--blackbox", "{}/blackbox.txt", "--top", "top"],
+            &["--top", "top", "--flat-paths", "--flatten-uniquify"],
+            &["--show-macro-defs"],
+            &["--show-macro-delta"],
+            &["--limit", "1", "--offset", "1"],
+            &["--port-clocks"],
+            &["--top", "top", "--port-clocks"],
+            &["--top", "top", "--leaf-cells"],
+            &["--assigns"],
+            &["--drive-directives"],
+            &["--package-decls"],
+            &["--const-pins"],
+            &["--vfs"],
+            &["--meta"],
+        ];
+        for args in cases {
+            let output = run_in(dir.path(), args);
+            let value: Value = serde_yaml::from_str(&output).unwrap_or_else(|x| panic!("{:?}: {}\n{}", args, x, output));
+            let mut errors = vec![];
+            validate(&value, &root, &root, "$", &mut errors);
+            assert!(errors.is_empty(), "{:?}: {:?}", args, errors);
+        }
+    }
+
+    #[test]
+    fn samples_are_valid() {
+        let root: Value = serde_json::from_str(SCHEMA).unwrap();
+        for name in ["ranges.yaml", "types.yaml"] {
+            let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "sample", name].iter().collect();
+            let value: Value = serde_yaml::from_str(&read_to_string(&path).unwrap()).unwrap();
+            let mut errors = vec![];
+            validate(&value, &root, &root, "$", &mut errors);
+            assert!(errors.is_empty(), "{}: {:?}", name, errors);
+        }
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let root: Value = serde_json::from_str(SCHEMA).unwrap();
+        let value: Value = serde_json::json!({ "files": [{ "file_name": "a.sv", "defs": [], "extra": 1 }] });
+        let mut errors = vec![];
+        validate(&value, &root, &root, "$", &mut errors);
+        assert_eq!(errors, vec![String::from("$.files[0]: unexpected extra")]);
+    }
+}
@@ -0,0 +1,242 @@
+// Providers of the source files, for both the input files and the include files:
+// the file system, the members of the archives, and an overlay of texts in memory
+// (e.g. the unsaved buffers of an editor). With --vfs or any overlay, the include
+// files are inlined through the provider into the text of the compilation unit,
+// which is parsed in memory instead of through a temporary file.
+
+use std::collections::HashMap;
+use std::fs::read;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::{archive, flatten};
+
+pub trait SourceProvider {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+    fn is_file(&self, path: &Path) -> bool;
+}
+
+pub struct FsProvider;
+
+impl SourceProvider for FsProvider {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        read(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+}
+
+// the members of the archives by the paths shown in the output ("<archive>/<member>"),
+// and the other files from the base provider
+pub struct ArchiveProvider<P: SourceProvider> {
+    pub base: P
+}
+
+impl<P: SourceProvider> SourceProvider for ArchiveProvider<P> {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.base.read(&archive::resolve(path))
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.base.is_file(&archive::resolve(path))
+    }
+}
+
+// texts in memory in place of the files of the same paths, over the base provider
+pub struct OverlayProvider<P: SourceProvider> {
+    pub files: HashMap<PathBuf, Vec<u8>>,
+    pub base: P
+}
+
+impl<P: SourceProvider> SourceProvider for OverlayProvider<P> {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self.files.get(path) {
+            Some(x) => Ok(x.clone()),
+            None => self.base.read(path)
+        }
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.contains_key(path) || self.base.is_file(path)
+    }
+}
+
+// an include file is searched as the preprocessor does: the name itself (relative
+// to the current directory), then the name in each include path if it is relative
+fn resolve_include(
+    provider: &dyn SourceProvider,
+    name: &str,
+    includes: &[PathBuf]
+) -> Option<PathBuf> {
+    let path = PathBuf::from(name);
+    if provider.is_file(&path) || path.is_absolute() {
+        return provider.is_file(&path).then_some(path);
+    }
+    includes.iter().map(|x| x.join(&path)).find(|x| provider.is_file(x))
+}
+
+// part of the unit text copied from a file: the position in the unit text, the
+// file, and the position in the file
+pub struct Piece {
+    pub begin: usize,
+    pub path: PathBuf,
+    pub offset: usize
+}
+
+// text of a compilation unit with the include files inlined
+pub struct Inliner<'a> {
+    pub provider: &'a dyn SourceProvider,
+    pub includes: &'a [PathBuf],
+    pub max_depth: usize,
+    // reads an include file as the input files are read (e.g. sanitized)
+    pub read: &'a dyn Fn(&Path) -> Result<String, String>,
+    pub pieces: Vec<Piece>,
+    // texts of the include files
+    pub texts: HashMap<PathBuf, String>,
+    stack: Vec<PathBuf>
+}
+
+impl<'a> Inliner<'a> {
+    pub fn new(
+        provider: &'a dyn SourceProvider,
+        includes: &'a [PathBuf],
+        max_depth: usize,
+        read: &'a dyn Fn(&Path) -> Result<String, String>
+    ) -> Self {
+        Inliner { provider, includes, max_depth, read, pieces: vec![], texts: HashMap::new(), stack: vec![] }
+    }
+
+    // append the text of a file to the unit text with the include files inlined; an
+    // include file which is not found, or which includes itself behind an include
+    // guard, is left to the preprocessor, which reports it only in the active code,
+    // and a cycle of the include files without a guard is an error with its chain
+    pub fn append(
+        &mut self,
+        path: &Path,
+        text: &str,
+        out: &mut String
+    ) -> Result<(), String> {
+        if self.stack.len() > self.max_depth {
+            let chain = self.stack.iter().map(|x| x.as_path()).chain(std::iter::once(path)).map(|x| x.to_string_lossy()).collect::<Vec<_>>().join(" -> ");
+            return Err(format!("include depth exceeds {}: {}", self.max_depth, chain));
+        }
+        self.stack.push(path.to_path_buf());
+        let mut pos = 0;
+        for (begin, end, name) in flatten::find_includes(text) {
+            let Some(file) = resolve_include(self.provider, &name, self.includes) else { continue; };
+            if let Some(i) = self.stack.iter().position(|x| *x == file) {
+                let guarded = self.stack[i..].iter().any(|x| {
+                    let text = self.texts.get(x).cloned().or_else(|| (self.read)(x).ok());
+                    text.is_some_and(|x| flatten::has_include_guard(&x))
+                });
+                if guarded {
+                    continue;
+                }
+                let chain = self.stack[i..].iter().chain(std::iter::once(&file)).map(|x| x.to_string_lossy()).collect::<Vec<_>>().join(" -> ");
+                return Err(format!("include cycle: {}", chain));
+            }
+            self.pieces.push(Piece { begin: out.len(), path: path.to_path_buf(), offset: pos });
+            out.push_str(&text[pos..begin]);
+            pos = end;
+            let included = match self.texts.get(&file) {
+                Some(x) => x.clone(),
+                None => (self.read)(&file).map_err(|x| format!("failed to read: {:?} ({})", file, x))?
+            };
+            self.texts.insert(file.clone(), included.clone());
+            self.append(&file, &included, out)?;
+        }
+        self.pieces.push(Piece { begin: out.len(), path: path.to_path_buf(), offset: pos });
+        out.push_str(&text[pos..]);
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, write};
+    use structopt::StructOpt;
+    use crate::{logging, run_opt, Opt, CAPTURE};
+    use super::*;
+
+    fn inline(
+        provider: &dyn SourceProvider,
+        path: &Path,
+        includes: &[PathBuf],
+        max_depth: usize
+    ) -> Result<(String, Vec<Piece>), String> {
+        let read = |x: &Path| provider.read(x).map(|x| String::from_utf8_lossy(&x).into_owned()).map_err(|x| x.to_string());
+        let mut inliner = Inliner::new(provider, includes, max_depth, &read);
+        let mut out = String::new();
+        inliner.append(path, &read(path)?, &mut out)?;
+        Ok((out, inliner.pieces))
+    }
+
+    fn overlay(
+        files: &[(&str, &str)]
+    ) -> OverlayProvider<FsProvider> {
+        let files = files.iter().map(|(path, text)| (PathBuf::from(path), text.as_bytes().to_vec())).collect();
+        OverlayProvider { files, base: FsProvider }
+    }
+
+    #[test]
+    fn overlays_replace_files() {
+        let dir = tempfile::tempdir().unwrap();
+        create_dir_all(dir.path().join("inc")).unwrap();
+        write(dir.path().join("inc/a.svh"), "typedef int saved_t;\n").unwrap();
+        write(dir.path().join("inc/b.svh"), "typedef int b_t;\n").unwrap();
+        let a = dir.path().join("inc/a.svh");
+        let provider = overlay(&[("top.sv", "`include \"a.svh\"\n`include \"b.svh\"\nmodule top; endmodule\n"), (a.to_str().unwrap(), "typedef int buffer_t;\n")]);
+        let (text, pieces) = inline(&provider, Path::new("top.sv"), &[dir.path().join("inc")], 10).unwrap();
+        assert_eq!(text, "typedef int buffer_t;\n\ntypedef int b_t;\n\nmodule top; endmodule\n");
+        // each piece maps the unit text back to its file
+        let pieces: Vec<_> = pieces.into_iter().map(|x| (x.begin, x.path, x.offset)).collect();
+        assert_eq!(pieces, [
+            (0, PathBuf::from("top.sv"), 0),
+            (0, a, 0),
+            (22, PathBuf::from("top.sv"), 16),
+            (23, dir.path().join("inc/b.svh"), 0),
+            (40, PathBuf::from("top.sv"), 33)
+        ]);
+    }
+
+    #[test]
+    fn guarded_includes_are_left_to_preprocessor() {
+        let provider = overlay(&[
+            ("top.sv", "`include \"g.svh\"\n"),
+            ("g.svh", "`ifndef G_SVH\n`define G_SVH\n`include \"g.svh\"\n`endif\n"),
+            ("u.svh", "`include \"missing.svh\"\n")
+        ]);
+        let (text, _) = inline(&provider, Path::new("top.sv"), &[], 10).unwrap();
+        assert_eq!(text, "`ifndef G_SVH\n`define G_SVH\n`include \"g.svh\"\n`endif\n\n");
+        let (text, _) = inline(&provider, Path::new("u.svh"), &[], 10).unwrap();
+        assert_eq!(text, "`include \"missing.svh\"\n");
+    }
+
+    #[test]
+    fn include_cycles_and_depth_are_errors() {
+        let provider = overlay(&[
+            ("top.sv", "`include \"a.svh\"\n"),
+            ("a.svh", "`include \"b.svh\"\n"),
+            ("b.svh", "`include \"a.svh\"\n"),
+            ("c.svh", "`include \"d.svh\"\n"),
+            ("d.svh", "\n")
+        ]);
+        assert_eq!(inline(&provider, Path::new("top.sv"), &[], 10).err().as_deref(), Some("include cycle: a.svh -> b.svh -> a.svh"));
+        assert!(inline(&provider, Path::new("c.svh"), &[], 1).is_ok());
+        assert_eq!(inline(&provider, Path::new("c.svh"), &[], 0).err().as_deref(), Some("include depth exceeds 0: c.svh -> d.svh"));
+    }
+
+    #[test]
+    fn parse_errors_are_shown_from_overlays() {
+        // the file exists only in the overlay
+        let overlays = HashMap::from([(PathBuf::from("unsaved.sv"), b"module m;\n  wire x\nendmodule\n".to_vec())]);
+        CAPTURE.with(|x| *x.borrow_mut() = Some(String::new()));
+        logging::begin_capture();
+        assert_eq!(run_opt(Opt::from_iter(["svinst_port", "unsaved.sv"]), overlays), 1);
+        let messages = logging::take_capture();
+        CAPTURE.with(|x| x.borrow_mut().take());
+        assert!(messages.iter().any(|x| x.contains("parse failed: \"unsaved.sv\"\n unsaved.sv:3:1\n")), "{:?}", messages);
+    }
+}
@@ -0,0 +1,97 @@
+// Special cells given by name patterns, such as clock gates and synchronizers: their
+// instances are counted and located for each clock domain, which is named by the
+// net connected to the clock port in the instantiating module.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use serde::Deserialize;
+use crate::{escape_str, pinout, FileResult, InstDef, ModuleDef};
+
+// a kind of special cells; the clock port is guessed by the names of the port
+// connections if not given
+#[derive(Deserialize)]
+pub struct SpecialCell {
+    pub kind: String,
+    pub patterns: Vec<String>,
+    #[serde(default)]
+    pub clock_port: Option<String>
+}
+
+pub fn read_special_cells(
+    path: &Path
+) -> Result<Vec<SpecialCell>, Box<dyn std::error::Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_yaml::from_reader(reader)?)
+}
+
+// whether a name matches a pattern with the wildcards `*` and `?`
+pub fn glob_match(
+    pattern: &[u8],
+    name: &[u8]
+) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => glob_match(rest, name) || (!name.is_empty() && glob_match(pattern, &name[1..])),
+        (Some((&p, rest)), Some((&c, name))) => (p == b'?' || p == c) && glob_match(rest, name),
+        _ => false
+    }
+}
+
+// net connected to the clock port of an instance, if any
+fn clock_net<'a>(
+    parent: &'a ModuleDef,
+    inst: &InstDef,
+    cell: &SpecialCell
+) -> Option<&'a str> {
+    parent.conns.iter()
+        .filter(|x| x.kind == "pin" && x.inst_name.as_deref() == Some(&*inst.inst_name) && !x.net.is_empty())
+        .find(|x| match (&cell.clock_port, &x.port_name) {
+            (Some(port), Some(name)) => port == name,
+            (None, Some(name)) => pinout::port_role(name) == "clock",
+            _ => false
+        })
+        .map(|x| x.net.as_str())
+}
+
+pub fn print_special_cells(
+    results: &[FileResult],
+    cells: &[SpecialCell]
+) {
+    for cell in cells {
+        // instances of the cells grouped by the clock nets in the order of appearance
+        let mut domains: Vec<(&str, Vec<(&ModuleDef, &InstDef)>)> = vec![];
+        for parent in results.iter().flat_map(|x| &x.defs) {
+            for inst in &parent.insts {
+                if !cell.patterns.iter().any(|x| glob_match(x.as_bytes(), inst.mod_name.as_bytes())) {
+                    continue;
+                }
+                let clock = clock_net(parent, inst, cell).unwrap_or("");
+                match domains.iter_mut().find(|x| x.0 == clock) {
+                    Some(x) => x.1.push((parent, inst)),
+                    None => domains.push((clock, vec![(parent, inst)]))
+                }
+            }
+        }
+        outln!("  - kind: {}", escape_str(&cell.kind));
+        outln!("    count: {}", domains.iter().map(|x| x.1.len()).sum::<usize>());
+        if domains.is_empty() {
+            outln!("    domains: []");
+            continue;
+        }
+        outln!("    domains:");
+        for (clock, insts) in &domains {
+            // an empty name for the instances whose clock is not found
+            outln!("      - clock_net: {}", escape_str(clock));
+            outln!("        count: {}", insts.len());
+            outln!("        insts:");
+            for (parent, inst) in insts {
+                outln!("          - mod_name: {}", escape_str(&inst.mod_name));
+                outln!("            inst_name: {}", escape_str(&inst.inst_name));
+                outln!("            parent_name: {}", escape_str(&parent.name));
+                outln!("            file_name: {}", escape_str(inst.file.to_str().unwrap()));
+                outln!("            line: {}", inst.line);
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::fs::{create_dir_all, read, write};
+use std::path::PathBuf;
+use structopt::StructOpt;
+use tracing::error;
+use crate::logging::{self, LogOpt};
+use crate::{escape_str, read_defines, read_sanitized, scan};
+
+#[derive(StructOpt)]
+#[structopt(name = "split", about = "Write each declaration into its own file")]
+pub struct SplitOpt {
+    /// A list of source files
+    #[structopt(parse(from_os_str), required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Output directory
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    pub output: PathBuf,
+
+    /// Define
+    #[structopt(short = "d", long = "define", multiple = true, number_of_values = 1)]
+    pub defines: Vec<String>,
+
+    #[structopt(flatten)]
+    pub log: LogOpt
+}
+
+// beginning of the comment lines immediately preceding a declaration, which are
+// kept with the declaration; the text before the limit is not taken
+fn comment_start(
+    text: &str,
+    begin: usize,
+    limit: usize
+) -> usize {
+    let mut start = text[..begin].rfind('\n').map_or(0, |x| x + 1);
+    if !text[start..begin].trim().is_empty() || start < limit {
+        return begin;
+    }
+    while start > limit {
+        let prev_start = text[..start - 1].rfind('\n').map_or(0, |x| x + 1);
+        let prev = text[prev_start..start - 1].trim();
+        if prev_start >= limit && prev.starts_with("//") {
+            start = prev_start;
+            continue;
+        }
+        if !prev.ends_with("*/") {
+            break;
+        }
+        let Some(open) = text[..start].rfind("/*") else { break; };
+        let open_start = text[..open].rfind('\n').map_or(0, |x| x + 1);
+        if open_start < limit || !text[open_start..open].trim().is_empty() {
+            break;
+        }
+        start = open_start;
+    }
+    start
+}
+
+pub fn run(
+    opt: &SplitOpt
+) -> i32 {
+    logging::init(&opt.log);
+    let mut exit_code = 0;
+    let mut defines = read_defines(&opt.defines);
+    let mut written: HashMap<String, PathBuf> = HashMap::new();
+    if let Err(x) = create_dir_all(&opt.output) {
+        error!("failed to create directory: {:?} ({})", opt.output, x);
+        return 1;
+    }
+    println!("split:");
+    for path in &opt.files {
+        let (Some(text), Ok(org)) = (read_sanitized(path), read(path)) else {
+            error!("failed to read: {:?}", path);
+            exit_code = 1;
+            continue;
+        };
+        let Some(decls) = scan::declarations(&text, &mut defines) else {
+            error!("split failed: {:?}", path);
+            exit_code = 1;
+            continue;
+        };
+        let ext = path.extension().and_then(|x| x.to_str()).unwrap_or("sv");
+        let mut limit = 0;
+        for (kind, name, begin, end) in decls {
+            let start = comment_start(&text, begin, limit);
+            limit = end;
+            let file_name: String = name.chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '$' { c } else { '_' })
+                .collect();
+            let out_path = opt.output.join(format!("{}.{}", file_name, ext));
+            if let Some(first) = written.get(&file_name) {
+                error!("duplicate declaration: {} ({:?}, {:?})", name, first, path);
+                exit_code = 1;
+                continue;
+            }
+            let mut out = org[start..end].to_vec();
+            out.push(b'\n');
+            if let Err(x) = write(&out_path, out) {
+                error!("failed to write: {:?} ({})", out_path, x);
+                exit_code = 1;
+                continue;
+            }
+            written.insert(file_name, path.clone());
+            println!("  - mod_name: {}", escape_str(&name));
+            println!("    kind: {}", escape_str(kind));
+            println!("    file_name: {}", escape_str(path.to_str().unwrap()));
+            println!("    output: {}", escape_str(out_path.to_str().unwrap()));
+        }
+    }
+    exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read_to_string;
+    use structopt::StructOpt;
+    use super::*;
+
+    #[test]
+    fn declarations_are_written_with_their_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("all.sv");
+        write(&path, "// package of the types
+package pkg;
+endpackage
+module a; endmodule // after a
+
+/* the top
+   module */
+module top;
+  a u_a ();
+endmodule
+interface \\bus.if ; endinterface
+").unwrap();
+        let output = dir.path().join("out");
+        let opt = SplitOpt::from_iter(["split", "-o", output.to_str().unwrap(), path.to_str().unwrap()]);
+        assert_eq!(run(&opt), 0);
+        let text = |x: &str| read_to_string(output.join(x)).unwrap();
+        assert_eq!(text("pkg.sv"), "// package of the types\npackage pkg;\nendpackage\n");
+        assert_eq!(text("a.sv"), "module a; endmodule\n");
+        assert_eq!(text("top.sv"), "/* the top\n   module */\nmodule top;\n  a u_a ();\nendmodule\n");
+        assert_eq!(text("_bus_if.sv"), "interface \\bus.if ; endinterface\n");
+    }
+
+    #[test]
+    fn duplicate_declarations_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.sv");
+        let b = dir.path().join("b.sv");
+        write(&a, "module m; endmodule\n").unwrap();
+        write(&b, "module m; wire w; endmodule\n").unwrap();
+        let output = dir.path().join("out");
+        let opt = SplitOpt::from_iter(["split", "-o", output.to_str().unwrap(), a.to_str().unwrap(), b.to_str().unwrap()]);
+        assert_eq!(run(&opt), 1);
+        assert_eq!(read_to_string(output.join("m.sv")).unwrap(), "module m; endmodule\n");
+    }
+}
@@ -0,0 +1,211 @@
+use std::fs::{read, File};
+use std::io::Write;
+use std::path::PathBuf;
+use tracing::error;
+use crate::Opt;
+
+// first words of the comments which are tool directives
+const PRAGMA_WORDS: &[&str] = &[
+    "synopsys", "synthesis", "pragma", "cadence", "ambit", "verilator", "coverage",
+    "spyglass",
+];
+
+fn is_pragma_comment(
+    comment: &str
+) -> bool {
+    let body = comment.trim_start_matches(['/', '*']).trim_start();
+    let word: String = body.chars().take_while(|c| c.is_ascii_alphanumeric() || *c == '_').collect();
+    PRAGMA_WORDS.contains(&word.to_ascii_lowercase().as_str())
+}
+
+// output text, where a line which becomes empty by the removal is dropped or kept
+// as an empty line
+struct Output {
+    text: String,
+    line_start: usize,
+    touched: bool,
+    keep_lines: bool
+}
+
+impl Output {
+    fn end_line(&mut self) {
+        if self.touched {
+            let line = self.text[self.line_start..].trim_end().len();
+            if line > 0 && !self.text[self.line_start..].trim().is_empty() {
+                self.text.truncate(self.line_start + line);
+                self.text.push('\n');
+            } else {
+                self.text.truncate(self.line_start);
+                if self.keep_lines {
+                    self.text.push('\n');
+                }
+            }
+        } else {
+            self.text.push('\n');
+        }
+        self.line_start = self.text.len();
+        self.touched = false;
+    }
+
+    // remove a construct, keeping its line breaks if desired
+    fn remove(&mut self, removed: &str) {
+        self.touched = true;
+        if self.keep_lines {
+            for _ in removed.matches('\n') {
+                self.end_line();
+                self.touched = true;
+            }
+        }
+    }
+}
+
+// text with the comments, pragmas, and/or attributes removed
+pub fn strip(
+    text: &str,
+    kinds: &[String],
+    keep_lines: bool
+) -> String {
+    let comments = kinds.iter().any(|x| x == "comments");
+    let pragmas = kinds.iter().any(|x| x == "pragmas");
+    let attributes = kinds.iter().any(|x| x == "attributes");
+    let b = text.as_bytes();
+    let mut out = Output { text: String::new(), line_start: 0, touched: false, keep_lines };
+    let mut i = 0;
+    while i < b.len() {
+        let begin = i;
+        let remove = if b[i..].starts_with(b"//") {
+            i = b[i..].iter().position(|&c| c == b'\n').map_or(b.len(), |x| i + x);
+            comments || (pragmas && is_pragma_comment(&text[begin..i]))
+        } else if b[i..].starts_with(b"/*") {
+            i = b[i + 2..].windows(2).position(|x| x == b"*/").map_or(b.len(), |x| i + x + 4);
+            comments || (pragmas && is_pragma_comment(&text[begin..i]))
+        } else if b[i..].starts_with(b"(*") && b.get(i + 2) != Some(&b')') {
+            i = b[i + 2..].windows(2).position(|x| x == b"*)").map_or(b.len(), |x| i + x + 4);
+            attributes
+        } else if b[i..].starts_with(b"`pragma") && !b.get(i + 7).is_some_and(|x| x.is_ascii_alphanumeric() || *x == b'_') {
+            i = b[i..].iter().position(|&c| c == b'\n').map_or(b.len(), |x| i + x);
+            pragmas
+        } else if b[i] == b'"' {
+            i += 1;
+            while i < b.len() && b[i] != b'"' && b[i] != b'\n' {
+                i += if b[i] == b'\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(b.len());
+            false
+        } else if b[i] == b'\n' {
+            i += 1;
+            out.end_line();
+            continue;
+        } else {
+            // a whole character to keep the text valid
+            i += text[i..].chars().next().map_or(1, |x| x.len_utf8());
+            false
+        };
+        if remove {
+            out.remove(&text[begin..i]);
+        } else {
+            out.text.push_str(&text[begin..i]);
+            // a multi-line comment or a string is kept as is
+            if let Some(x) = text[begin..i].rfind('\n') {
+                out.line_start = out.text.len() - (i - begin - x - 1);
+                out.touched = false;
+            }
+        }
+    }
+    out.text
+}
+
+
+// write the files stripped as by --strip into the output directory if it exists, or
+// concatenated into the output file or the standard output; false if any file is not
+// read or written
+pub fn write_files(
+    files: &[(&PathBuf, usize)],
+    opt: &Opt
+) -> bool {
+    let mut ok = true;
+    let dir = opt.output.as_ref().filter(|x| x.is_dir());
+    let mut text = String::new();
+    for (path, _) in files {
+        let Ok(org) = read(path) else {
+            error!("failed to read: {:?}", path);
+            ok = false;
+            continue;
+        };
+        let stripped = strip(&String::from_utf8_lossy(&org), &opt.strip, opt.keep_lines);
+        match dir.zip(path.file_name()) {
+            Some((dir, name)) => {
+                if let Err(x) = File::create(dir.join(name)).and_then(|mut x| x.write_all(stripped.as_bytes())) {
+                    error!("failed to write: {:?} ({})", dir.join(name), x);
+                    ok = false;
+                }
+            }
+            None => text.push_str(&stripped)
+        }
+    }
+    match &opt.output {
+        Some(_) if dir.is_some() => (),
+        Some(path) => {
+            if let Err(x) = File::create(path).and_then(|mut x| x.write_all(text.as_bytes())) {
+                error!("failed to write: {:?} ({})", path, x);
+                ok = false;
+            }
+        }
+        None => out!("{}", text)
+    }
+    ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "// header
+module m; // trailing
+  /* block
+     comment */ wire a;
+  (* keep *) wire b;
+  // synopsys translate_off
+  initial $display(\"// not /* a comment\");
+  `pragma protect begin
+endmodule
+";
+
+    fn kinds(x: &[&str]) -> Vec<String> {
+        x.iter().map(|x| String::from(*x)).collect()
+    }
+
+    #[test]
+    fn comments_are_removed_and_strings_kept() {
+        assert_eq!(strip(TEXT, &kinds(&["comments"]), false), "module m;
+   wire a;
+  (* keep *) wire b;
+  initial $display(\"// not /* a comment\");
+  `pragma protect begin
+endmodule
+");
+        // the line numbers are kept
+        assert_eq!(strip(TEXT, &kinds(&["comments"]), true), "
+module m;
+
+ wire a;
+  (* keep *) wire b;
+
+  initial $display(\"// not /* a comment\");
+  `pragma protect begin
+endmodule
+");
+    }
+
+    #[test]
+    fn pragmas_and_attributes_are_removed() {
+        assert_eq!(strip(TEXT, &kinds(&["pragmas", "attributes"]), false), "// header
+module m; // trailing
+  /* block
+     comment */ wire a;
+   wire b;
+  initial $display(\"// not /* a comment\");
+endmodule
+");
+    }
+}
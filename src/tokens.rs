@@ -0,0 +1,74 @@
+// The token streams of the files after preprocessing (--tokens), with the file, line,
+// and column each token comes from.
+
+use std::io::Write;
+use std::path::PathBuf;
+use sv_parser::preprocess;
+use tempfile::NamedTempFile;
+use tracing::error;
+use crate::{escape_str, logging, read_source, scan, Library, Opt, SourceMap};
+
+// the files with their tokens; false if any file is not read or preprocessed
+pub fn print_files(
+    files: &[(&PathBuf, usize)],
+    libraries: &mut [Library],
+    opt: &Opt
+) -> bool {
+    let mut ok = true;
+    outln!("files:");
+    for (path, lib) in files {
+        if !print_tokens(path, &mut libraries[*lib], opt) {
+            ok = false;
+        }
+    }
+    ok
+}
+
+// print the tokens of a file after preprocessing
+fn print_tokens(
+    path: &PathBuf,
+    library: &mut Library,
+    opt: &Opt
+) -> bool {
+    let text = match read_source(path, &opt.sanitize) {
+        Ok((text, _)) => text,
+        Err(x) => {
+            error!("failed to read: {:?} ({})", path, x);
+            return false;
+        }
+    };
+    let Ok(mut tmpfile) = NamedTempFile::new() else { return false; };
+    let _ = tmpfile.write_all(text.as_bytes());
+    let mut sources = SourceMap::new(tmpfile.path());
+    sources.add(path, 0, text);
+    let (pp_text, new_defines) = match preprocess(tmpfile.path(), &library.defines, &library.includes, true, opt.ignore_include) {
+        Ok(x) => x,
+        Err(x) => {
+            error!("preprocess failed: {:?} ({}){}", path, x, logging::causes(&x));
+            return false;
+        }
+    };
+    if !opt.separate {
+        library.defines = new_defines;
+    }
+    outln!("  - file_name: {}", escape_str(path.to_str().unwrap()));
+    let tokens = scan::lex(pp_text.text()).unwrap_or_default();
+    if tokens.is_empty() {
+        outln!("    tokens: []");
+    } else {
+        outln!("    tokens:");
+    }
+    for (kind, text, offset) in tokens {
+        outln!("      - kind: {}", escape_str(kind));
+        outln!("        text: {}", escape_str(&text));
+        let Some((origin_path, origin_pos)) = pp_text.origin(offset) else { continue; };
+        let (origin_path, origin_pos) = sources.origin(origin_path, origin_pos);
+        if origin_path != *path {
+            outln!("        file_name: {}", escape_str(origin_path.to_str().unwrap()));
+        }
+        let (line, column) = sources.line_col(&origin_path, origin_pos);
+        outln!("        line: {}", line);
+        outln!("        column: {}", column);
+    }
+    true
+}
@@ -0,0 +1,122 @@
+// Declarations in the compilation-unit scope ($unit), i.e. outside any module,
+// interface, program, or package. They are visible to the files parsed after them
+// in the same compilation unit, so that they affect how those files elaborate.
+// Only the parser finds them; the scanner leaves them empty.
+
+use std::path::PathBuf;
+use sv_parser::{unwrap_node, Locate, RefNode, SyntaxTree};
+use sv_parser_syntaxtree::*;
+use crate::{escape_str, get_identifier, get_token_str, SourceMap};
+
+pub struct UnitDecl {
+    pub name: String,
+    pub kind: String,
+    pub file: PathBuf,
+    pub line: usize
+}
+
+// compilation unit and the declarations in its scope
+pub struct UnitScope {
+    pub files: Vec<PathBuf>,
+    pub decls: Vec<UnitDecl>
+}
+
+// kind and identifiers of an item in the compilation-unit scope; an import is
+// named by the imported items
+fn item_names(
+    item: &PackageItem
+) -> Option<(&'static str, Vec<RefNode<'_>>)> {
+    let PackageItem::PackageOrGenerateItemDeclaration(x) = item else { return None; };
+    let ret = match x.as_ref() {
+        PackageOrGenerateItemDeclaration::NetDeclaration(x) => {
+            let names = RefNode::from(x.as_ref()).into_iter()
+                .filter_map(|x| if let RefNode::NetDeclAssignment(x) = x { Some(RefNode::from(&x.nodes.0)) } else { None })
+                .collect();
+            ("net", names)
+        }
+        PackageOrGenerateItemDeclaration::DataDeclaration(x) => match x.as_ref() {
+            DataDeclaration::Variable(x) => {
+                let names = RefNode::from(&x.nodes.4).into_iter()
+                    .filter_map(|x| match x {
+                        RefNode::VariableDeclAssignmentVariable(x) => Some(RefNode::from(&x.nodes.0)),
+                        RefNode::VariableDeclAssignmentDynamicArray(x) => Some(RefNode::from(&x.nodes.0)),
+                        RefNode::VariableDeclAssignmentClass(x) => Some(RefNode::from(&x.nodes.0)),
+                        _ => None
+                    })
+                    .collect();
+                ("variable", names)
+            }
+            DataDeclaration::TypeDeclaration(x) => match x.as_ref() {
+                TypeDeclaration::DataType(x) => ("typedef", vec![RefNode::from(&x.nodes.2)]),
+                TypeDeclaration::Interface(x) => ("typedef", vec![RefNode::from(&x.nodes.5)]),
+                TypeDeclaration::Reserved(x) => ("typedef", vec![RefNode::from(&x.nodes.2)])
+            },
+            DataDeclaration::PackageImportDeclaration(x) => ("import", x.nodes.1.contents().into_iter().map(RefNode::from).collect()),
+            DataDeclaration::NetTypeDeclaration(x) => ("nettype", unwrap_node!(x.as_ref(), NetTypeIdentifier).into_iter().collect())
+        },
+        PackageOrGenerateItemDeclaration::TaskDeclaration(x) => ("task", unwrap_node!(x.as_ref(), TaskIdentifier).into_iter().collect()),
+        PackageOrGenerateItemDeclaration::FunctionDeclaration(x) => ("function", unwrap_node!(x.as_ref(), FunctionIdentifier).into_iter().collect()),
+        PackageOrGenerateItemDeclaration::DpiImportExport(x) => ("dpi", unwrap_node!(x.as_ref(), FunctionIdentifier, TaskIdentifier).into_iter().collect()),
+        PackageOrGenerateItemDeclaration::ClassDeclaration(x) => ("class", vec![RefNode::from(&x.nodes.3)]),
+        PackageOrGenerateItemDeclaration::InterfaceClassDeclaration(x) => ("class", vec![RefNode::from(&x.nodes.2)]),
+        PackageOrGenerateItemDeclaration::CheckerDeclaration(x) => ("checker", unwrap_node!(x.as_ref(), CheckerIdentifier).into_iter().collect()),
+        PackageOrGenerateItemDeclaration::CovergroupDeclaration(x) => ("covergroup", unwrap_node!(x.as_ref(), CovergroupIdentifier).into_iter().collect()),
+        PackageOrGenerateItemDeclaration::LocalParameterDeclaration(x) => ("localparam", param_names(RefNode::from(&x.0))),
+        PackageOrGenerateItemDeclaration::ParameterDeclaration(x) => ("parameter", param_names(RefNode::from(&x.0))),
+        _ => return None
+    };
+    Some(ret)
+}
+
+fn param_names(
+    node: RefNode
+) -> Vec<RefNode> {
+    node.into_iter()
+        .filter_map(|x| if let RefNode::ParamAssignment(x) = x { Some(RefNode::from(&x.nodes.0)) } else { None })
+        .collect()
+}
+
+// declarations in the compilation-unit scope, in the order of the source
+pub fn analyze(
+    syntax_tree: &SyntaxTree,
+    sources: &mut SourceMap
+) -> Vec<UnitDecl> {
+    let mut ret = vec![];
+    for node in syntax_tree {
+        // a package item is a description only outside the other declarations
+        let RefNode::DescriptionPackageItem(x) = node else { continue; };
+        let Some((kind, names)) = item_names(&x.nodes.1) else { continue; };
+        for node in names {
+            let (name, locate) = if kind == "import" {
+                let first = node.clone().into_iter().find_map(|x| if let RefNode::Locate(x) = x { Some(*x) } else { None });
+                (get_token_str(syntax_tree, node).replace(' ', ""), first)
+            } else {
+                let locate: Option<Locate> = get_identifier(node);
+                (locate.and_then(|x| syntax_tree.get_str(&x)).map(String::from).unwrap_or_default(), locate)
+            };
+            let Some(locate) = locate else { continue; };
+            let Some((file, line)) = sources.resolve(syntax_tree, &locate) else { continue; };
+            ret.push(UnitDecl { name, kind: String::from(kind), file, line });
+        }
+    }
+    ret
+}
+
+pub fn print_unit_decls(
+    scopes: &[UnitScope]
+) {
+    for scope in scopes.iter().filter(|x| !x.decls.is_empty()) {
+        outln!("  - unit_name: \"$unit\"");
+        outln!("    files:");
+        for path in &scope.files {
+            outln!("      - {}", escape_str(path.to_str().unwrap()));
+        }
+        outln!("    decls:");
+        for decl in &scope.decls {
+            outln!("      - decl_name: {}", escape_str(&decl.name));
+            outln!("        kind: {}", escape_str(&decl.kind));
+            outln!("        file_name: {}", escape_str(decl.file.to_str().unwrap()));
+            outln!("        line: {}", decl.line);
+        }
+    }
+}